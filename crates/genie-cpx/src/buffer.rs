@@ -0,0 +1,200 @@
+use crate::read::{read_campaign_header, read_scenario_meta, ReadCampaignError};
+use crate::{CPXVersion, CampaignHeader, ScenarioMeta};
+use genie_scx::Scenario;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+type Result<T> = std::result::Result<T, ReadCampaignError>;
+
+/// A campaign file that is fully available in memory, like a byte slice or a memory-mapped
+/// file.
+///
+/// Unlike `Campaign`, this does not need mutable access to read scenarios, and hands out borrowed
+/// slices instead of copying scenario data. If the buffer type is `Send` and `Sync`, so is the
+/// campaign, so it can be shared between threads.
+#[derive(Debug, Clone)]
+pub struct CampaignBuffer<B>
+where
+    B: AsRef<[u8]>,
+{
+    data: B,
+    header: CampaignHeader,
+    entries: Vec<ScenarioMeta>,
+}
+
+/// A campaign file that is mapped into memory.
+pub type MappedCampaign = CampaignBuffer<Mmap>;
+
+impl MappedCampaign {
+    /// Map a campaign file into memory and read its metadata.
+    ///
+    /// The file must not be modified while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        // Safety: Modifying the file while it is mapped is undefined behaviour, which is
+        // documented above.
+        let data = unsafe { Mmap::map(&file)? };
+        Self::from(data)
+    }
+}
+
+impl<B> CampaignBuffer<B>
+where
+    B: AsRef<[u8]>,
+{
+    /// Create a campaign instance from a buffer containing a campaign file.
+    ///
+    /// This immediately reads the campaign header and scenario metadata.
+    pub fn from(data: B) -> Result<Self> {
+        let mut input = Cursor::new(data.as_ref());
+        let header = read_campaign_header(&mut input)?;
+        let mut entries = vec![];
+        for _ in 0..header.num_scenarios {
+            entries.push(read_scenario_meta(&mut input)?);
+        }
+
+        Ok(Self {
+            data,
+            header,
+            entries,
+        })
+    }
+
+    /// Consume this instance and get the buffer.
+    pub fn into_inner(self) -> B {
+        self.data
+    }
+
+    /// Get the campaign file version.
+    pub fn version(&self) -> CPXVersion {
+        self.header.version
+    }
+
+    pub fn name(&self) -> &str {
+        &self.header.name
+    }
+
+    /// Iterate over the scenario metadata for this campaign.
+    pub fn entries(&self) -> impl Iterator<Item = &ScenarioMeta> {
+        self.entries.iter()
+    }
+
+    /// Get the number of scenarios in this campaign.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get_name(&self, id: usize) -> Option<&str> {
+        self.entries.get(id).map(|entry| entry.name.as_ref())
+    }
+
+    pub fn get_filename(&self, id: usize) -> Option<&str> {
+        self.entries.get(id).map(|entry| entry.filename.as_ref())
+    }
+
+    fn get_id(&self, filename: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.filename == filename)
+    }
+
+    /// Get a scenario by its file name.
+    pub fn by_name(&self, filename: &str) -> Result<Scenario> {
+        let mut buf = Cursor::new(self.by_name_raw(filename)?);
+        Scenario::from(&mut buf).map_err(ReadCampaignError::ParseSCXError)
+    }
+
+    /// Get a scenario by its campaign index.
+    pub fn by_index(&self, index: usize) -> Result<Scenario> {
+        let mut buf = Cursor::new(self.by_index_raw(index)?);
+        Scenario::from(&mut buf).map_err(ReadCampaignError::ParseSCXError)
+    }
+
+    /// Get a scenario file buffer by its file name.
+    pub fn by_name_raw(&self, filename: &str) -> Result<&[u8]> {
+        self.get_id(filename)
+            .ok_or(ReadCampaignError::NotFoundError)
+            .and_then(|index| self.by_index_raw(index))
+    }
+
+    /// Get a scenario file buffer by its campaign index.
+    ///
+    /// Returns an error if the scenario's data runs past the end of the campaign file.
+    pub fn by_index_raw(&self, index: usize) -> Result<&[u8]> {
+        let entry = self
+            .entries
+            .get(index)
+            .ok_or(ReadCampaignError::NotFoundError)?;
+        entry
+            .offset
+            .checked_add(entry.size)
+            .and_then(|end| self.data.as_ref().get(entry.offset..end))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Campaign;
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+
+    const CAMPAIGN: &str = "test/campaigns/Rise of Egypt Learning Campaign.cpn";
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn matches_stream_reader() {
+        assert_send_sync::<CampaignBuffer<&[u8]>>();
+        assert_send_sync::<MappedCampaign>();
+
+        let bytes = fs::read(CAMPAIGN).unwrap();
+        let buffer = CampaignBuffer::from(&bytes[..]).unwrap();
+        let mut campaign = Campaign::from(fs::File::open(CAMPAIGN).unwrap()).unwrap();
+        assert_eq!(buffer.name(), campaign.name());
+        assert_eq!(buffer.len(), campaign.len());
+        for index in 0..buffer.len() {
+            assert_eq!(buffer.get_filename(index), campaign.get_filename(index));
+            assert_eq!(
+                buffer.by_index_raw(index).unwrap(),
+                &campaign.by_index_raw(index).unwrap()[..]
+            );
+        }
+        assert!(buffer.by_name_raw("TRADE.scn").is_ok());
+        assert!(buffer.by_index_raw(12).is_err());
+    }
+
+    #[test]
+    fn share_mapped_campaign_between_threads() {
+        let campaign = Arc::new(MappedCampaign::open(CAMPAIGN).unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let campaign = Arc::clone(&campaign);
+                thread::spawn(move || {
+                    (thread..campaign.len())
+                        .step_by(4)
+                        .map(|index| campaign.by_index_raw(index).unwrap().len())
+                        .sum::<usize>()
+                })
+            })
+            .collect();
+        let total: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(total, campaign.entries().map(|e| e.size).sum());
+    }
+
+    #[test]
+    fn truncated_campaign() {
+        let bytes = fs::read(CAMPAIGN).unwrap();
+        let buffer = CampaignBuffer::from(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(buffer.by_index_raw(10).is_ok());
+        assert!(buffer.by_index_raw(11).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::edit::CampaignEditor;
+use crate::read::{Campaign, ReadCampaignError};
+use genie_scx::convert::{ConvertError, Converter};
+use genie_scx::{self as scx, ConversionWarning, Scenario, VersionBundle};
+use std::io::{Cursor, Read, Seek};
+
+/// Reason why a scenario in a campaign could not be converted.
+#[derive(Debug)]
+pub enum ConvertScenarioError {
+    /// The scenario file could not be read from the campaign, or could not be parsed.
+    ReadError(ReadCampaignError),
+    /// The converter failed.
+    ConvertError(ConvertError),
+    /// The converted scenario could not be written in the target version.
+    WriteError(scx::Error),
+}
+
+impl std::fmt::Display for ConvertScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConvertScenarioError::ReadError(err) => write!(f, "could not read scenario: {}", err),
+            ConvertScenarioError::ConvertError(err) => {
+                write!(f, "could not convert scenario: {}", err)
+            }
+            ConvertScenarioError::WriteError(err) => {
+                write!(f, "could not write scenario: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConvertScenarioError {}
+
+/// The outcome of converting a single scenario in a campaign.
+#[derive(Debug)]
+pub struct ConvertReport {
+    /// The campaign index of the scenario.
+    pub index: usize,
+    /// The file name of the scenario.
+    pub filename: String,
+    /// Whether the conversion succeeded. If it failed, the original scenario data was kept.
+    pub result: Result<(), ConvertScenarioError>,
+    /// Data that was lost when writing the converted scenario.
+    pub warnings: Vec<ConversionWarning>,
+}
+
+impl ConvertReport {
+    /// Returns whether the scenario was converted.
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+fn convert_scenario(
+    bytes: &[u8],
+    converter: &impl Converter,
+    version: &VersionBundle,
+) -> Result<(Vec<u8>, Vec<ConversionWarning>), ConvertScenarioError> {
+    let mut scenario = Scenario::from(&mut Cursor::new(bytes))
+        .map_err(|err| ConvertScenarioError::ReadError(ReadCampaignError::ParseSCXError(err)))?;
+    converter
+        .convert(&mut scenario)
+        .map_err(ConvertScenarioError::ConvertError)?;
+    let mut output = vec![];
+    let warnings = scenario
+        .write_to_version(&mut output, version)
+        .map_err(ConvertScenarioError::WriteError)?;
+    Ok((output, warnings))
+}
+
+impl<R> Campaign<R>
+where
+    R: Read + Seek,
+{
+    /// Convert every scenario in this campaign, and write them in the given scenario version.
+    ///
+    /// Returns a new campaign with the converted scenarios, in the same order and with the same
+    /// names, and a report for each scenario. Scenarios that fail to convert keep their original
+    /// data, so nothing is lost. Scenarios that can not be read from this campaign at all are
+    /// kept as empty placeholders, so the converted campaign still lines up with the reports.
+    pub fn convert_all(
+        &mut self,
+        converter: &impl Converter,
+        version: &VersionBundle,
+    ) -> (CampaignEditor, Vec<ConvertReport>) {
+        let mut converted = CampaignEditor::new(self.name());
+
+        let mut reports = vec![];
+        for index in 0..self.len() {
+            let name = self.get_name(index).unwrap_or_default().to_string();
+            let filename = self.get_filename(index).unwrap_or_default().to_string();
+            let original = match self.by_index_raw(index) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    // There is no data to keep, but the entry is kept so later indices stay put.
+                    converted.push_raw(&name, &filename, vec![]);
+                    reports.push(ConvertReport {
+                        index,
+                        filename,
+                        result: Err(ConvertScenarioError::ReadError(err)),
+                        warnings: vec![],
+                    });
+                    continue;
+                }
+            };
+
+            let (bytes, result, warnings) = match convert_scenario(&original, converter, version) {
+                Ok((bytes, warnings)) => (bytes, Ok(()), warnings),
+                Err(err) => (original, Err(err), vec![]),
+            };
+            converted.push_raw(&name, &filename, bytes);
+            reports.push(ConvertReport {
+                index,
+                filename,
+                result,
+                warnings,
+            });
+        }
+
+        (converted, reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genie_scx::convert::NoConversion;
+    use std::fs::File;
+
+    #[test]
+    fn convert_aoe1_campaign() {
+        let f = File::open("test/campaigns/Rise of Egypt Learning Campaign.cpn").unwrap();
+        let mut campaign = Campaign::from(f).unwrap();
+        let (converted, reports) = campaign.convert_all(&NoConversion, &VersionBundle::aoc());
+
+        assert_eq!(reports.len(), campaign.len());
+        assert_eq!(converted.len(), campaign.len());
+        for (index, (report, entry)) in reports.iter().zip(converted.entries()).enumerate() {
+            assert_eq!(report.index, index);
+            assert_eq!(Some(entry.filename()), campaign.get_filename(index));
+            assert_eq!(Some(entry.name()), campaign.get_name(index));
+            if !report.is_ok() {
+                assert_eq!(entry.bytes(), &campaign.by_index_raw(index).unwrap()[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn failed_scenarios_keep_their_data() {
+        let mut editor = CampaignEditor::new("Broken");
+        editor.push_raw("Broken", "broken.scx", vec![1, 2, 3, 4]);
+        let mut bytes = vec![];
+        editor.write_to(&mut bytes).unwrap();
+
+        let mut campaign = Campaign::from(Cursor::new(bytes)).unwrap();
+        let (converted, reports) = campaign.convert_all(&NoConversion, &VersionBundle::aoc());
+        assert!(matches!(
+            reports[0].result,
+            Err(ConvertScenarioError::ReadError(_))
+        ));
+        assert_eq!(converted.get(0).unwrap().bytes(), &[1, 2, 3, 4]);
+        assert_eq!(converted.name(), "Broken");
+    }
+
+    /// A reader that fails when reading past a given position.
+    struct FailAfter {
+        inner: Cursor<Vec<u8>>,
+        limit: u64,
+    }
+
+    impl Read for FailAfter {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.inner.position() >= self.limit {
+                return Err(std::io::Error::other("bad sector"));
+            }
+            let max = (self.limit - self.inner.position()) as usize;
+            let len = buf.len().min(max);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    impl Seek for FailAfter {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn unreadable_scenarios_keep_their_place() {
+        let mut editor = CampaignEditor::new("Damaged");
+        editor.push_raw("First", "first.scx", vec![1, 2, 3, 4]);
+        editor.push_raw("Second", "second.scx", vec![5, 6, 7, 8]);
+        editor.push_raw("Third", "third.scx", vec![9, 10, 11, 12]);
+        let limit = editor.metas()[1].offset as u64;
+        let mut bytes = vec![];
+        editor.write_to(&mut bytes).unwrap();
+
+        let mut campaign = Campaign::from(FailAfter {
+            inner: Cursor::new(bytes),
+            limit,
+        })
+        .unwrap();
+        let (converted, reports) = campaign.convert_all(&NoConversion, &VersionBundle::aoc());
+        assert_eq!(converted.len(), 3);
+        assert_eq!(reports.len(), 3);
+        for (index, (report, entry)) in reports.iter().zip(converted.entries()).enumerate() {
+            assert_eq!(report.index, index);
+            assert_eq!(report.filename, entry.filename());
+        }
+        assert_eq!(converted.get(0).unwrap().bytes(), &[1, 2, 3, 4]);
+        assert_eq!(converted.get(2).unwrap().filename(), "third.scx");
+        // The second and third scenarios can not be read.
+        for (report, entry) in reports.iter().zip(converted.entries()).skip(1) {
+            assert!(entry.bytes().is_empty());
+            match &report.result {
+                Err(ConvertScenarioError::ReadError(ReadCampaignError::IoError(_))) => (),
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::read::{Campaign, ReadCampaignError};
+use crate::write::{compute_metas, write_campaign, CampaignEntry, WriteCampaignError};
+use crate::{CampaignHeader, ScenarioMeta};
+use genie_scx::{Result as SCXResult, Scenario};
+use std::io::{Read, Seek, Write};
+
+/// An editable campaign, with all scenario files stored in memory.
+///
+/// Scenario sizes and offsets are computed when writing the campaign, so entries can be freely
+/// inserted, removed, and reordered.
+#[derive(Debug, Clone)]
+pub struct CampaignEditor {
+    header: CampaignHeader,
+    entries: Vec<CampaignEntry>,
+}
+
+fn scenario_bytes(scx: &Scenario) -> SCXResult<Vec<u8>> {
+    let mut bytes = vec![];
+    scx.write_to(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl CampaignEditor {
+    /// Create an empty campaign.
+    pub fn new(name: &str) -> Self {
+        Self {
+            header: CampaignHeader::new(name),
+            entries: vec![],
+        }
+    }
+
+    /// Load all scenario files from a campaign.
+    pub fn from_campaign<R: Read + Seek>(
+        campaign: &mut Campaign<R>,
+    ) -> Result<Self, ReadCampaignError> {
+        let mut editor = Self::new(campaign.name());
+        for index in 0..campaign.len() {
+            let bytes = campaign.by_index_raw(index)?;
+            let (name, filename) = match (campaign.get_name(index), campaign.get_filename(index)) {
+                (Some(name), Some(filename)) => (name, filename),
+                _ => return Err(ReadCampaignError::NotFoundError),
+            };
+            editor.push_raw(name, filename, bytes);
+        }
+        Ok(editor)
+    }
+
+    /// Get the name of the campaign.
+    pub fn name(&self) -> &str {
+        &self.header.name
+    }
+
+    /// Set the name of the campaign.
+    ///
+    /// Classic campaigns can store names up to `MAX_CAMPAIGN_NAME_LEN` bytes long; `write_to`
+    /// fails for longer names.
+    pub fn set_name(&mut self, name: &str) {
+        self.header.name = name.to_owned();
+    }
+
+    /// Get the number of scenarios in this campaign.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the scenarios in this campaign.
+    pub fn entries(&self) -> impl Iterator<Item = &CampaignEntry> {
+        self.entries.iter()
+    }
+
+    /// Get a scenario by its campaign index.
+    pub fn get(&self, index: usize) -> Option<&CampaignEntry> {
+        self.entries.get(index)
+    }
+
+    /// Get a mutable reference to a scenario by its campaign index, to rename it or replace its
+    /// data.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut CampaignEntry> {
+        self.entries.get_mut(index)
+    }
+
+    /// Find the campaign index of a scenario by its file name.
+    pub fn position(&self, filename: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.filename() == filename)
+    }
+
+    /// Add a scenario file to the end of the campaign.
+    pub fn push_raw(&mut self, name: &str, filename: &str, scx: Vec<u8>) {
+        self.entries.push(CampaignEntry::new(name, filename, scx));
+    }
+
+    /// Add a scenario to the end of the campaign.
+    pub fn push(&mut self, name: &str, scx: &Scenario) -> SCXResult<()> {
+        let bytes = scenario_bytes(scx)?;
+        self.push_raw(name, scx.filename(), bytes);
+        Ok(())
+    }
+
+    /// Insert a scenario file at a campaign index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert_raw(&mut self, index: usize, name: &str, filename: &str, scx: Vec<u8>) {
+        self.entries
+            .insert(index, CampaignEntry::new(name, filename, scx));
+    }
+
+    /// Insert a scenario at a campaign index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, name: &str, scx: &Scenario) -> SCXResult<()> {
+        let bytes = scenario_bytes(scx)?;
+        self.insert_raw(index, name, scx.filename(), bytes);
+        Ok(())
+    }
+
+    /// Remove a scenario from the campaign, returning it.
+    pub fn remove(&mut self, index: usize) -> Option<CampaignEntry> {
+        if index < self.entries.len() {
+            Some(self.entries.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Replace the data of a scenario, keeping its name and file name. Returns the old data, or
+    /// None if there is no scenario at this index.
+    pub fn replace_raw(&mut self, index: usize, scx: Vec<u8>) -> Option<Vec<u8>> {
+        self.entries
+            .get_mut(index)
+            .map(|entry| entry.set_bytes(scx))
+    }
+
+    /// Replace a scenario, keeping its name and file name. Returns the old data, or None if there
+    /// is no scenario at this index.
+    pub fn replace(&mut self, index: usize, scx: &Scenario) -> SCXResult<Option<Vec<u8>>> {
+        let bytes = scenario_bytes(scx)?;
+        Ok(self.replace_raw(index, bytes))
+    }
+
+    /// Move a scenario to a different campaign index. Scenarios in between shift to fill the gap.
+    /// Returns false if `from` or `to` is not a scenario index.
+    pub fn move_entry(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.entries.len() || to >= self.entries.len() {
+            return false;
+        }
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        true
+    }
+
+    /// Set the name of a scenario. Returns false if there is no scenario at this index.
+    ///
+    /// Classic campaigns can store names up to `MAX_SCENARIO_NAME_LEN` bytes long; `write_to`
+    /// fails for longer names.
+    pub fn rename(&mut self, index: usize, name: &str) -> bool {
+        match self.entries.get_mut(index) {
+            Some(entry) => {
+                entry.set_name(name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the file name of a scenario. Returns false if there is no scenario at this index.
+    ///
+    /// Classic campaigns can store file names up to `MAX_SCENARIO_NAME_LEN` bytes long;
+    /// `write_to` fails for longer names.
+    pub fn set_filename(&mut self, index: usize, filename: &str) -> bool {
+        match self.entries.get_mut(index) {
+            Some(entry) => {
+                entry.set_filename(filename);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the metadata that will be written for each scenario, with sizes and offsets computed
+    /// for the current order.
+    pub fn metas(&self) -> Vec<ScenarioMeta> {
+        compute_metas(&self.entries)
+    }
+
+    /// Write the campaign file to an output stream.
+    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<(), WriteCampaignError> {
+        write_campaign(&self.header, &self.entries, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_CAMPAIGN_NAME_LEN, MAX_SCENARIO_NAME_LEN};
+    use std::fs::File;
+    use std::io::Cursor;
+
+    fn editor() -> CampaignEditor {
+        let f = File::open("test/campaigns/Rise of Egypt Learning Campaign.cpn").unwrap();
+        let mut campaign = Campaign::from(f).unwrap();
+        CampaignEditor::from_campaign(&mut campaign).unwrap()
+    }
+
+    fn filenames<R: Read + Seek>(campaign: &Campaign<R>) -> Vec<String> {
+        campaign.entries().map(|e| e.filename.clone()).collect()
+    }
+
+    #[test]
+    fn edit_and_rewrite() {
+        let mut editor = editor();
+        let hunting = editor.get(0).unwrap().bytes().to_vec();
+        let foraging = editor.get(1).unwrap().bytes().to_vec();
+
+        editor.set_name("Rise of Egypt, Remixed");
+        assert!(editor.move_entry(0, 11));
+        assert!(!editor.move_entry(0, 12));
+        assert!(!editor.move_entry(12, 0));
+        editor.remove(0).unwrap();
+        editor.rename(0, "Discoveries!");
+        editor.set_filename(0, "DISCOVERIES.scn");
+        editor.insert_raw(1, "Foraging Again", "FORAGING2.scn", foraging.clone());
+        assert_eq!(editor.len(), 12);
+
+        let metas = editor.metas();
+        for pair in metas.windows(2) {
+            assert_eq!(pair[0].offset + pair[0].size, pair[1].offset);
+        }
+
+        let mut bytes = vec![];
+        editor.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), metas[11].offset + metas[11].size);
+
+        let mut campaign = Campaign::from(Cursor::new(bytes)).unwrap();
+        assert_eq!(campaign.name(), "Rise of Egypt, Remixed");
+        let filenames = filenames(&campaign);
+        assert_eq!(filenames[0], "DISCOVERIES.scn");
+        assert_eq!(filenames[1], "FORAGING2.scn");
+        assert_eq!(filenames[11], "HUNTING.scn");
+        assert_eq!(campaign.get_name(0), Some("Discoveries!"));
+        assert_eq!(campaign.by_index_raw(11).unwrap(), hunting);
+        assert_eq!(campaign.by_index_raw(1).unwrap(), foraging);
+    }
+
+    #[test]
+    fn rewrite_unchanged() {
+        let editor = editor();
+        let mut bytes = vec![];
+        editor.write_to(&mut bytes).unwrap();
+        let mut original = vec![];
+        File::open("test/campaigns/Rise of Egypt Learning Campaign.cpn")
+            .unwrap()
+            .read_to_end(&mut original)
+            .unwrap();
+        // The original campaign name field contains garbage after the name.
+        assert_eq!(bytes.len(), original.len());
+        assert_eq!(&bytes[260..], &original[260..]);
+    }
+
+    #[test]
+    fn replace_scenario_data() {
+        let mut editor = editor();
+        let old = editor.replace_raw(3, vec![1, 2, 3]).unwrap();
+        assert!(!old.is_empty());
+        assert_eq!(editor.get(3).unwrap().filename(), "Dawn of a New Age.scn");
+        assert_eq!(editor.replace_raw(12, vec![]), None);
+        assert!(editor.remove(12).is_none());
+
+        let mut bytes = vec![];
+        editor.write_to(&mut bytes).unwrap();
+        let mut campaign = Campaign::from(Cursor::new(bytes)).unwrap();
+        assert_eq!(campaign.by_index_raw(3).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn names_that_do_not_fit() {
+        let mut editor = editor();
+        editor.set_name(&"x".repeat(MAX_CAMPAIGN_NAME_LEN));
+        editor.rename(0, &"x".repeat(MAX_SCENARIO_NAME_LEN));
+        editor.set_filename(1, &"x".repeat(MAX_SCENARIO_NAME_LEN));
+        editor.write_to(&mut vec![]).unwrap();
+
+        editor.set_name(&"x".repeat(MAX_CAMPAIGN_NAME_LEN + 1));
+        assert!(matches!(
+            editor.write_to(&mut vec![]),
+            Err(WriteCampaignError::StringTooLongError { max: 255, .. })
+        ));
+        editor.set_name("Campaign");
+        editor.rename(0, &"x".repeat(MAX_SCENARIO_NAME_LEN + 1));
+        assert!(matches!(
+            editor.write_to(&mut vec![]),
+            Err(WriteCampaignError::StringTooLongError { max: 254, .. })
+        ));
+        editor.rename(0, "Scenario");
+        editor.set_filename(11, &"x".repeat(MAX_SCENARIO_NAME_LEN + 1));
+        let mut bytes = vec![];
+        assert!(matches!(
+            editor.write_to(&mut bytes),
+            Err(WriteCampaignError::StringTooLongError { max: 254, .. })
+        ));
+        // Nothing is written if the last name does not fit.
+        assert!(bytes.is_empty());
+    }
+}
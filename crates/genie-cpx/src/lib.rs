@@ -1,14 +1,25 @@
 //! Campaign files store multiple scenario files in one easily distributable chunk.
 //!
 //! genie-cpx can read and write campaign files using the Campaign and CampaignWriter structs,
-//! respectively.
+//! respectively. Existing campaigns can be modified using the CampaignEditor struct.
+//!
+//! Campaigns that are fully available in memory, like memory-mapped files, can be read without
+//! copying using the CampaignBuffer struct.
 use std::io::{Read, Seek, Write};
 
+mod buffer;
+mod convert;
+mod edit;
 mod read;
+mod validate;
 mod write;
 
+pub use buffer::{CampaignBuffer, MappedCampaign};
+pub use convert::{ConvertReport, ConvertScenarioError};
+pub use edit::CampaignEditor;
 pub use read::{Campaign, ReadCampaignError};
-pub use write::{CampaignWriter, WriteCampaignError};
+pub use validate::{CampaignIssue, ValidationReport, MAX_CAMPAIGN_NAME_LEN, MAX_SCENARIO_NAME_LEN};
+pub use write::{CampaignEntry, CampaignWriter, WriteCampaignError};
 
 /// Version identifier for the campaign file format.
 ///
@@ -52,7 +63,7 @@ impl<R> Campaign<R>
 where
     R: Read + Seek,
 {
-    /// Write the scenario file to an output stream.
+    /// Write the campaign file to an output stream.
     pub fn write_to<W: Write>(&mut self, output: &mut W) -> Result<(), WriteCampaignError> {
         let mut writer = CampaignWriter::new(self.name(), output);
 
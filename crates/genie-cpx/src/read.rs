@@ -38,6 +38,8 @@ impl std::fmt::Display for ReadCampaignError {
     }
 }
 
+impl std::error::Error for ReadCampaignError {}
+
 type Result<T> = std::result::Result<T, ReadCampaignError>;
 
 pub fn read_fixed_str<R: Read>(input: &mut R, len: usize) -> Result<Option<String>> {
@@ -56,7 +58,7 @@ pub fn read_fixed_str<R: Read>(input: &mut R, len: usize) -> Result<Option<Strin
     }
 }
 
-fn read_campaign_header<R: Read>(input: &mut R) -> Result<CampaignHeader> {
+pub(crate) fn read_campaign_header<R: Read>(input: &mut R) -> Result<CampaignHeader> {
     let mut version = [0; 4];
     input.read_exact(&mut version)?;
     let name = read_fixed_str(input, 256)?.ok_or(ReadCampaignError::MissingNameError)?;
@@ -69,7 +71,7 @@ fn read_campaign_header<R: Read>(input: &mut R) -> Result<CampaignHeader> {
     })
 }
 
-fn read_scenario_meta<R: Read>(input: &mut R) -> Result<ScenarioMeta> {
+pub(crate) fn read_scenario_meta<R: Read>(input: &mut R) -> Result<ScenarioMeta> {
     let size = input.read_i32::<LE>()? as usize;
     let offset = input.read_i32::<LE>()? as usize;
     let name = read_fixed_str(input, 255)?.ok_or(ReadCampaignError::MissingNameError)?;
@@ -91,9 +93,9 @@ pub struct Campaign<R>
 where
     R: Read + Seek,
 {
-    reader: R,
+    pub(crate) reader: R,
     header: CampaignHeader,
-    entries: Vec<ScenarioMeta>,
+    pub(crate) entries: Vec<ScenarioMeta>,
 }
 
 impl<R> Campaign<R>
@@ -0,0 +1,324 @@
+use crate::read::{Campaign, ReadCampaignError};
+use genie_scx::{SCXVersion, Scenario};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/// The longest campaign name that fits in a classic campaign file, in bytes.
+pub const MAX_CAMPAIGN_NAME_LEN: usize = 255;
+/// The longest scenario name or file name that fits in a classic campaign file, in bytes.
+pub const MAX_SCENARIO_NAME_LEN: usize = 254;
+
+/// A problem found in a campaign file.
+#[derive(Debug)]
+pub enum CampaignIssue {
+    /// A scenario could not be read or parsed.
+    InvalidScenario {
+        index: usize,
+        error: ReadCampaignError,
+    },
+    /// Two scenarios have the same file name.
+    DuplicateFilename {
+        first: usize,
+        index: usize,
+        filename: String,
+    },
+    /// The data of two scenarios overlaps.
+    OverlappingScenarios { first: usize, second: usize },
+    /// The data of a scenario runs past the end of the file.
+    OutOfBounds {
+        index: usize,
+        end: u64,
+        file_size: u64,
+    },
+    /// The campaign name is too long to be stored in a classic campaign file.
+    CampaignNameTooLong { len: usize },
+    /// A scenario name is too long to be stored in a classic campaign file.
+    NameTooLong { index: usize, len: usize },
+    /// A scenario file name is too long to be stored in a classic campaign file.
+    FilenameTooLong { index: usize, len: usize },
+    /// A scenario uses a different format version than most other scenarios in the campaign.
+    VersionMismatch {
+        index: usize,
+        version: SCXVersion,
+        expected: SCXVersion,
+    },
+}
+
+impl std::fmt::Display for CampaignIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CampaignIssue::InvalidScenario { index, error } => {
+                write!(f, "scenario {} is invalid: {}", index, error)
+            }
+            CampaignIssue::DuplicateFilename {
+                first,
+                index,
+                filename,
+            } => write!(
+                f,
+                "scenarios {} and {} have the same file name {:?}",
+                first, index, filename
+            ),
+            CampaignIssue::OverlappingScenarios { first, second } => {
+                write!(f, "scenarios {} and {} overlap", first, second)
+            }
+            CampaignIssue::OutOfBounds {
+                index,
+                end,
+                file_size,
+            } => write!(
+                f,
+                "scenario {} ends at byte {}, but the file is only {} bytes long",
+                index, end, file_size
+            ),
+            CampaignIssue::CampaignNameTooLong { len } => write!(
+                f,
+                "campaign name is {} bytes long, the maximum is {}",
+                len, MAX_CAMPAIGN_NAME_LEN
+            ),
+            CampaignIssue::NameTooLong { index, len } => write!(
+                f,
+                "name of scenario {} is {} bytes long, the maximum is {}",
+                index, len, MAX_SCENARIO_NAME_LEN
+            ),
+            CampaignIssue::FilenameTooLong { index, len } => write!(
+                f,
+                "file name of scenario {} is {} bytes long, the maximum is {}",
+                index, len, MAX_SCENARIO_NAME_LEN
+            ),
+            CampaignIssue::VersionMismatch {
+                index,
+                version,
+                expected,
+            } => write!(
+                f,
+                "scenario {} has version {}, but the other scenarios have version {}",
+                index,
+                String::from_utf8_lossy(version),
+                String::from_utf8_lossy(expected)
+            ),
+        }
+    }
+}
+
+/// The result of validating a campaign.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// The problems that were found.
+    pub issues: Vec<CampaignIssue>,
+    /// The format version of each scenario, or None if it could not be parsed.
+    pub versions: Vec<Option<SCXVersion>>,
+}
+
+impl ValidationReport {
+    /// Returns whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Find the most common version, preferring the earliest scenario's version in case of a tie.
+fn most_common_version(versions: &[Option<SCXVersion>]) -> Option<SCXVersion> {
+    let mut best: Option<(SCXVersion, usize)> = None;
+    for version in versions.iter().flatten() {
+        let count = versions
+            .iter()
+            .filter(|other| **other == Some(*version))
+            .count();
+        if !matches!(best, Some((_, best_count)) if best_count >= count) {
+            best = Some((*version, count));
+        }
+    }
+    best.map(|(version, _)| version)
+}
+
+impl<R> Campaign<R>
+where
+    R: Read + Seek,
+{
+    /// Check this campaign for problems, and parse every scenario in it.
+    ///
+    /// Only I/O errors while reading the campaign are returned as errors; everything else is
+    /// reported in the `ValidationReport`.
+    pub fn validate(&mut self) -> Result<ValidationReport, ReadCampaignError> {
+        let mut report = ValidationReport::default();
+        let file_size = self.reader.seek(SeekFrom::End(0))?;
+
+        let len = self.name().len();
+        if len > MAX_CAMPAIGN_NAME_LEN {
+            report
+                .issues
+                .push(CampaignIssue::CampaignNameTooLong { len });
+        }
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if let Some(first) = self.entries[..index]
+                .iter()
+                .position(|other| other.filename == entry.filename)
+            {
+                report.issues.push(CampaignIssue::DuplicateFilename {
+                    first,
+                    index,
+                    filename: entry.filename.clone(),
+                });
+            }
+
+            if entry.name.len() > MAX_SCENARIO_NAME_LEN {
+                report.issues.push(CampaignIssue::NameTooLong {
+                    index,
+                    len: entry.name.len(),
+                });
+            }
+            if entry.filename.len() > MAX_SCENARIO_NAME_LEN {
+                report.issues.push(CampaignIssue::FilenameTooLong {
+                    index,
+                    len: entry.filename.len(),
+                });
+            }
+
+            // Sizes and offsets come from the file, so they can be anything.
+            let start = entry.offset as u64;
+            let end = start.checked_add(entry.size as u64);
+            match end {
+                Some(end) if end <= file_size => (),
+                _ => report.issues.push(CampaignIssue::OutOfBounds {
+                    index,
+                    end: end.unwrap_or(u64::MAX),
+                    file_size,
+                }),
+            }
+            let end = end.unwrap_or(u64::MAX);
+            for (first, other) in self.entries[..index].iter().enumerate() {
+                let other_start = other.offset as u64;
+                let other_end = other_start.saturating_add(other.size as u64);
+                if start < other_end && other_start < end {
+                    report.issues.push(CampaignIssue::OverlappingScenarios {
+                        first,
+                        second: index,
+                    });
+                }
+            }
+        }
+
+        for index in 0..self.len() {
+            let parsed = self.by_index_raw(index).and_then(|bytes| {
+                Scenario::from(&mut Cursor::new(bytes)).map_err(ReadCampaignError::ParseSCXError)
+            });
+            match parsed {
+                Ok(scenario) => report.versions.push(Some(scenario.format_version())),
+                Err(error) => {
+                    report.versions.push(None);
+                    report
+                        .issues
+                        .push(CampaignIssue::InvalidScenario { index, error });
+                }
+            }
+        }
+
+        if let Some(expected) = most_common_version(&report.versions) {
+            for (index, version) in report.versions.iter().enumerate() {
+                match version {
+                    Some(version) if *version != expected => {
+                        report.issues.push(CampaignIssue::VersionMismatch {
+                            index,
+                            version: *version,
+                            expected,
+                        });
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CampaignEditor;
+    use std::fs::File;
+
+    #[test]
+    fn valid_campaign() {
+        let f = File::open("test/campaigns/Armies at War A Combat Showcase.cpn").unwrap();
+        let mut campaign = Campaign::from(f).unwrap();
+        let report = campaign.validate().unwrap();
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert_eq!(report.versions.len(), 1);
+    }
+
+    #[test]
+    fn invalid_campaign() {
+        let f = File::open("test/campaigns/Armies at War A Combat Showcase.cpn").unwrap();
+        let mut campaign = Campaign::from(f).unwrap();
+        let mut editor = CampaignEditor::from_campaign(&mut campaign).unwrap();
+        let scenario = editor.get(0).unwrap().bytes().to_vec();
+        editor.push_raw("Copy", "Bronze Age Art of War.scn", scenario);
+        editor.push_raw("Broken", "broken.scn", vec![0; 16]);
+        let mut bytes = vec![];
+        editor.write_to(&mut bytes).unwrap();
+
+        let mut campaign = Campaign::from(Cursor::new(bytes.clone())).unwrap();
+        campaign.entries[1].name = "x".repeat(300);
+        // Make the first scenario overlap the second, and the last one run past the end.
+        campaign.entries[0].size += 10;
+        campaign.entries[2].size += 10;
+
+        let report = campaign.validate().unwrap();
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+        assert!(matches!(
+            report.issues[0],
+            CampaignIssue::DuplicateFilename {
+                first: 0,
+                index: 1,
+                ..
+            }
+        ));
+        assert!(issues
+            .contains(&"name of scenario 1 is 300 bytes long, the maximum is 254".to_string()));
+        assert!(issues.contains(&"scenarios 0 and 1 overlap".to_string()));
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, CampaignIssue::OutOfBounds { index: 2, .. })));
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, CampaignIssue::InvalidScenario { index: 2, .. })));
+        assert_eq!(report.versions[2], None);
+    }
+
+    #[test]
+    fn negative_size() {
+        let mut editor = CampaignEditor::new("Negative");
+        editor.push_raw("First", "first.scn", vec![0; 16]);
+        editor.push_raw("Second", "second.scn", vec![0; 16]);
+        let mut bytes = vec![];
+        editor.write_to(&mut bytes).unwrap();
+        // The size of the first scenario is stored right after the campaign header.
+        bytes[264..268].copy_from_slice(&(-1i32).to_le_bytes());
+
+        let mut campaign = Campaign::from(Cursor::new(bytes)).unwrap();
+        let report = campaign.validate().unwrap();
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            CampaignIssue::OutOfBounds {
+                index: 0,
+                end: u64::MAX,
+                ..
+            }
+        )));
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            CampaignIssue::OverlappingScenarios {
+                first: 0,
+                second: 1
+            }
+        )));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::validate::{MAX_CAMPAIGN_NAME_LEN, MAX_SCENARIO_NAME_LEN};
 use crate::{CampaignHeader, ScenarioMeta};
 use byteorder::{WriteBytesExt, LE};
 use genie_scx::{Result as SCXResult, Scenario};
@@ -9,6 +10,13 @@ pub enum WriteCampaignError {
     // EncodeStringError,
     IoError(io::Error),
     NotFoundError(usize),
+    /// A name or file name is longer than the campaign format can store.
+    StringTooLongError {
+        string: String,
+        max: usize,
+    },
+    /// The campaign is too large to be stored in the campaign format.
+    TooLargeError(usize),
 }
 
 impl From<io::Error> for WriteCampaignError {
@@ -24,66 +32,164 @@ impl std::fmt::Display for WriteCampaignError {
             WriteCampaignError::NotFoundError(n) => {
                 write!(f, "missing scenario data for index {}", n)
             }
+            WriteCampaignError::StringTooLongError { string, max } => write!(
+                f,
+                "{:?} is {} bytes long, the maximum is {}",
+                string,
+                string.len(),
+                max
+            ),
+            WriteCampaignError::TooLargeError(size) => {
+                write!(f, "campaign is too large ({} bytes)", size)
+            }
         }
     }
 }
 
 impl std::error::Error for WriteCampaignError {}
 
-fn write_campaign_header<W: Write>(header: &CampaignHeader, output: &mut W) -> io::Result<()> {
-    assert!(header.num_scenarios < std::i32::MAX as usize);
+type Result<T> = std::result::Result<T, WriteCampaignError>;
 
-    output.write_all(&header.version)?;
-    let mut name_bytes = header.name.as_bytes().to_vec();
-    assert!(name_bytes.len() < 256);
-    name_bytes.extend(vec![0; 256 - name_bytes.len()]);
-    output.write_all(&name_bytes)?;
-    output.write_i32::<LE>(header.num_scenarios as i32)?;
+/// Write a string into a fixed-size, zero-padded field. The string must leave room for at least
+/// one zero byte.
+fn write_fixed_str<W: Write>(string: &str, size: usize, output: &mut W) -> Result<()> {
+    if string.len() >= size {
+        return Err(WriteCampaignError::StringTooLongError {
+            string: string.to_owned(),
+            max: size - 1,
+        });
+    }
+    output.write_all(string.as_bytes())?;
+    output.write_all(&vec![0; size - string.len()])?;
     Ok(())
 }
 
-fn write_scenario_meta<W: Write>(meta: &ScenarioMeta, output: &mut W) -> io::Result<()> {
-    assert!(meta.size < std::i32::MAX as usize);
-    assert!(meta.offset < std::i32::MAX as usize);
+/// Convert a size or offset to the integer type used to store it.
+fn to_int<T: std::convert::TryFrom<usize>>(value: usize) -> Result<T> {
+    T::try_from(value).map_err(|_| WriteCampaignError::TooLargeError(value))
+}
 
-    output.write_i32::<LE>(meta.size as i32)?;
-    output.write_i32::<LE>(meta.offset as i32)?;
+fn write_campaign_header<W: Write>(
+    header: &CampaignHeader,
+    num_scenarios: usize,
+    output: &mut W,
+) -> Result<()> {
+    output.write_all(&header.version)?;
+    write_fixed_str(&header.name, MAX_CAMPAIGN_NAME_LEN + 1, output)?;
+    output.write_i32::<LE>(to_int(num_scenarios)?)?;
+    Ok(())
+}
 
-    let mut name_bytes = meta.name.as_bytes().to_vec();
-    assert!(name_bytes.len() < 255);
-    name_bytes.extend(vec![0; 255 - name_bytes.len()]);
-    output.write_all(&name_bytes)?;
+fn write_scenario_meta<W: Write>(meta: &ScenarioMeta, output: &mut W) -> Result<()> {
+    output.write_i32::<LE>(to_int(meta.size)?)?;
+    output.write_i32::<LE>(to_int(meta.offset)?)?;
+    write_fixed_str(&meta.name, MAX_SCENARIO_NAME_LEN + 1, output)?;
+    write_fixed_str(&meta.filename, MAX_SCENARIO_NAME_LEN + 1, output)?;
 
-    let mut filename_bytes = meta.filename.as_bytes().to_vec();
-    assert!(filename_bytes.len() < 255);
-    filename_bytes.extend(vec![0; 255 - filename_bytes.len()]);
-    output.write_all(&filename_bytes)?;
+    // Padding to align the next entry.
+    output.write_all(&[0; 2])?;
 
     Ok(())
 }
 
-struct CampaignEntry {
+/// Size in bytes of the campaign header.
+const HEADER_SIZE: usize = 4 + 256 + 4;
+
+/// Size in bytes of the metadata for a single scenario.
+const META_SIZE: usize = 4 + 4 + 255 + 255 + 2;
+
+/// A scenario file that is stored in memory, to be written to a campaign.
+#[derive(Debug, Clone)]
+pub struct CampaignEntry {
     name: String,
     filename: String,
     bytes: Vec<u8>,
 }
 
 impl CampaignEntry {
-    fn name(&self) -> &str {
+    /// Create an entry for a scenario file.
+    pub fn new(name: &str, filename: &str, bytes: Vec<u8>) -> Self {
+        Self {
+            name: name.to_owned(),
+            filename: filename.to_owned(),
+            bytes,
+        }
+    }
+
+    /// Get the name of the scenario.
+    pub fn name(&self) -> &str {
         &self.name
     }
 
-    fn filename(&self) -> &str {
+    /// Set the name of the scenario.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_owned();
+    }
+
+    /// Get the file name of the scenario.
+    pub fn filename(&self) -> &str {
         &self.filename
     }
 
-    fn size(&self) -> usize {
+    /// Set the file name of the scenario.
+    pub fn set_filename(&mut self, filename: &str) {
+        self.filename = filename.to_owned();
+    }
+
+    /// Get the size in bytes of the scenario file.
+    pub fn size(&self) -> usize {
         self.bytes.len()
     }
 
-    fn bytes(&self) -> &[u8] {
+    /// Get the scenario file data.
+    pub fn bytes(&self) -> &[u8] {
         &self.bytes
     }
+
+    /// Replace the scenario file data, returning the old data.
+    pub fn set_bytes(&mut self, bytes: Vec<u8>) -> Vec<u8> {
+        std::mem::replace(&mut self.bytes, bytes)
+    }
+}
+
+/// Compute the metadata for scenario files that are stored in a campaign in this order.
+pub(crate) fn compute_metas(entries: &[CampaignEntry]) -> Vec<ScenarioMeta> {
+    let mut offset = HEADER_SIZE + entries.len() * META_SIZE;
+    entries
+        .iter()
+        .map(|entry| {
+            let meta = ScenarioMeta {
+                size: entry.size(),
+                offset,
+                name: entry.name().to_owned(),
+                filename: entry.filename().to_owned(),
+            };
+            offset += entry.size();
+            meta
+        })
+        .collect()
+}
+
+/// Write a campaign file containing these scenario files.
+///
+/// The header and metadata are encoded before anything is written, so a name that does not fit
+/// does not leave a partial campaign file behind.
+pub(crate) fn write_campaign<W: Write>(
+    header: &CampaignHeader,
+    entries: &[CampaignEntry],
+    output: &mut W,
+) -> Result<()> {
+    let mut head = Vec::with_capacity(HEADER_SIZE + entries.len() * META_SIZE);
+    write_campaign_header(header, entries.len(), &mut head)?;
+    for meta in compute_metas(entries) {
+        write_scenario_meta(&meta, &mut head)?;
+    }
+
+    output.write_all(&head)?;
+    for entry in entries {
+        output.write_all(entry.bytes())?;
+    }
+    Ok(())
 }
 
 pub struct CampaignWriter<W: Write> {
@@ -102,11 +208,7 @@ impl<W: Write> CampaignWriter<W> {
     }
 
     pub fn add_raw(&mut self, name: &str, filename: &str, scx: Vec<u8>) {
-        self.scenarios.push(CampaignEntry {
-            name: name.to_owned(),
-            filename: filename.to_owned(),
-            bytes: scx,
-        });
+        self.scenarios.push(CampaignEntry::new(name, filename, scx));
     }
 
     pub fn add(&mut self, name: &str, scx: &Scenario) -> SCXResult<()> {
@@ -124,37 +226,12 @@ impl<W: Write> CampaignWriter<W> {
         self.writer
     }
 
-    fn write_header(&mut self) -> io::Result<()> {
-        self.header.num_scenarios = self.scenarios.len();
-        write_campaign_header(&self.header, &mut self.writer)
-    }
-
-    fn write_metas(&mut self) -> io::Result<()> {
-        let mut offset = 256 + 8 + self.scenarios.len() * (255 + 255 + 8);
-        for scen in &self.scenarios {
-            let meta = ScenarioMeta {
-                size: scen.size(),
-                offset,
-                name: scen.name().to_owned(),
-                filename: scen.filename().to_owned(),
-            };
-            write_scenario_meta(&meta, &mut self.writer)?;
-            offset += scen.size();
-        }
-        Ok(())
-    }
-
-    fn write_scenarios(&mut self) -> io::Result<()> {
-        for scen in &self.scenarios {
-            self.writer.write_all(scen.bytes())?;
-        }
-        Ok(())
-    }
-
-    pub fn flush(mut self) -> io::Result<W> {
-        self.write_header()?;
-        self.write_metas()?;
-        self.write_scenarios()?;
+    /// Write the campaign.
+    ///
+    /// Fails if a name does not fit in the campaign format, or if the campaign is too large.
+    /// Nothing is written in that case.
+    pub fn flush(mut self) -> Result<W> {
+        write_campaign(&self.header, &self.scenarios, &mut self.writer)?;
 
         Ok(self.into_inner())
     }
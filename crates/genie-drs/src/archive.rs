@@ -0,0 +1,244 @@
+use crate::{DRSHeader, DRSResource, DRSTable, DRSTableIterator, ResourceType};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{Cursor, Error, ErrorKind};
+use std::path::Path;
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Check that `count` entries of `size` bytes starting at `offset` fit in a buffer of length
+/// `len`.
+fn fits(offset: u32, count: u32, size: u32, len: usize) -> bool {
+    let end = u64::from(offset) + u64::from(count) * u64::from(size);
+    end <= len as u64
+}
+
+/// A DRS archive that is fully available in memory, like a byte slice or a memory-mapped file.
+///
+/// Unlike `DRSReader`, this does not need a file handle to read resources, and hands out borrowed
+/// slices instead of copying resource data. If the buffer type is `Send` and `Sync`, so is the
+/// archive, so it can be shared between threads.
+///
+/// All offsets in the archive are checked when it is opened, so a truncated or corrupt archive
+/// results in an error instead of garbage data.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use genie_drs::MappedArchive;
+///
+/// let drs = MappedArchive::open("test.drs")?;
+/// let content = drs.read_resource("json".into(), 4)?;
+/// println!("{}", std::str::from_utf8(content)?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DRSArchive<B>
+where
+    B: AsRef<[u8]>,
+{
+    data: B,
+    header: DRSHeader,
+    tables: Vec<DRSTable>,
+}
+
+/// A DRS archive file that is mapped into memory.
+pub type MappedArchive = DRSArchive<Mmap>;
+
+impl MappedArchive {
+    /// Map a DRS archive file into memory and read its directory.
+    ///
+    /// The file must not be modified while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        // Safety: Modifying the file while it is mapped is undefined behaviour, which is
+        // documented above.
+        let data = unsafe { Mmap::map(&file)? };
+        Self::from(data)
+    }
+}
+
+impl<B> DRSArchive<B>
+where
+    B: AsRef<[u8]>,
+{
+    /// Create an archive instance from a buffer containing a DRS archive.
+    ///
+    /// This immediately reads the header and the directory, and checks that all tables and
+    /// resources are inside the buffer.
+    pub fn from(data: B) -> Result<Self, Error> {
+        let bytes = data.as_ref();
+        let len = bytes.len();
+        let mut input = Cursor::new(bytes);
+        let header = DRSHeader::from(&mut input)?;
+        if !fits(64, header.num_resource_types, 12, len) {
+            return Err(invalid_data("Table list runs past the end of the archive"));
+        }
+
+        let mut tables = vec![];
+        for _ in 0..header.num_resource_types {
+            tables.push(DRSTable::from(&mut input)?);
+        }
+
+        for table in tables.iter_mut() {
+            if !fits(table.offset, table.num_resources, 12, len) {
+                return Err(invalid_data(
+                    "Resource list runs past the end of the archive",
+                ));
+            }
+            input.set_position(u64::from(table.offset));
+            table.read_resources(&mut input)?;
+            if !table
+                .resources()
+                .all(|resource| fits(resource.offset, resource.size, 1, len))
+            {
+                return Err(invalid_data(
+                    "Resource data runs past the end of the archive",
+                ));
+            }
+        }
+
+        Ok(Self {
+            data,
+            header,
+            tables,
+        })
+    }
+
+    /// Consume this instance and get the buffer.
+    pub fn into_inner(self) -> B {
+        self.data
+    }
+
+    /// Get the archive header.
+    pub fn header(&self) -> &DRSHeader {
+        &self.header
+    }
+
+    /// Iterate over the tables in this DRS archive.
+    #[inline]
+    pub fn tables(&self) -> DRSTableIterator<'_> {
+        self.tables.iter()
+    }
+
+    /// Get the table for the given resource type.
+    pub fn get_table(&self, resource_type: ResourceType) -> Option<&DRSTable> {
+        self.tables
+            .iter()
+            .find(|table| table.resource_type == resource_type)
+    }
+
+    /// Get a resource of a given type and ID.
+    pub fn get_resource(&self, resource_type: ResourceType, id: u32) -> Option<&DRSResource> {
+        self.get_table(resource_type)
+            .and_then(|table| table.get_resource(id))
+    }
+
+    /// Get the type of a resource with the given ID.
+    pub fn get_resource_type(&self, id: u32) -> Option<ResourceType> {
+        self.tables
+            .iter()
+            .find(|table| table.get_resource(id).is_some())
+            .map(|table| table.resource_type)
+    }
+
+    /// Get the data of a resource.
+    pub fn read_resource(&self, resource_type: ResourceType, id: u32) -> Result<&[u8], Error> {
+        let &DRSResource { offset, size, .. } = self
+            .get_resource(resource_type, id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Resource not found in this archive"))?;
+        // Bounds were checked when reading the directory.
+        let start = offset as usize;
+        Ok(&self.data.as_ref()[start..start + size as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DRSReader;
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn matches_stream_reader() {
+        assert_send_sync::<DRSArchive<&[u8]>>();
+        assert_send_sync::<MappedArchive>();
+
+        let archive = MappedArchive::open("test.drs").unwrap();
+        let mut file = fs::File::open("test.drs").unwrap();
+        let reader = DRSReader::new(&mut file).unwrap();
+        assert_eq!(archive.tables().count(), reader.tables().count());
+        for table in reader.tables() {
+            for resource in table.resources() {
+                let expected = reader
+                    .read_resource(&mut file, table.resource_type, resource.id)
+                    .unwrap();
+                let data = archive
+                    .read_resource(table.resource_type, resource.id)
+                    .unwrap();
+                assert_eq!(data, &expected[..]);
+            }
+        }
+        assert_eq!(archive.get_resource_type(4), Some("json".into()));
+        assert_eq!(
+            archive.read_resource("js".into(), 4).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn share_between_threads() {
+        let archive = Arc::new(MappedArchive::open("test.drs").unwrap());
+        let handles: Vec<_> = (1..=4)
+            .map(|id| {
+                let archive = Arc::clone(&archive);
+                thread::spawn(move || {
+                    let t = archive.get_resource_type(id).unwrap();
+                    archive.read_resource(t, id).unwrap().len()
+                })
+            })
+            .collect();
+        let sizes: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(sizes, vec![632, 452, 38, 710]);
+    }
+
+    #[test]
+    fn truncated_archive() {
+        let bytes = fs::read("test.drs").unwrap();
+        assert!(DRSArchive::from(&bytes[..]).is_ok());
+        for &len in &[0, 40, 70, 100, bytes.len() - 1] {
+            let err = DRSArchive::from(&bytes[..len]).unwrap_err();
+            assert!(
+                matches!(
+                    err.kind(),
+                    ErrorKind::InvalidData | ErrorKind::UnexpectedEof
+                ),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_directory() {
+        let bytes = fs::read("test.drs").unwrap();
+
+        let mut huge_table = bytes.clone();
+        // Claim the first table has u32::MAX resources.
+        huge_table[72..76].copy_from_slice(&[0xFF; 4]);
+        let err = DRSArchive::from(huge_table).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut bad_offset = bytes;
+        // Point the first resource far past the end of the file.
+        bad_offset[92..96].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0x7F]);
+        let err = DRSArchive::from(bad_offset).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,678 @@
+use crate::{DRSHeader, DRSResource, DRSTable, ResourceType};
+use std::convert::TryFrom;
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Where the data for a resource lives.
+#[derive(Debug)]
+enum ResourceData {
+    /// Unchanged data in the original archive.
+    Original { offset: u32, size: u32 },
+    /// New data, with the offset and size of the original data it replaces, if any.
+    Replaced {
+        data: Vec<u8>,
+        slot: Option<(u32, u32)>,
+    },
+}
+
+#[derive(Debug)]
+struct EditorResource {
+    id: u32,
+    data: ResourceData,
+}
+
+impl EditorResource {
+    fn size(&self) -> u32 {
+        match &self.data {
+            ResourceData::Original { size, .. } => *size,
+            ResourceData::Replaced { data, .. } => data.len() as u32,
+        }
+    }
+
+    /// Whether this resource's data can be written without moving other data around.
+    fn fits(&self) -> bool {
+        match &self.data {
+            ResourceData::Replaced {
+                data,
+                slot: Some((_, size)),
+            } => data.len() <= *size as usize,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct EditorTable {
+    resource_type: ResourceType,
+    resources: Vec<EditorResource>,
+}
+
+impl EditorTable {
+    fn get(&self, id: u32) -> Option<&EditorResource> {
+        self.resources.iter().find(|resource| resource.id == id)
+    }
+
+    fn get_mut(&mut self, id: u32) -> Option<&mut EditorResource> {
+        self.resources.iter_mut().find(|resource| resource.id == id)
+    }
+}
+
+/// How `DRSEditor::save` wrote the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveMode {
+    /// Only changed metadata and resources were written. New data was appended to the end of the
+    /// archive.
+    InPlace,
+    /// The entire archive was rewritten, and is now `len` bytes long. If the old archive was
+    /// longer, the bytes after `len` are unused, and a file can be truncated using
+    /// `File::set_len`.
+    Rebuilt { len: u64 },
+}
+
+fn not_found() -> Error {
+    Error::new(ErrorKind::NotFound, "Resource not found in this archive")
+}
+
+fn file_too_large() -> Error {
+    Error::new(ErrorKind::InvalidInput, "file too large")
+}
+
+fn archive_too_large() -> Error {
+    Error::new(ErrorKind::InvalidInput, "archive too large")
+}
+
+fn already_exists() -> Error {
+    Error::new(
+        ErrorKind::AlreadyExists,
+        "A resource with this ID already exists in this table",
+    )
+}
+
+/// Editor for existing .drs archives.
+///
+/// Resource data is only read from the original archive when it is needed. Changes are kept in
+/// memory until the archive is saved or written somewhere else. The banner message, version and
+/// password in the archive header are kept as they are.
+///
+/// ```rust
+/// use std::{io::Cursor, fs::File};
+/// use genie_drs::{DRSEditor, SaveMode};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut drs = DRSEditor::new(Cursor::new(std::fs::read("test.drs")?))?;
+/// drs.replace("js", 1, b"console.log('hi')".to_vec())?;
+/// drs.remove("js", 2);
+/// drs.set_id("json", 4, 5)?;
+/// drs.add("txt", 1, b"some text".to_vec())?;
+/// if let SaveMode::Rebuilt { len } = drs.save()? {
+///     drs.into_inner().get_mut().truncate(len as usize);
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug)]
+pub struct DRSEditor<R>
+where
+    R: Read + Seek,
+{
+    input: R,
+    header: DRSHeader,
+    tables: Vec<EditorTable>,
+}
+
+impl<R> DRSEditor<R>
+where
+    R: Read + Seek,
+{
+    /// Open an archive for editing.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        input.seek(SeekFrom::Start(0))?;
+        let header = DRSHeader::from(&mut input)?;
+        let mut tables = vec![];
+        for _ in 0..header.num_resource_types {
+            tables.push(DRSTable::from(&mut input)?);
+        }
+
+        let mut editor_tables = vec![];
+        for mut table in tables {
+            input.seek(SeekFrom::Start(u64::from(table.offset)))?;
+            table.read_resources(&mut input)?;
+            let resources = table
+                .resources()
+                .map(|resource| EditorResource {
+                    id: resource.id,
+                    data: ResourceData::Original {
+                        offset: resource.offset,
+                        size: resource.size,
+                    },
+                })
+                .collect();
+            editor_tables.push(EditorTable {
+                resource_type: table.resource_type,
+                resources,
+            });
+        }
+
+        Ok(Self {
+            input,
+            header,
+            tables: editor_tables,
+        })
+    }
+
+    /// Get the archive header, as it was read from the original archive.
+    pub fn header(&self) -> &DRSHeader {
+        &self.header
+    }
+
+    /// Consume the editor and return the underlying handle.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    /// Iterate over the types of the tables in this archive.
+    pub fn table_types(&self) -> impl Iterator<Item = ResourceType> + '_ {
+        self.tables.iter().map(|table| table.resource_type)
+    }
+
+    /// Get the IDs of the resources in a table, in the order they are stored.
+    pub fn resource_ids(&self, t: impl Into<ResourceType>) -> Vec<u32> {
+        self.table(t.into())
+            .map(|table| table.resources.iter().map(|resource| resource.id).collect())
+            .unwrap_or_default()
+    }
+
+    /// Check if the archive contains a resource.
+    pub fn contains(&self, t: impl Into<ResourceType>, id: u32) -> bool {
+        self.resource_size(t, id).is_some()
+    }
+
+    /// Get the size in bytes of a resource.
+    pub fn resource_size(&self, t: impl Into<ResourceType>, id: u32) -> Option<u32> {
+        self.table(t.into())
+            .and_then(|table| table.get(id))
+            .map(EditorResource::size)
+    }
+
+    fn table(&self, t: ResourceType) -> Option<&EditorTable> {
+        self.tables.iter().find(|table| table.resource_type == t)
+    }
+
+    fn table_mut(&mut self, t: ResourceType) -> Option<&mut EditorTable> {
+        self.tables
+            .iter_mut()
+            .find(|table| table.resource_type == t)
+    }
+
+    fn resource_mut(&mut self, t: ResourceType, id: u32) -> io::Result<&mut EditorResource> {
+        self.table_mut(t)
+            .and_then(|table| table.get_mut(id))
+            .ok_or_else(not_found)
+    }
+
+    /// Read the current data of a resource.
+    pub fn read_resource(&mut self, t: impl Into<ResourceType>, id: u32) -> io::Result<Vec<u8>> {
+        let t = t.into();
+        let resource = self
+            .table(t)
+            .and_then(|table| table.get(id))
+            .ok_or_else(not_found)?;
+        match resource.data {
+            ResourceData::Original { offset, size } => {
+                self.input.seek(SeekFrom::Start(u64::from(offset)))?;
+                let mut buf = vec![0; size as usize];
+                self.input.read_exact(&mut buf)?;
+                Ok(buf)
+            }
+            ResourceData::Replaced { ref data, .. } => Ok(data.clone()),
+        }
+    }
+
+    /// Add a table. Returns false if the archive already has a table of this type.
+    pub fn add_table(&mut self, t: impl Into<ResourceType>) -> bool {
+        let t = t.into();
+        if self.table(t).is_some() {
+            return false;
+        }
+        self.tables.push(EditorTable {
+            resource_type: t,
+            resources: vec![],
+        });
+        true
+    }
+
+    /// Add a resource, creating its table if it does not exist yet.
+    ///
+    /// Returns an error if the table already contains a resource with this ID, or if the data is
+    /// too large to be stored in an archive.
+    pub fn add(&mut self, t: impl Into<ResourceType>, id: u32, data: Vec<u8>) -> io::Result<()> {
+        let t = t.into();
+        if data.len() >= u32::MAX as usize {
+            return Err(file_too_large());
+        }
+        self.add_table(t);
+        let table = self.table_mut(t).unwrap();
+        if table.get(id).is_some() {
+            return Err(already_exists());
+        }
+        table.resources.push(EditorResource {
+            id,
+            data: ResourceData::Replaced { data, slot: None },
+        });
+        Ok(())
+    }
+
+    /// Replace the data of a resource.
+    ///
+    /// Returns an error if the resource does not exist, or if the data is too large to be stored
+    /// in an archive.
+    pub fn replace(
+        &mut self,
+        t: impl Into<ResourceType>,
+        id: u32,
+        data: Vec<u8>,
+    ) -> io::Result<()> {
+        if data.len() >= u32::MAX as usize {
+            return Err(file_too_large());
+        }
+        let resource = self.resource_mut(t.into(), id)?;
+        let slot = match resource.data {
+            ResourceData::Original { offset, size } => Some((offset, size)),
+            ResourceData::Replaced { slot, .. } => slot,
+        };
+        resource.data = ResourceData::Replaced { data, slot };
+        Ok(())
+    }
+
+    /// Remove a resource. Returns false if the resource does not exist.
+    ///
+    /// The table is kept even if it becomes empty.
+    pub fn remove(&mut self, t: impl Into<ResourceType>, id: u32) -> bool {
+        match self.table_mut(t.into()) {
+            Some(table) => {
+                let len = table.resources.len();
+                table.resources.retain(|resource| resource.id != id);
+                table.resources.len() != len
+            }
+            None => false,
+        }
+    }
+
+    /// Change the ID of a resource.
+    ///
+    /// Returns an error if the resource does not exist, or if the table already contains a
+    /// resource with the new ID.
+    pub fn set_id(&mut self, t: impl Into<ResourceType>, id: u32, new_id: u32) -> io::Result<()> {
+        let t = t.into();
+        if id != new_id && self.table(t).and_then(|table| table.get(new_id)).is_some() {
+            return Err(already_exists());
+        }
+        self.resource_mut(t, id)?.id = new_id;
+        Ok(())
+    }
+
+    /// Size in bytes of the metadata and tables for the current contents.
+    fn directory_size(&self) -> u32 {
+        let num_resources = self
+            .tables
+            .iter()
+            .fold(0, |acc, table| acc + table.resources.len());
+        64 + 12 * (self.tables.len() + num_resources) as u32
+    }
+
+    /// Check if `save` can write the changes without rebuilding the entire archive.
+    ///
+    /// This is possible if the new tables fit in the space used by the original tables, and all
+    /// replaced resources fit in the space used by their original data. Space for extra tables can
+    /// be reserved when creating an archive using `ReserveDirectoryStrategy`.
+    pub fn can_save_in_place(&self) -> bool {
+        self.directory_size() <= self.header.directory_size
+            && self
+                .tables
+                .iter()
+                .all(|table| table.resources.iter().all(EditorResource::fits))
+    }
+
+    /// Write the header and tables, using the given resource offsets.
+    fn write_directory<W: Write>(
+        &self,
+        output: &mut W,
+        directory_size: u32,
+        offsets: &[Vec<u32>],
+    ) -> io::Result<()> {
+        let mut header = self.header.clone();
+        header.num_resource_types = self.tables.len() as u32;
+        header.directory_size = directory_size;
+        header.write_to(output)?;
+
+        let mut table_offset = 64 + 12 * (self.tables.len() as u32);
+        for table in &self.tables {
+            let num_resources = table.resources.len() as u32;
+            DRSTable::new(table.resource_type, table_offset, num_resources).write_to(output)?;
+            table_offset += 12 * num_resources;
+        }
+        for (table, offsets) in self.tables.iter().zip(offsets) {
+            for (resource, &offset) in table.resources.iter().zip(offsets) {
+                DRSResource {
+                    id: resource.id,
+                    offset,
+                    size: resource.size(),
+                }
+                .write_to(output)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Point all resources at the given offsets, after they have been written there.
+    fn commit(&mut self, directory_size: u32, offsets: Vec<Vec<u32>>) {
+        self.header.num_resource_types = self.tables.len() as u32;
+        self.header.directory_size = directory_size;
+        for (table, offsets) in self.tables.iter_mut().zip(offsets) {
+            for (resource, offset) in table.resources.iter_mut().zip(offsets) {
+                resource.data = ResourceData::Original {
+                    offset,
+                    size: resource.size(),
+                };
+            }
+        }
+    }
+
+    /// Compute resource offsets for a fully rebuilt archive, with all data stored back to back.
+    fn packed_offsets(&self, directory_size: u32) -> io::Result<Vec<Vec<u32>>> {
+        let mut file_offset = directory_size;
+        let mut offsets = vec![];
+        for table in &self.tables {
+            let mut table_offsets = vec![];
+            for resource in &table.resources {
+                table_offsets.push(file_offset);
+                file_offset = file_offset
+                    .checked_add(resource.size())
+                    .ok_or_else(archive_too_large)?;
+            }
+            offsets.push(table_offsets);
+        }
+        Ok(offsets)
+    }
+
+    /// Write a fully rebuilt archive with all changes to a different output.
+    ///
+    /// The editor keeps reading from the original archive afterwards.
+    pub fn write_to<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        let directory_size = self.directory_size();
+        let offsets = self.packed_offsets(directory_size)?;
+        self.write_directory(output, directory_size, &offsets)?;
+
+        let input = &mut self.input;
+        for table in &self.tables {
+            for resource in &table.resources {
+                match &resource.data {
+                    ResourceData::Original { offset, size } => {
+                        input.seek(SeekFrom::Start(u64::from(*offset)))?;
+                        let copied = io::copy(&mut input.by_ref().take(u64::from(*size)), output)?;
+                        if copied != u64::from(*size) {
+                            return Err(Error::new(
+                                ErrorKind::UnexpectedEof,
+                                "Resource data runs past the end of the archive",
+                            ));
+                        }
+                    }
+                    ResourceData::Replaced { data, .. } => output.write_all(data)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R> DRSEditor<R>
+where
+    R: Read + Write + Seek,
+{
+    /// Write all changes back to the original archive.
+    ///
+    /// If `can_save_in_place` returns true, only the changed parts of the archive are written.
+    /// Otherwise, the archive is rebuilt in a temporary file, which is then copied over the
+    /// original. Use `save_with_scratch` to choose where the rebuilt archive is stored.
+    ///
+    /// A rebuilt archive can be shorter than the original. `R` can not be truncated generically,
+    /// so the old data after the new end stays in place: when this returns `SaveMode::Rebuilt`,
+    /// the caller must truncate the archive to `len` bytes, for example using `File::set_len`.
+    pub fn save(&mut self) -> io::Result<SaveMode> {
+        if self.can_save_in_place() {
+            self.save_in_place()?;
+            return Ok(SaveMode::InPlace);
+        }
+        self.save_with_scratch(tempfile::tempfile()?)
+    }
+
+    /// Write all changes back to the original archive, using `scratch` to store the rebuilt
+    /// archive if it can not be saved in place. `scratch` must be empty.
+    ///
+    /// See `save` for details.
+    pub fn save_with_scratch<T>(&mut self, mut scratch: T) -> io::Result<SaveMode>
+    where
+        T: Read + Write + Seek,
+    {
+        if self.can_save_in_place() {
+            self.save_in_place()?;
+            return Ok(SaveMode::InPlace);
+        }
+
+        self.write_to(&mut scratch)?;
+        let len = scratch.stream_position()?;
+        scratch.seek(SeekFrom::Start(0))?;
+        self.input.seek(SeekFrom::Start(0))?;
+        io::copy(&mut scratch, &mut self.input)?;
+        self.input.flush()?;
+
+        let directory_size = self.directory_size();
+        let offsets = self.packed_offsets(directory_size)?;
+        self.commit(directory_size, offsets);
+        Ok(SaveMode::Rebuilt { len })
+    }
+
+    fn save_in_place(&mut self) -> io::Result<()> {
+        // Compute all offsets first, so nothing is written if the archive would be too large.
+        let mut end = self.input.seek(SeekFrom::End(0))?;
+        let mut offsets = vec![];
+        for table in &self.tables {
+            let mut table_offsets = vec![];
+            for resource in &table.resources {
+                let offset = match &resource.data {
+                    ResourceData::Original { offset, .. } => *offset,
+                    ResourceData::Replaced {
+                        slot: Some((offset, _)),
+                        ..
+                    } => *offset,
+                    ResourceData::Replaced { data, slot: None } => {
+                        let offset = u32::try_from(end).map_err(|_| archive_too_large())?;
+                        end += data.len() as u64;
+                        if end > u64::from(u32::MAX) {
+                            return Err(archive_too_large());
+                        }
+                        offset
+                    }
+                };
+                table_offsets.push(offset);
+            }
+            offsets.push(table_offsets);
+        }
+
+        for (table, offsets) in self.tables.iter().zip(&offsets) {
+            for (resource, &offset) in table.resources.iter().zip(offsets) {
+                if let ResourceData::Replaced { data, .. } = &resource.data {
+                    self.input.seek(SeekFrom::Start(u64::from(offset)))?;
+                    self.input.write_all(data)?;
+                }
+            }
+        }
+
+        // Keep the original directory size, and clear the leftover space.
+        let directory_size = self.header.directory_size;
+        let mut directory = vec![];
+        self.write_directory(&mut directory, directory_size, &offsets)?;
+        directory.resize(directory_size as usize, 0);
+        self.input.seek(SeekFrom::Start(0))?;
+        self.input.write_all(&directory)?;
+        self.input.flush()?;
+
+        self.commit(directory_size, offsets);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DRSReader, DRSWriter, ReserveDirectoryStrategy};
+    use std::fs;
+    use std::io::Cursor;
+
+    fn open() -> DRSEditor<Cursor<Vec<u8>>> {
+        DRSEditor::new(Cursor::new(fs::read("test.drs").unwrap())).unwrap()
+    }
+
+    fn read_all(bytes: Vec<u8>) -> Vec<(String, u32, Vec<u8>)> {
+        let mut input = Cursor::new(bytes);
+        let drs = DRSReader::new(&mut input).unwrap();
+        let mut resources = vec![];
+        for table in drs.tables() {
+            for resource in table.resources() {
+                let data = drs
+                    .read_resource(&mut input, table.resource_type, resource.id)
+                    .unwrap();
+                resources.push((table.resource_ext(), resource.id, data.to_vec()));
+            }
+        }
+        resources
+    }
+
+    #[test]
+    fn unchanged_rebuild_is_identical() {
+        let original = fs::read("test.drs").unwrap();
+        let mut drs = open();
+        assert_eq!(drs.table_types().count(), 2);
+        assert_eq!(drs.resource_ids("js"), vec![1, 2, 3]);
+        assert!(drs.can_save_in_place());
+
+        let mut output = vec![];
+        drs.write_to(&mut output).unwrap();
+        assert_eq!(output, original);
+    }
+
+    #[test]
+    fn rebuild_too_large() {
+        let mut bytes = fs::read("test.drs").unwrap();
+        // Claim that the first two resources are 2GiB each.
+        for index in 0..2 {
+            let size = 64 + 2 * 12 + index * 12 + 8;
+            bytes[size..size + 4].copy_from_slice(&0x8000_0000u32.to_le_bytes());
+        }
+        let mut drs = DRSEditor::new(Cursor::new(bytes)).unwrap();
+        let mut output = vec![];
+        let err = drs.write_to(&mut output).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn replace_in_place() {
+        let original = fs::read("test.drs").unwrap();
+        let mut drs = open();
+        drs.replace("js", 2, b"short".to_vec()).unwrap();
+        drs.remove("js", 3);
+        assert!(drs.can_save_in_place());
+        assert_eq!(drs.save().unwrap(), SaveMode::InPlace);
+        assert_eq!(drs.resource_size("js", 2), Some(5));
+
+        let output = drs.into_inner().into_inner();
+        assert_eq!(output.len(), original.len());
+        // The banner, version, password, and directory size are unchanged.
+        assert_eq!(&output[..60], &original[..60]);
+        let resources = read_all(output);
+        assert_eq!(resources.len(), 3);
+        assert_eq!(resources[1], ("js".to_string(), 2, b"short".to_vec()));
+        assert_eq!(resources[2].1, 4);
+    }
+
+    #[test]
+    fn rebuild_when_too_large() {
+        let original = fs::read("test.drs").unwrap();
+        let mut drs = open();
+        let big = vec![b'x'; 1000];
+        drs.replace("js", 3, big.clone()).unwrap();
+        drs.set_id("json", 4, 40).unwrap();
+        assert_eq!(
+            drs.set_id("js", 1, 2).unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            drs.set_id("js", 10, 11).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert!(drs.add_table("txt"));
+        assert!(!drs.add_table("js"));
+        drs.add("txt", 1, b"new".to_vec()).unwrap();
+        assert!(drs.add("txt", 1, vec![]).is_err());
+        assert!(!drs.can_save_in_place());
+
+        let len = match drs.save().unwrap() {
+            SaveMode::Rebuilt { len } => len as usize,
+            mode => panic!("expected a rebuild, got {:?}", mode),
+        };
+        // The editor now reads from the rebuilt archive.
+        assert_eq!(drs.read_resource("js", 3).unwrap(), big);
+        assert_eq!(drs.read_resource("json", 40).unwrap().len(), 710);
+        assert!(drs.can_save_in_place());
+
+        let mut output = drs.into_inner().into_inner();
+        output.truncate(len);
+        assert_eq!(&output[..56], &original[..56]);
+        let resources = read_all(output);
+        let ids: Vec<_> = resources
+            .iter()
+            .map(|(ext, id, _)| (ext.as_str(), *id))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![("js", 1), ("js", 2), ("js", 3), ("json", 40), ("txt", 1)]
+        );
+        assert_eq!(resources[4].2, b"new".to_vec());
+    }
+
+    #[test]
+    fn rebuild_with_scratch() {
+        let mut drs = open();
+        drs.replace("js", 3, vec![b'x'; 1000]).unwrap();
+        let mut scratch = Cursor::new(vec![]);
+        let len = match drs.save_with_scratch(&mut scratch).unwrap() {
+            SaveMode::Rebuilt { len } => len as usize,
+            mode => panic!("expected a rebuild, got {:?}", mode),
+        };
+        let scratch = scratch.into_inner();
+        assert_eq!(scratch.len(), len);
+        let output = drs.into_inner().into_inner();
+        assert_eq!(&output[..len], &scratch[..]);
+    }
+
+    #[test]
+    fn append_to_reserved_directory() {
+        let mut writer =
+            DRSWriter::new(Cursor::new(vec![]), ReserveDirectoryStrategy::new(2, 4)).unwrap();
+        writer.add("txt", 1, &b"one"[..]).unwrap();
+        let output = writer.flush().unwrap();
+
+        let mut drs = DRSEditor::new(output).unwrap();
+        drs.add("txt", 2, b"two".to_vec()).unwrap();
+        drs.add("bina", 1, b"three".to_vec()).unwrap();
+        assert_eq!(drs.save().unwrap(), SaveMode::InPlace);
+
+        let resources = read_all(drs.into_inner().into_inner());
+        assert_eq!(
+            resources,
+            vec![
+                ("txt".to_string(), 1, b"one".to_vec()),
+                ("txt".to_string(), 2, b"two".to_vec()),
+                ("bina".to_string(), 1, b"three".to_vec()),
+            ]
+        );
+    }
+}
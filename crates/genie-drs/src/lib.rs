@@ -24,19 +24,28 @@
 
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use sorted_vec::SortedVec;
-use std::io::{Error, Read, Write};
+use std::io::{Error, ErrorKind, Read, Write};
 use std::slice;
 use std::str;
 
+mod archive;
+mod edit;
+#[cfg(feature = "manifest")]
+mod manifest;
 mod read;
 mod write;
 
+pub use archive::{DRSArchive, MappedArchive};
+pub use edit::{DRSEditor, SaveMode};
+#[cfg(feature = "manifest")]
+pub use manifest::{Manifest, ManifestResource, ManifestTable, MANIFEST_FILE};
 pub use read::DRSReader;
 pub use write::{
     DRSWriter,
     InMemoryStrategy,
     ReserveDirectoryStrategy,
     Strategy as WriteStrategy,
+    TempFileStrategy,
 };
 
 /// A DRS version string.
@@ -46,7 +55,7 @@ type DRSVersion = [u8; 4];
 ///
 /// In a .drs archive, type names are represented as 4 bytes. They are laid out in reverse order and
 /// padded with ASCII space characters (`' '`). For example, the "slp" resource type is stored as `" pls"`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ResourceType([u8; 4]);
 impl ResourceType {
     #[inline]
@@ -110,6 +119,7 @@ impl From<&str> for ResourceType {
 }
 
 /// The DRS archive header.
+#[derive(Clone)]
 pub struct DRSHeader {
     /// A copyright message.
     banner_msg: [u8; 40],
@@ -190,8 +200,8 @@ pub struct DRSTable {
     num_resources: u32,
     /// Resources.
     resources: Vec<DRSResource>,
-    /// Resource IDs.
-    resource_ids: SortedVec<u32>,
+    /// Resource IDs and their index in `resources`, sorted by ID.
+    resource_ids: SortedVec<(u32, usize)>,
 }
 
 impl DRSTable {
@@ -224,16 +234,32 @@ impl DRSTable {
     }
 
     /// Read the table itself.
+    ///
+    /// Returns an `InvalidData` error if the table contains the same resource ID more than once.
     #[inline]
     fn read_resources<R: Read>(&mut self, source: &mut R) -> Result<(), Error> {
         for _ in 0..self.num_resources {
             let resource = DRSResource::from(source)?;
-            let _discard = self.resource_ids.insert(resource.id);
-            self.resources.push(resource);
+            if self.get_resource(resource.id).is_some() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Duplicate resource ID {} in table '{}'",
+                        resource.id,
+                        self.resource_ext()
+                    ),
+                ));
+            }
+            self.push_resource(resource);
         }
         Ok(())
     }
 
+    fn push_resource(&mut self, res: DRSResource) {
+        let _discard = self.resource_ids.insert((res.id, self.resources.len()));
+        self.resources.push(res);
+    }
+
     /// Get the number of resources in this table.
     #[inline]
     pub fn len(&self) -> usize {
@@ -255,9 +281,10 @@ impl DRSTable {
     /// Find a resource by ID.
     #[inline]
     pub fn get_resource(&self, id: u32) -> Option<&DRSResource> {
-        self.resource_ids.binary_search(&id)
+        self.resource_ids
+            .binary_search_by_key(&id, |&(id, _)| id)
             .ok()
-            .map(|index| &self.resources[index])
+            .map(|position| &self.resources[self.resource_ids[position].1])
     }
 
     #[inline]
@@ -265,12 +292,26 @@ impl DRSTable {
         self.resource_type.to_string()
     }
 
+    /// Add a resource. The caller must make sure that the ID is not in use yet.
     #[inline]
     pub(crate) fn add(&mut self, res: DRSResource) -> &mut DRSResource {
-        self.resources.push(res);
+        debug_assert!(self.get_resource(res.id).is_none());
+        self.push_resource(res);
         self.num_resources += 1;
         self.resources.last_mut().unwrap()
     }
+
+    /// Sort the resources by ID.
+    pub(crate) fn sort(&mut self) {
+        self.resources.sort_by_key(|res| res.id);
+        self.resource_ids = SortedVec::from_unsorted(
+            self.resources
+                .iter()
+                .enumerate()
+                .map(|(index, res)| (res.id, index))
+                .collect(),
+        );
+    }
 }
 
 impl std::fmt::Debug for DRSTable {
@@ -321,7 +362,9 @@ pub type DRSResourceIterator<'a> = slice::Iter<'a, DRSResource>;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     use std::fs::File;
+    use std::io::Cursor;
 
     #[test]
     fn it_works() {
@@ -347,4 +390,105 @@ mod tests {
             }
         }
     }
+
+    fn resource_data(id: u32) -> Vec<u8> {
+        id.to_le_bytes().repeat(id as usize % 5)
+    }
+
+    /// Write an archive with the given resources, in order.
+    fn write_archive(resources: &[(&str, u32)], sorted: bool, in_memory: bool) -> Vec<u8> {
+        fn add_all<S: WriteStrategy<Cursor<Vec<u8>>>>(
+            mut drs: DRSWriter<Cursor<Vec<u8>>, S>,
+            resources: &[(&str, u32)],
+            sorted: bool,
+        ) -> Vec<u8> {
+            drs.set_sorted(sorted);
+            for &(t, id) in resources {
+                drs.add(t, id, &resource_data(id)[..]).unwrap();
+            }
+            drs.flush().unwrap().into_inner()
+        }
+
+        let output = Cursor::new(vec![]);
+        if in_memory {
+            add_all(DRSWriter::new(output, InMemoryStrategy::default()).unwrap(), resources, sorted)
+        } else {
+            let strategy = ReserveDirectoryStrategy::new(2, resources.len() as u32);
+            add_all(DRSWriter::new(output, strategy).unwrap(), resources, sorted)
+        }
+    }
+
+    #[test]
+    fn unsorted_lookup() {
+        let bytes = write_archive(&[("txt", 3), ("txt", 1), ("txt", 2)], false, true);
+        let mut input = Cursor::new(bytes);
+        let drs = DRSReader::new(&mut input).unwrap();
+        let table = drs.get_table("txt".into()).unwrap();
+        let ids: Vec<_> = table.resources().map(|res| res.id).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+        for id in 1..=3 {
+            assert_eq!(table.get_resource(id).unwrap().id, id);
+            let content = drs.read_resource(&mut input, "txt".into(), id).unwrap();
+            assert_eq!(content.to_vec(), resource_data(id));
+        }
+        assert!(table.get_resource(4).is_none());
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let output = Cursor::new(vec![]);
+        let mut drs = DRSWriter::new(output, InMemoryStrategy::default()).unwrap();
+        drs.add("txt", 1, &b"one"[..]).unwrap();
+        drs.add("bina", 1, &b"one"[..]).unwrap();
+        let err = drs.add("txt", 1, &b"again"[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        // Change the ID of the second resource in the first table from 2 to 1.
+        let mut bytes = write_archive(&[("txt", 1), ("txt", 2)], false, true);
+        assert_eq!(&bytes[88..92], &[2, 0, 0, 0]);
+        bytes[88] = 1;
+        let err = DRSReader::new(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    fn arbitrary_resources() -> impl Strategy<Value = Vec<(&'static str, u32)>> {
+        let table = prop_oneof![Just("slp"), Just("wav")];
+        prop::collection::hash_map(any::<u32>(), table, 0..64).prop_flat_map(|resources| {
+            Just(resources.into_iter().map(|(id, t)| (t, id)).collect::<Vec<_>>()).prop_shuffle()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn lookup_in_any_order(
+            resources in arbitrary_resources(),
+            sorted in any::<bool>(),
+            in_memory in any::<bool>(),
+        ) {
+            let bytes = write_archive(&resources, sorted, in_memory);
+            let mut input = Cursor::new(bytes);
+            let drs = DRSReader::new(&mut input).unwrap();
+
+            for &(t, id) in &resources {
+                let t = ResourceType::from(t);
+                prop_assert_eq!(drs.get_resource(t, id).map(|res| res.id), Some(id));
+                prop_assert_eq!(drs.get_resource_type(id), Some(t));
+                let content = drs.read_resource(&mut input, t, id).unwrap();
+                prop_assert_eq!(content.to_vec(), resource_data(id));
+            }
+
+            for table in drs.tables() {
+                let ids: Vec<_> = table.resources().map(|res| res.id).collect();
+                let mut expected: Vec<_> = resources
+                    .iter()
+                    .filter(|&&(t, _)| ResourceType::from(t) == table.resource_type)
+                    .map(|&(_, id)| id)
+                    .collect();
+                if sorted {
+                    expected.sort();
+                }
+                prop_assert_eq!(ids, expected);
+            }
+        }
+    }
 }
@@ -0,0 +1,292 @@
+use crate::{DRSHeader, DRSReader, DRSTable, DRSWriter, ResourceType, WriteStrategy};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, Write};
+use std::path::{Component, Path};
+
+/// File name of the manifest in an extracted archive directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Convert header bytes to a string that maps each byte to the character with the same code
+/// point, so the manifest can store arbitrary bytes losslessly.
+fn bytes_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+/// Convert a manifest string back into header bytes of the given length.
+fn string_to_bytes(name: &str, string: &str, output: &mut [u8]) -> Result<(), Error> {
+    let bytes = string
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid_data(format!("Manifest {} contains non-byte characters", name)))?;
+    if bytes.len() != output.len() {
+        return Err(invalid_data(format!(
+            "Manifest {} must be {} bytes long",
+            name,
+            output.len()
+        )));
+    }
+    output.copy_from_slice(&bytes);
+    Ok(())
+}
+
+/// Check that a resource file name from a manifest stays inside the manifest directory.
+fn check_resource_file(file: &str) -> Result<(), Error> {
+    let path = Path::new(file);
+    let inside = path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !inside {
+        return Err(invalid_data(format!(
+            "Manifest resource file '{}' must be a relative path inside the manifest directory",
+            file
+        )));
+    }
+    Ok(())
+}
+
+/// A resource in an extracted archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestResource {
+    /// The resource ID.
+    pub id: u32,
+    /// The file containing the resource data, relative to the manifest.
+    pub file: String,
+}
+
+/// A table in an extracted archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestTable {
+    /// The resource type, like "slp" or "bina".
+    pub resource_type: String,
+    /// The resources, in the order they are stored in the archive.
+    pub resources: Vec<ManifestResource>,
+}
+
+/// Describes everything that is needed to rebuild an extracted DRS archive: the header fields,
+/// and the order of the tables and resources.
+///
+/// Header fields are stored as strings where every character stands for the byte with the same
+/// value, so banners containing control characters or padding round-trip exactly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The copyright message.
+    pub banner_msg: String,
+    /// The file version.
+    pub version: String,
+    /// The file password / identifier.
+    pub password: String,
+    /// The tables, in the order they are stored in the archive.
+    pub tables: Vec<ManifestTable>,
+}
+
+impl Manifest {
+    /// Describe an archive with the given header and tables. Resources are stored as files named
+    /// `{id}.{type}`.
+    pub(crate) fn new<'a>(header: &DRSHeader, tables: impl Iterator<Item = &'a DRSTable>) -> Self {
+        let tables = tables
+            .map(|table| {
+                let ext = table.resource_ext();
+                ManifestTable {
+                    resource_type: ext.clone(),
+                    resources: table
+                        .resources()
+                        .map(|resource| ManifestResource {
+                            id: resource.id,
+                            file: format!("{}.{}", resource.id, ext),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        Self {
+            banner_msg: bytes_to_string(&header.banner_msg),
+            version: bytes_to_string(&header.version),
+            password: bytes_to_string(&header.password),
+            tables,
+        }
+    }
+
+    /// Read the manifest from an extracted archive directory.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(dir.as_ref().join(MANIFEST_FILE))?;
+        serde_json::from_reader(BufReader::new(file)).map_err(Error::from)
+    }
+
+    /// Write the manifest to an extracted archive directory.
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let mut file = File::create(dir.as_ref().join(MANIFEST_FILE))?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Get the archive header described by this manifest.
+    pub(crate) fn header(&self) -> Result<DRSHeader, Error> {
+        let mut header = DRSHeader::default();
+        string_to_bytes("banner_msg", &self.banner_msg, &mut header.banner_msg)?;
+        string_to_bytes("version", &self.version, &mut header.version)?;
+        string_to_bytes("password", &self.password, &mut header.password)?;
+        Ok(header)
+    }
+}
+
+impl DRSReader {
+    /// Describe this archive's header, tables, and resources.
+    pub fn manifest(&self) -> Manifest {
+        Manifest::new(self.header(), self.tables())
+    }
+
+    /// Extract all resources to a directory, and write a manifest that can be used to rebuild
+    /// the archive with `DRSWriter::from_manifest`.
+    pub fn extract<R: Read + Seek>(
+        &self,
+        handle: &mut R,
+        dir: impl AsRef<Path>,
+    ) -> Result<Manifest, Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let manifest = self.manifest();
+        for (table, manifest_table) in self.tables().zip(&manifest.tables) {
+            for (resource, manifest_resource) in table.resources().zip(&manifest_table.resources) {
+                let data = self.read_resource(handle, table.resource_type, resource.id)?;
+                fs::write(dir.join(&manifest_resource.file), data)?;
+            }
+        }
+        manifest.save(dir)?;
+        Ok(manifest)
+    }
+}
+
+impl<W, S> DRSWriter<W, S>
+where
+    W: Write + Seek,
+    S: WriteStrategy<W>,
+{
+    /// Create a writer containing the resources from an extracted archive directory, using the
+    /// header and the order of tables and resources from its manifest.
+    ///
+    /// When this is used with `InMemoryStrategy` or `TempFileStrategy`, the result is
+    /// byte-identical to the original archive if that archive stored its resources in directory
+    /// order without gaps, which is how the game's archives and the ones created by `DRSWriter`
+    /// are laid out. `DRSReader::is_packed` checks this.
+    ///
+    /// Resource files must be relative paths inside `dir`; absolute paths and `..` components
+    /// are rejected.
+    pub fn from_manifest(dir: impl AsRef<Path>, output: W, strategy: S) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let manifest = Manifest::load(dir)?;
+        let mut writer = Self::with_header(output, strategy, manifest.header()?)?;
+        for table in &manifest.tables {
+            let resource_type: ResourceType = table.resource_type.parse().map_err(|_| {
+                invalid_data(format!("Invalid resource type '{}'", table.resource_type))
+            })?;
+            for resource in &table.resources {
+                check_resource_file(&resource.file)?;
+                let file = File::open(dir.join(&resource.file))?;
+                writer.add(resource_type, resource.id, file)?;
+            }
+        }
+        Ok(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InMemoryStrategy, ReserveDirectoryStrategy};
+    use std::io::Cursor;
+
+    fn extract_test_drs() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = File::open("test.drs").unwrap();
+        let drs = DRSReader::new(&mut file).unwrap();
+        assert!(drs.is_packed());
+        let manifest = drs.extract(&mut file, dir.path()).unwrap();
+        assert_eq!(manifest.version, "1.00");
+        assert_eq!(manifest.tables.len(), 2);
+        assert_eq!(manifest.tables[0].resource_type, "js");
+        assert_eq!(manifest.tables[0].resources[2].file, "3.js");
+        assert_eq!(Manifest::load(dir.path()).unwrap(), manifest);
+        dir
+    }
+
+    #[test]
+    fn rebuild_identical() {
+        let dir = extract_test_drs();
+        let writer =
+            DRSWriter::from_manifest(dir.path(), Cursor::new(vec![]), InMemoryStrategy::default())
+                .unwrap();
+        let rebuilt = writer.flush().unwrap().into_inner();
+        assert_eq!(rebuilt, fs::read("test.drs").unwrap());
+
+        // Other strategies produce a different layout, but the same content.
+        let writer = DRSWriter::from_manifest(
+            dir.path(),
+            Cursor::new(vec![]),
+            ReserveDirectoryStrategy::new(2, 4),
+        )
+        .unwrap();
+        let mut rebuilt = writer.flush().unwrap();
+        rebuilt.set_position(0);
+        let drs = DRSReader::new(&mut rebuilt).unwrap();
+        assert_eq!(drs.manifest(), Manifest::load(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn reject_paths_outside_manifest_dir() {
+        let dir = extract_test_drs();
+        let original = Manifest::load(dir.path()).unwrap();
+        for file in &["../test.drs", "/etc/passwd", "a/../../test.drs", ""] {
+            let mut manifest = original.clone();
+            manifest.tables[0].resources[0].file = file.to_string();
+            manifest.save(dir.path()).unwrap();
+            let result = DRSWriter::from_manifest(
+                dir.path(),
+                Cursor::new(vec![]),
+                InMemoryStrategy::default(),
+            );
+            assert_eq!(
+                result.err().map(|err| err.kind()),
+                Some(ErrorKind::InvalidData),
+                "{}",
+                file
+            );
+        }
+
+        // Nested relative paths are fine.
+        fs::create_dir(dir.path().join("js")).unwrap();
+        fs::rename(dir.path().join("1.js"), dir.path().join("js/1.js")).unwrap();
+        let mut manifest = original;
+        manifest.tables[0].resources[0].file = "js/1.js".to_string();
+        manifest.save(dir.path()).unwrap();
+        DRSWriter::from_manifest(dir.path(), Cursor::new(vec![]), InMemoryStrategy::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut header = DRSHeader::default();
+        header.banner_msg[39] = 0xFF;
+        header.password = *b"swbg\x1a\x00\x00\x00\x00\x00\x00\x01";
+        let manifest = Manifest::new(&header, std::iter::empty());
+        let json = serde_json::to_string(&manifest).unwrap();
+        let parsed: Manifest = serde_json::from_str(&json).unwrap();
+        let parsed = parsed.header().unwrap();
+        assert_eq!(&parsed.banner_msg[..], &header.banner_msg[..]);
+        assert_eq!(parsed.password, header.password);
+
+        let mut bad = manifest;
+        bad.version = "1.0".to_string();
+        assert_eq!(bad.header().unwrap_err().kind(), ErrorKind::InvalidData);
+        bad.version = "1.0\u{100}".to_string();
+        assert_eq!(bad.header().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
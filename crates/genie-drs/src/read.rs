@@ -53,6 +53,36 @@ impl DRSReader {
         Ok(())
     }
 
+    /// Get the archive header.
+    pub fn header(&self) -> &DRSHeader {
+        self.header.as_ref().expect("header is read in the constructor")
+    }
+
+    /// Check if the resources are stored in directory order, directly after the directory and
+    /// without gaps. The game's archives and the ones created by `DRSWriter` are laid out like
+    /// this. Other archives can not be rebuilt byte-for-byte from a manifest.
+    pub fn is_packed(&self) -> bool {
+        let num_resources: u64 = self.tables.iter().map(|table| table.len() as u64).sum();
+        let mut table_offset = 64 + 12 * self.tables.len() as u64;
+        let mut data_offset = table_offset + 12 * num_resources;
+        if u64::from(self.header().directory_size) != data_offset {
+            return false;
+        }
+        for table in &self.tables {
+            if u64::from(table.offset) != table_offset {
+                return false;
+            }
+            table_offset += 12 * table.len() as u64;
+            for resource in table.resources() {
+                if u64::from(resource.offset) != data_offset {
+                    return false;
+                }
+                data_offset += u64::from(resource.size);
+            }
+        }
+        true
+    }
+
     /// Get the table for the given resource type.
     pub fn get_table(&self, resource_type: ResourceType) -> Option<&DRSTable> {
         self.tables
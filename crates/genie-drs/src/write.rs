@@ -1,5 +1,8 @@
 use crate::{DRSHeader, DRSResource, DRSTable, ResourceType};
 use byteorder::{WriteBytesExt, LE};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom, Write};
 
 /// Strategy to use when writing files to the archive.
@@ -12,7 +15,9 @@ use std::io::{self, Read, Seek, SeekFrom, Write};
 /// writing it.
 ///
 /// There are tricks to work around this and reduce memory usage. The `InMemory` strategy keeps the
-/// file in memory before writing it and is great for small archives. The other strateg(y|ies) start writing files without needing to keep them entirely in memory, with other tradeoffs.
+/// file in memory before writing it and is great for small archives. The other strategies start writing files without needing to keep them entirely in memory, with other tradeoffs:
+/// `ReserveDirectory` needs to know the number of files upfront, and `TempFile` needs disk space
+/// for a second copy of the resource data.
 pub trait Strategy<W>
 where
     W: Write + Seek,
@@ -33,7 +38,7 @@ where
 /// This works best for archives with small files.
 #[derive(Default)]
 pub struct InMemoryStrategy {
-    resources: Vec<(ResourceType, Vec<u8>)>,
+    resources: HashMap<(ResourceType, u32), Vec<u8>>,
 }
 
 impl<W> Strategy<W> for InMemoryStrategy
@@ -55,7 +60,7 @@ where
         data.read_to_end(&mut bytes)?;
         assert!(bytes.len() < u32::max_value() as usize, "file too large");
         resource.size = bytes.len() as u32;
-        self.resources.push((table, bytes));
+        self.resources.insert((table, resource.id), bytes);
         Ok(resource)
     }
 
@@ -88,16 +93,9 @@ where
         drs.write_tables()?;
 
         for table in &drs.tables {
-            let mut data = self.resources.iter().filter_map(|(t, bytes)| {
-                if table.resource_type == *t {
-                    Some(bytes)
-                } else {
-                    None
-                }
-            });
-            for _ in &table.resources {
-                let bytes = data.next().expect("genie-drs bug: mismatch between InMemoryStrategy resources and DRSWriter table data");
-                drs.output.write_all(&bytes)?;
+            for res in &table.resources {
+                let bytes = self.resources.get(&(table.resource_type, res.id)).expect("genie-drs bug: mismatch between InMemoryStrategy resources and DRSWriter table data");
+                drs.output.write_all(bytes)?;
             }
         }
 
@@ -193,6 +191,126 @@ impl Default for ReserveDirectoryStrategy {
     }
 }
 
+/// Stream resource data to a scratch file, and copy it into the archive when it is flushed.
+///
+/// Only the directory is kept in memory, so this works for archives of any size when the number
+/// of files is not known upfront. The output is identical to the output of `InMemoryStrategy`.
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use genie_drs::{DRSWriter, TempFileStrategy};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut writer = DRSWriter::new(Cursor::new(vec![]), TempFileStrategy::new()?)?;
+/// writer.add("slp", 2, &b"some bytes"[..])?;
+/// let buf = writer.flush()?;
+/// # Ok(()) }
+/// ```
+pub struct TempFileStrategy<T = File>
+where
+    T: Read + Write + Seek,
+{
+    scratch: T,
+    /// Offset and size of each resource in the scratch file.
+    resources: HashMap<(ResourceType, u32), (u64, u32)>,
+    scratch_len: u64,
+}
+
+impl TempFileStrategy<File> {
+    /// Create a write strategy using a temporary file, which is deleted when the strategy is
+    /// dropped.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self::with_scratch(tempfile::tempfile()?))
+    }
+}
+
+impl<T> TempFileStrategy<T>
+where
+    T: Read + Write + Seek,
+{
+    /// Create a write strategy that stores resource data in `scratch`, which must be empty.
+    pub fn with_scratch(scratch: T) -> Self {
+        Self {
+            scratch,
+            resources: HashMap::new(),
+            scratch_len: 0,
+        }
+    }
+}
+
+impl<W, T> Strategy<W> for TempFileStrategy<T>
+where
+    W: Write + Seek,
+    T: Read + Write + Seek,
+{
+    fn open(&mut self, _drs: &mut InnerDRSWriter<W>) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn add_resource<R: Read>(
+        &mut self,
+        _drs: &mut InnerDRSWriter<W>,
+        table: ResourceType,
+        mut resource: DRSResource,
+        mut data: R,
+    ) -> Result<DRSResource, io::Error> {
+        self.scratch.seek(SeekFrom::Start(self.scratch_len))?;
+        let len = io::copy(&mut data, &mut self.scratch)?;
+        if len >= u64::from(u32::MAX) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file too large"));
+        }
+        resource.size = len as u32;
+        self.resources
+            .insert((table, resource.id), (self.scratch_len, resource.size));
+        self.scratch_len += len;
+        Ok(resource)
+    }
+
+    fn close(mut self, drs: &mut InnerDRSWriter<W>) -> Result<(), io::Error> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "archive too large");
+
+        let num_tables = drs.tables.len() as u64;
+        let num_resources = drs.tables.iter().map(|t| t.len() as u64).sum::<u64>();
+        let directory_size = 64 + 12 * (num_tables + num_resources);
+        drs.header.directory_size = u32::try_from(directory_size).map_err(|_| too_large())?;
+        drs.write_header()?;
+
+        // Assign table and file offsets
+        let mut table_offset = 64 + 12 * num_tables;
+        let mut file_offset = directory_size;
+        for table in drs.tables.iter_mut() {
+            table.offset = table_offset as u32;
+            table_offset += 12 * table.len() as u64;
+
+            for res in table.resources.iter_mut() {
+                res.offset = u32::try_from(file_offset).map_err(|_| too_large())?;
+                file_offset += u64::from(res.size);
+            }
+        }
+        if file_offset > u64::from(u32::MAX) {
+            return Err(too_large());
+        }
+
+        drs.write_tables()?;
+
+        for table in &drs.tables {
+            for res in &table.resources {
+                let (offset, size) = self.resources[&(table.resource_type, res.id)];
+                self.scratch.seek(SeekFrom::Start(offset))?;
+                let mut data = (&mut self.scratch).take(u64::from(size));
+                let copied = io::copy(&mut data, &mut drs.output)?;
+                if copied != u64::from(size) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "scratch file is shorter than the data written to it",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub struct InnerDRSWriter<W>
 where
     W: Write + Seek,
@@ -251,6 +369,7 @@ where
 {
     inner: InnerDRSWriter<W>,
     strategy: S,
+    sorted: bool,
 }
 
 impl<W, S> DRSWriter<W, S>
@@ -260,8 +379,11 @@ where
 {
     /// Create a writer with the given strategy.
     pub fn new(output: W, strategy: S) -> io::Result<Self> {
-        let header = DRSHeader::default();
+        Self::with_header(output, strategy, DRSHeader::default())
+    }
 
+    /// Create a writer that uses the banner, version, and password from `header`.
+    pub(crate) fn with_header(output: W, strategy: S, header: DRSHeader) -> io::Result<Self> {
         let mut writer = Self {
             inner: InnerDRSWriter {
                 output,
@@ -269,6 +391,7 @@ where
                 tables: vec![],
             },
             strategy,
+            sorted: false,
         };
 
         writer.strategy.open(&mut writer.inner)?;
@@ -276,13 +399,33 @@ where
         Ok(writer)
     }
 
+    /// Sort the resources in each table by ID when writing the directory, like the original game
+    /// tools do. By default, resources are written in the order they were added.
+    pub fn set_sorted(&mut self, sorted: bool) {
+        self.sorted = sorted;
+    }
+
     /// Add a file to the archive.
+    ///
+    /// Returns an `AlreadyExists` error if the table already contains a file with this ID.
     #[inline]
     pub fn add(&mut self, t: impl Into<ResourceType>, id: u32, data: impl Read) -> io::Result<()> {
         self.add_inner(t.into(), id, data)
     }
 
     fn add_inner(&mut self, t: ResourceType, id: u32, data: impl Read) -> io::Result<()> {
+        let exists = self
+            .inner
+            .tables
+            .iter()
+            .any(|table| table.resource_type == t && table.get_resource(id).is_some());
+        if exists {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "A resource with this ID already exists in this table",
+            ));
+        }
+
         let res = DRSResource {
             id,
             offset: 0, // TBD
@@ -317,6 +460,11 @@ where
             "too many tables"
         );
         self.inner.header.num_resource_types = self.inner.tables.len() as u32;
+        if self.sorted {
+            for table in self.inner.tables.iter_mut() {
+                table.sort();
+            }
+        }
 
         self.strategy.close(&mut self.inner)?;
 
@@ -327,6 +475,8 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::DRSReader;
+    use proptest::prelude::{any, prop, prop_assert_eq, proptest, Strategy as _};
     use std::io::Cursor;
 
     /// A drs archive with a single text file containing the ASCII text "example test file".
@@ -349,6 +499,78 @@ mod test {
         assert_eq!(output, ONE_FILE.to_vec());
     }
 
+    #[test]
+    fn one_file_temp_file() {
+        let output = Cursor::new(vec![]);
+        let mut drs = DRSWriter::new(output, TempFileStrategy::new().unwrap()).unwrap();
+        drs.add("txt", 1, "example test file".as_bytes()).unwrap();
+        let output = drs.flush().unwrap().into_inner();
+        assert_eq!(output, ONE_FILE.to_vec());
+    }
+
+    type Resources = Vec<(&'static str, u32, Vec<u8>)>;
+
+    fn write_with<S>(strategy: S, resources: &Resources, sorted: bool) -> Vec<u8>
+    where
+        S: Strategy<Cursor<Vec<u8>>>,
+    {
+        let mut drs = DRSWriter::new(Cursor::new(vec![]), strategy).unwrap();
+        drs.set_sorted(sorted);
+        for (t, id, data) in resources {
+            drs.add(*t, *id, &data[..]).unwrap();
+        }
+        drs.flush().unwrap().into_inner()
+    }
+
+    /// Read all resources from an archive, in directory order.
+    fn read_all(archive: Vec<u8>) -> Vec<(String, u32, Vec<u8>)> {
+        let mut input = Cursor::new(archive);
+        let drs = DRSReader::new(&mut input).unwrap();
+        let mut resources = vec![];
+        for table in drs.tables() {
+            for res in table.resources() {
+                let data = drs
+                    .read_resource(&mut input, table.resource_type, res.id)
+                    .unwrap();
+                resources.push((table.resource_ext(), res.id, data.to_vec()));
+            }
+        }
+        resources
+    }
+
+    fn arbitrary_resources() -> impl proptest::strategy::Strategy<Value = Resources> {
+        let resource = (
+            prop::sample::select(vec!["slp", "wav", "bina"]),
+            0..50u32,
+            prop::collection::vec(any::<u8>(), 0..100),
+        );
+        prop::collection::vec(resource, 0..30).prop_map(|mut resources| {
+            // IDs must be unique within a table.
+            let mut seen = std::collections::HashSet::new();
+            resources.retain(|(t, id, _)| seen.insert((*t, *id)));
+            resources
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn temp_file_matches_other_strategies(
+            resources in arbitrary_resources(),
+            sorted in any::<bool>(),
+        ) {
+            let temp_file = write_with(TempFileStrategy::new().unwrap(), &resources, sorted);
+            let in_memory = write_with(InMemoryStrategy::default(), &resources, sorted);
+            prop_assert_eq!(&temp_file, &in_memory);
+
+            let scratch = TempFileStrategy::with_scratch(Cursor::new(vec![]));
+            prop_assert_eq!(&write_with(scratch, &resources, sorted), &in_memory);
+
+            let reserved = ReserveDirectoryStrategy::new(3, resources.len() as u32);
+            let reserved = write_with(reserved, &resources, sorted);
+            prop_assert_eq!(read_all(temp_file), read_all(reserved));
+        }
+    }
+
     #[test]
     fn one_file_memory() {
         let output = Cursor::new(vec![]);
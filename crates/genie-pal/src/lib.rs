@@ -0,0 +1,403 @@
+//! Palettes map the color indices used by sprites and other graphics to RGB colors.
+//!
+//! The Genie Engine stores palettes as JASC-PAL text files, for example as the `50500.bina`
+//! resource in `interfac.drs`. genie-pal reads and writes these files, and can convert palettes to
+//! and from flat RGB tables.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use genie_pal::{Color, Palette};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let text = b"JASC-PAL\r\n0100\r\n3\r\n0 0 0\r\n255 0 0\r\n0 0 255\r\n";
+//! let palette = Palette::from(&mut &text[..])?;
+//! assert_eq!(palette.get(1), Some(Color::new(255, 0, 0)));
+//! assert_eq!(palette.nearest(Color::new(20, 10, 200)), Some(2));
+//!
+//! let mut out = vec![];
+//! palette.write_to(&mut out)?;
+//! assert_eq!(&out[..], &text[..]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Features
+//!
+//! - `chariot_palette`: convert palettes read by the
+//!   [chariot_palette](https://github.com/ChariotEngine/Palette/) crate using `Palette::from`.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::iter::FromIterator;
+use std::ops::Range;
+
+/// The first line of a JASC-PAL file.
+const JASC_HEADER: &str = "JASC-PAL";
+/// The JASC-PAL version, stored on the second line.
+const JASC_VERSION: &str = "0100";
+
+/// The number of player colors for each player in the AoE2 palette.
+pub const PLAYER_COLORS: usize = 8;
+
+/// Get the range of palette indices containing the colors for a player in the AoE2 palette.
+///
+/// Player 1's colors start at index 16, player 2's at index 32, and so on. This matches the
+/// offsets that player color pixels in SLP frames are shifted by.
+pub fn player_color_range(player: u8) -> Range<usize> {
+    let start = 16 * usize::from(player);
+    start..start + PLAYER_COLORS
+}
+
+#[derive(Debug)]
+pub enum ReadPaletteError {
+    IoError(io::Error),
+    /// The file does not start with the JASC-PAL header.
+    InvalidHeader,
+    /// The file uses a JASC-PAL version other than 0100.
+    UnsupportedVersion(String),
+    /// The number of colors is not a number.
+    InvalidColorCount(String),
+    /// A line does not contain a valid color. Line numbers start at 1.
+    InvalidColor {
+        line: usize,
+    },
+    /// The file contains fewer colors than its header says.
+    MissingColors {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl From<io::Error> for ReadPaletteError {
+    fn from(err: io::Error) -> ReadPaletteError {
+        ReadPaletteError::IoError(err)
+    }
+}
+
+impl std::fmt::Display for ReadPaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReadPaletteError::IoError(err) => write!(f, "{}", err),
+            ReadPaletteError::InvalidHeader => write!(f, "not a JASC-PAL file"),
+            ReadPaletteError::UnsupportedVersion(version) => {
+                write!(f, "unsupported JASC-PAL version {:?}", version)
+            }
+            ReadPaletteError::InvalidColorCount(count) => {
+                write!(f, "invalid number of colors {:?}", count)
+            }
+            ReadPaletteError::InvalidColor { line } => write!(f, "invalid color on line {}", line),
+            ReadPaletteError::MissingColors { expected, found } => {
+                write!(f, "expected {} colors, but found only {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReadPaletteError {}
+
+/// An RGB color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    /// Create a color from its red, green, and blue components.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Get the squared Euclidean distance between two colors.
+    fn distance(self, other: Color) -> u32 {
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+        d(self.r, other.r) + d(self.g, other.g) + d(self.b, other.b)
+    }
+}
+
+impl From<[u8; 3]> for Color {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        Self::new(r, g, b)
+    }
+}
+
+impl From<Color> for [u8; 3] {
+    fn from(color: Color) -> Self {
+        [color.r, color.g, color.b]
+    }
+}
+
+/// A list of colors.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<Color>,
+}
+
+impl Palette {
+    /// Create an empty palette.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a palette from a JASC-PAL file.
+    pub fn from<R: Read>(input: &mut R) -> Result<Self, ReadPaletteError> {
+        let mut lines = BufReader::new(input).lines();
+        let mut next_line = || -> Result<Option<String>, ReadPaletteError> {
+            match lines.next() {
+                Some(line) => Ok(Some(line?.trim().to_string())),
+                None => Ok(None),
+            }
+        };
+
+        if next_line()?.as_deref() != Some(JASC_HEADER) {
+            return Err(ReadPaletteError::InvalidHeader);
+        }
+        let version = next_line()?.unwrap_or_default();
+        if version != JASC_VERSION {
+            return Err(ReadPaletteError::UnsupportedVersion(version));
+        }
+        let count = next_line()?.unwrap_or_default();
+        let count: usize = count
+            .parse()
+            .map_err(|_| ReadPaletteError::InvalidColorCount(count))?;
+
+        let mut colors = Vec::with_capacity(count.min(0x1_0000));
+        for found in 0..count {
+            let line = next_line()?.ok_or(ReadPaletteError::MissingColors {
+                expected: count,
+                found,
+            })?;
+            let invalid = ReadPaletteError::InvalidColor { line: found + 4 };
+            let mut components = line.split_whitespace().map(str::parse::<u8>);
+            match (
+                components.next(),
+                components.next(),
+                components.next(),
+                components.next(),
+            ) {
+                (Some(Ok(r)), Some(Ok(g)), Some(Ok(b)), None) => colors.push(Color::new(r, g, b)),
+                _ => return Err(invalid),
+            }
+        }
+
+        Ok(Self { colors })
+    }
+
+    /// Write the palette as a JASC-PAL file, using the Windows line endings of the game's files.
+    pub fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        write!(
+            output,
+            "{}\r\n{}\r\n{}\r\n",
+            JASC_HEADER,
+            JASC_VERSION,
+            self.len()
+        )?;
+        for color in &self.colors {
+            write!(output, "{} {} {}\r\n", color.r, color.g, color.b)?;
+        }
+        Ok(())
+    }
+
+    /// Create a palette from a flat table of RGB bytes. Returns None if the length of the table
+    /// is not a multiple of 3.
+    pub fn from_rgb(rgb: &[u8]) -> Option<Self> {
+        if !rgb.len().is_multiple_of(3) {
+            return None;
+        }
+        Some(
+            rgb.chunks_exact(3)
+                .map(|c| Color::new(c[0], c[1], c[2]))
+                .collect(),
+        )
+    }
+
+    /// Convert the palette to a flat table of RGB bytes.
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|color| <[u8; 3]>::from(*color))
+            .collect()
+    }
+
+    /// Get the number of colors.
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Get all colors in this palette.
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Get the color at an index.
+    pub fn get(&self, index: usize) -> Option<Color> {
+        self.colors.get(index).copied()
+    }
+
+    /// Set the color at an index. Returns false if the index is outside the palette.
+    pub fn set(&mut self, index: usize, color: Color) -> bool {
+        match self.colors.get_mut(index) {
+            Some(target) => {
+                *target = color;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add a color to the end of the palette.
+    pub fn push(&mut self, color: Color) {
+        self.colors.push(color);
+    }
+
+    /// Get the colors for a player in the AoE2 palette. See `player_color_range`.
+    pub fn player_colors(&self, player: u8) -> Option<&[Color]> {
+        self.colors.get(player_color_range(player))
+    }
+
+    /// Find the index of the color that is closest to `color`. If several colors are equally
+    /// close, the lowest index is returned. Returns None if the palette is empty.
+    pub fn nearest(&self, color: Color) -> Option<usize> {
+        self.nearest_in(0..self.len(), color)
+    }
+
+    /// Find the index of the color in `range` that is closest to `color`. This can be used to
+    /// skip special colors, such as the player colors. Returns None if the range does not contain
+    /// any colors.
+    pub fn nearest_in(&self, range: Range<usize>, color: Color) -> Option<usize> {
+        let end = range.end.min(self.len());
+        let start = range.start.min(end);
+        self.colors[start..end]
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, other)| color.distance(**other))
+            .map(|(index, _)| start + index)
+    }
+}
+
+impl FromIterator<Color> for Palette {
+    fn from_iter<I: IntoIterator<Item = Color>>(iter: I) -> Self {
+        Self {
+            colors: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(feature = "chariot_palette")]
+impl From<chariot_palette::Palette> for Palette {
+    fn from(palette: chariot_palette::Palette) -> Self {
+        palette
+            .into_iter()
+            .map(|color| Color::new(color.r, color.g, color.b))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> Palette {
+        (0..=255).map(|i| Color::new(i, 0, 255 - i)).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let palette = gradient();
+        let mut out = vec![];
+        palette.write_to(&mut out).unwrap();
+        assert!(out.starts_with(b"JASC-PAL\r\n0100\r\n256\r\n0 0 255\r\n1 0 254\r\n"));
+        assert_eq!(Palette::from(&mut &out[..]).unwrap(), palette);
+    }
+
+    #[test]
+    fn read_unix_line_endings() {
+        let text = b"JASC-PAL\n0100\n2\n1 2 3\n 4  5 6 \n\n";
+        let palette = Palette::from(&mut &text[..]).unwrap();
+        assert_eq!(
+            palette.colors(),
+            &[Color::new(1, 2, 3), Color::new(4, 5, 6)]
+        );
+    }
+
+    #[test]
+    fn invalid_files() {
+        let read = |text: &[u8]| Palette::from(&mut &text[..]).unwrap_err();
+        assert!(matches!(read(b""), ReadPaletteError::InvalidHeader));
+        assert!(matches!(read(b"RIFF"), ReadPaletteError::InvalidHeader));
+        assert!(matches!(
+            read(b"JASC-PAL\r\n0200\r\n"),
+            ReadPaletteError::UnsupportedVersion(_)
+        ));
+        assert!(matches!(
+            read(b"JASC-PAL\r\n0100\r\nmany\r\n"),
+            ReadPaletteError::InvalidColorCount(_)
+        ));
+        assert!(matches!(
+            read(b"JASC-PAL\r\n0100\r\n2\r\n1 2 3\r\n1 2 256\r\n"),
+            ReadPaletteError::InvalidColor { line: 5 }
+        ));
+        assert!(matches!(
+            read(b"JASC-PAL\r\n0100\r\n2\r\n1 2 3 4\r\n"),
+            ReadPaletteError::InvalidColor { line: 4 }
+        ));
+        assert!(matches!(
+            read(b"JASC-PAL\r\n0100\r\n3\r\n1 2 3\r\n"),
+            ReadPaletteError::MissingColors {
+                expected: 3,
+                found: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn rgb_tables() {
+        let palette = gradient();
+        let rgb = palette.to_rgb();
+        assert_eq!(rgb.len(), 768);
+        assert_eq!(&rgb[3..6], &[1, 0, 254]);
+        assert_eq!(Palette::from_rgb(&rgb), Some(palette));
+        assert_eq!(Palette::from_rgb(&rgb[1..]), None);
+    }
+
+    #[test]
+    fn nearest_color() {
+        let mut palette = gradient();
+        assert_eq!(palette.nearest(Color::new(100, 0, 155)), Some(100));
+        assert_eq!(palette.nearest(Color::new(100, 90, 150)), Some(102));
+        // Ties go to the lowest index.
+        palette.set(200, Color::new(100, 0, 155));
+        assert_eq!(palette.nearest(Color::new(100, 0, 155)), Some(100));
+        assert_eq!(
+            palette.nearest_in(101..256, Color::new(100, 0, 155)),
+            Some(200)
+        );
+        assert_eq!(palette.nearest_in(300..400, Color::new(0, 0, 0)), None);
+        assert_eq!(Palette::new().nearest(Color::new(0, 0, 0)), None);
+    }
+
+    #[test]
+    fn player_colors() {
+        let palette = gradient();
+        assert_eq!(player_color_range(1), 16..24);
+        assert_eq!(player_color_range(8), 128..136);
+        assert_eq!(palette.player_colors(2).unwrap()[0], Color::new(32, 0, 223));
+        assert!(palette.player_colors(16).is_none());
+    }
+
+    #[cfg(feature = "chariot_palette")]
+    #[test]
+    fn from_chariot_palette() {
+        let mut out = vec![];
+        gradient().write_to(&mut out).unwrap();
+        // chariot_palette only accepts Unix line endings.
+        let text = String::from_utf8(out).unwrap().replace("\r\n", "\n");
+        let chariot = chariot_palette::read_from(&mut std::io::Cursor::new(text)).unwrap();
+        let palette: Palette = chariot.into();
+        assert_eq!(palette, gradient());
+    }
+}
@@ -0,0 +1,109 @@
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use genie_scx::{Map, Tile};
+use std::io::{Cursor, Read};
+
+/// Size of the largest maps in the Definitive Edition.
+const SIZE: u32 = 480;
+
+/// Encode a map of the given size, in the format used by scenario files.
+fn encoded_map(size: u32) -> Vec<u8> {
+    let mut map = Map::new(size, size);
+    for (i, tile) in map.tiles_mut().enumerate() {
+        tile.terrain = (i % 41) as i8;
+        tile.elevation = (i % 7) as i8;
+    }
+    let mut bytes = vec![];
+    map.write_to(&mut bytes).unwrap();
+    bytes
+}
+
+/// Decode a map into one allocation per row, the way `Map` used to store tiles.
+fn read_nested<R: Read>(input: &mut R) -> Vec<Vec<Tile>> {
+    let width = input.read_u32::<LE>().unwrap();
+    let height = input.read_u32::<LE>().unwrap();
+    let mut tiles = Vec::with_capacity(height as usize);
+    for _ in 0..height {
+        let mut row = Vec::with_capacity(width as usize);
+        for _ in 0..width {
+            row.push(Tile {
+                terrain: input.read_i8().unwrap(),
+                elevation: input.read_i8().unwrap(),
+                zone: input.read_i8().unwrap(),
+            });
+        }
+        tiles.push(row);
+    }
+    tiles
+}
+
+fn decode(c: &mut Criterion) {
+    let bytes = encoded_map(SIZE);
+    let mut group = c.benchmark_group("decode 480x480");
+    group.bench_function("nested rows", |b| {
+        b.iter(|| read_nested(&mut Cursor::new(black_box(&bytes))))
+    });
+    group.bench_function("flat buffer", |b| {
+        b.iter(|| Map::from(&mut Cursor::new(black_box(&bytes))).unwrap())
+    });
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let bytes = encoded_map(SIZE);
+    let nested = read_nested(&mut Cursor::new(&bytes));
+    let map = Map::from(&mut Cursor::new(&bytes)).unwrap();
+    let mut group = c.benchmark_group("encode 480x480");
+    group.bench_function("nested rows", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(bytes.len());
+            out.write_u32::<LE>(SIZE).unwrap();
+            out.write_u32::<LE>(SIZE).unwrap();
+            for row in black_box(&nested) {
+                for tile in row {
+                    out.write_i8(tile.terrain).unwrap();
+                    out.write_i8(tile.elevation).unwrap();
+                    out.write_i8(tile.zone).unwrap();
+                }
+            }
+            out
+        })
+    });
+    group.bench_function("flat buffer", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(bytes.len());
+            black_box(&map).write_to(&mut out).unwrap();
+            out
+        })
+    });
+    group.finish();
+}
+
+fn terrain_histogram(c: &mut Criterion) {
+    let bytes = encoded_map(SIZE);
+    let nested = read_nested(&mut Cursor::new(&bytes));
+    let map = Map::from(&mut Cursor::new(&bytes)).unwrap();
+    let mut group = c.benchmark_group("terrain histogram 480x480");
+    group.bench_function("nested rows", |b| {
+        b.iter(|| {
+            let mut counts = [0u32; 256];
+            for tile in black_box(&nested).iter().flatten() {
+                counts[tile.terrain as u8 as usize] += 1;
+            }
+            counts
+        })
+    });
+    group.bench_function("flat buffer", |b| {
+        b.iter(|| {
+            let mut counts = [0u32; 256];
+            for terrain in black_box(&map).terrains() {
+                counts[terrain as u8 as usize] += 1;
+            }
+            counts
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode, encode, terrain_histogram);
+criterion_main!(benches);
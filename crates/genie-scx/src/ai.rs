@@ -1,10 +1,22 @@
 use crate::{util::*, Result};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
-use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
+use encoding_rs::WINDOWS_1252;
 use std::io::{Read, Write};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+/// Could not parse an AI error code because given number is an unknown error code.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseAIErrorCodeError(i32);
+
+impl std::fmt::Display for ParseAIErrorCodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid AI error code {} (must be 0-26)", self.0)
+    }
+}
+
+impl std::error::Error for ParseAIErrorCodeError {}
+
+/// The kind of error that the game encountered while compiling an AI script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AIErrorCode {
     ConstantAlreadyDefined = 0,
     FileOpenFailed = 1,
@@ -35,6 +47,55 @@ pub enum AIErrorCode {
     UnexpectedEOF = 26,
 }
 
+impl AIErrorCode {
+    pub fn try_from(n: i32) -> std::result::Result<Self, ParseAIErrorCodeError> {
+        use AIErrorCode::*;
+        match n {
+            0 => Ok(ConstantAlreadyDefined),
+            1 => Ok(FileOpenFailed),
+            2 => Ok(FileReadFailed),
+            3 => Ok(InvalidIdentifier),
+            4 => Ok(InvalidKeyword),
+            5 => Ok(InvalidPreprocessorDirective),
+            6 => Ok(ListFull),
+            7 => Ok(MissingArrow),
+            8 => Ok(MissingClosingParenthesis),
+            9 => Ok(MissingClosingQuote),
+            10 => Ok(MissingEndIf),
+            11 => Ok(MissingFileName),
+            12 => Ok(MissingIdentifier),
+            13 => Ok(MissingKeyword),
+            14 => Ok(MissingLHS),
+            15 => Ok(MissingOpeningParenthesis),
+            16 => Ok(MissingPreprocessorSymbol),
+            17 => Ok(MissingRHS),
+            18 => Ok(NoRules),
+            19 => Ok(PreprocessorNestingTooDeep),
+            20 => Ok(RuleTooLong),
+            21 => Ok(StringTableFull),
+            22 => Ok(UndocumentedError),
+            23 => Ok(UnexpectedElse),
+            24 => Ok(UnexpectedEndIf),
+            25 => Ok(UnexpectedError),
+            26 => Ok(UnexpectedEOF),
+            n => Err(ParseAIErrorCodeError(n)),
+        }
+    }
+}
+
+impl From<AIErrorCode> for i32 {
+    fn from(code: AIErrorCode) -> i32 {
+        code as i32
+    }
+}
+
+/// Size of the file name buffer in an AI error block.
+const ERROR_FILENAME_SIZE: usize = 257;
+/// Size of the description buffer in an AI error block.
+const ERROR_DESCRIPTION_SIZE: usize = 128;
+
+/// Describes an error that the game encountered while compiling one of the AI scripts in a
+/// scenario. The game stores the last error in the scenario file.
 #[derive(Debug, Clone)]
 pub struct AIErrorInfo {
     filename: String,
@@ -43,29 +104,30 @@ pub struct AIErrorInfo {
     error_code: AIErrorCode,
 }
 
-fn parse_bytes(bytes: &[u8]) -> std::result::Result<String, ReadStringError> {
-    let mut bytes = bytes.to_vec();
-    if let Some(end) = bytes.iter().position(|&byte| byte == 0) {
-        bytes.truncate(end);
-    }
-    if bytes.is_empty() {
-        Ok("<empty>".to_string())
-    } else {
-        String::from_utf8(bytes).map_err(|_| ReadStringError::DecodeStringError(DecodeStringError))
+/// Read a fixed-size, null-terminated string.
+fn read_fixed_str<R: Read>(input: &mut R, size: usize) -> Result<String> {
+    Ok(read_str(input, size)?.unwrap_or_default())
+}
+
+/// Write a string into a fixed-size, null-padded buffer, truncating it if necessary.
+fn write_fixed_str<W: Write>(output: &mut W, string: &str, size: usize) -> Result<()> {
+    let (bytes, _enc, failed) = WINDOWS_1252.encode(string);
+    if failed {
+        return Err(WriteStringError::EncodeStringError(EncodeStringError).into());
     }
+    let mut buffer = vec![0; size];
+    let len = bytes.len().min(size - 1);
+    buffer[..len].copy_from_slice(&bytes[..len]);
+    output.write_all(&buffer)?;
+    Ok(())
 }
 
 impl AIErrorInfo {
     pub fn from<R: Read>(input: &mut R) -> Result<Self> {
-        let mut filename_bytes = [0; 257];
-        input.read_exact(&mut filename_bytes)?;
+        let filename = read_fixed_str(input, ERROR_FILENAME_SIZE)?;
         let line_number = input.read_i32::<LE>()?;
-        let mut description_bytes = [0; 128];
-        input.read_exact(&mut description_bytes)?;
-        let error_code = AIErrorCode::from_u32(input.read_u32::<LE>()?).unwrap();
-
-        let filename = parse_bytes(&filename_bytes)?;
-        let description = parse_bytes(&description_bytes)?;
+        let description = read_fixed_str(input, ERROR_DESCRIPTION_SIZE)?;
+        let error_code = AIErrorCode::try_from(input.read_i32::<LE>()?)?;
 
         Ok(AIErrorInfo {
             filename,
@@ -74,8 +136,47 @@ impl AIErrorInfo {
             error_code,
         })
     }
+
+    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<()> {
+        write_fixed_str(output, &self.filename, ERROR_FILENAME_SIZE)?;
+        output.write_i32::<LE>(self.line_number)?;
+        write_fixed_str(output, &self.description, ERROR_DESCRIPTION_SIZE)?;
+        output.write_i32::<LE>(self.error_code.into())?;
+        Ok(())
+    }
+
+    /// The name of the AI file that contains the error.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The line number of the error.
+    pub fn line_number(&self) -> i32 {
+        self.line_number
+    }
+
+    /// The error message.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The kind of error.
+    pub fn error_code(&self) -> AIErrorCode {
+        self.error_code
+    }
 }
 
+impl std::fmt::Display for AIErrorInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} ({:?})",
+            self.filename, self.line_number, self.description, self.error_code
+        )
+    }
+}
+
+/// An AI script embedded in a scenario, either a `.per` or an `.ai` file.
 #[derive(Debug, Clone)]
 pub struct AIFile {
     filename: String,
@@ -83,16 +184,59 @@ pub struct AIFile {
 }
 
 impl AIFile {
+    /// Create an AI file with the given file name and script source.
+    pub fn new(filename: &str, content: &str) -> Self {
+        Self {
+            filename: filename.to_string(),
+            content: content.to_string(),
+        }
+    }
+
     pub fn from<R: Read>(input: &mut R) -> Result<Self> {
         let len = input.read_i32::<LE>()? as usize;
-        let filename = read_str(input, len)?.expect("missing ai file name");
+        let filename = read_str(input, len)?.ok_or(crate::Error::MissingFileNameError)?;
         let len = input.read_i32::<LE>()? as usize;
-        let content = read_str(input, len)?.expect("empty ai file?");
+        let content = read_str(input, len)?.unwrap_or_default();
 
         Ok(Self { filename, content })
     }
+
+    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<()> {
+        write_i32_str(output, &self.filename)?;
+        write_i32_str(output, &self.content)?;
+        Ok(())
+    }
+
+    /// The file name of this AI, including the extension.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The name of this AI as shown in the player AI selection, i.e. the file name without the
+    /// `.per` or `.ai` extension.
+    pub fn ai_name(&self) -> &str {
+        let lower = self.filename.to_ascii_lowercase();
+        if lower.ends_with(".per") {
+            &self.filename[..self.filename.len() - 4]
+        } else if lower.ends_with(".ai") {
+            &self.filename[..self.filename.len() - 3]
+        } else {
+            &self.filename
+        }
+    }
+
+    /// The script source of this AI.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Replace the script source of this AI.
+    pub fn set_content(&mut self, content: &str) {
+        self.content = content.to_string();
+    }
 }
 
+/// AI scripts embedded in a scenario, and the last error that occurred while compiling them.
 #[derive(Debug, Default, Clone)]
 pub struct AIInfo {
     error: Option<AIErrorInfo>,
@@ -124,8 +268,77 @@ impl AIInfo {
     }
 
     pub fn write_to<W: Write>(&self, output: &mut W) -> Result<()> {
-        output.write_u32::<LE>(0)?;
-        output.write_u32::<LE>(0)?;
+        output.write_u32::<LE>(if self.files.is_empty() { 0 } else { 1 })?;
+        output.write_u32::<LE>(if self.error.is_some() { 1 } else { 0 })?;
+
+        if self.error.is_none() && self.files.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(ref error) = self.error {
+            error.write_to(output)?;
+        }
+
+        output.write_u32::<LE>(self.files.len() as u32)?;
+        for file in &self.files {
+            file.write_to(output)?;
+        }
+
         Ok(())
     }
+
+    /// Get the last error that occurred while compiling the embedded AI scripts.
+    pub fn error(&self) -> Option<&AIErrorInfo> {
+        self.error.as_ref()
+    }
+
+    /// Remove the stored AI error.
+    pub fn clear_error(&mut self) {
+        self.error = None;
+    }
+
+    /// Iterate over the embedded AI files.
+    pub fn files(&self) -> impl Iterator<Item = &AIFile> {
+        self.files.iter()
+    }
+
+    /// Get an embedded AI file by its file name. File names are compared case-insensitively.
+    pub fn file(&self, filename: &str) -> Option<&AIFile> {
+        self.files
+            .iter()
+            .find(|file| file.filename.eq_ignore_ascii_case(filename))
+    }
+
+    /// Get a mutable reference to an embedded AI file by its file name.
+    pub fn file_mut(&mut self, filename: &str) -> Option<&mut AIFile> {
+        self.files
+            .iter_mut()
+            .find(|file| file.filename.eq_ignore_ascii_case(filename))
+    }
+
+    /// Add an AI file. If a file with the same name already exists, it is replaced, and the old
+    /// file is returned.
+    pub fn insert_file(&mut self, file: AIFile) -> Option<AIFile> {
+        match self.file_mut(&file.filename) {
+            Some(existing) => Some(std::mem::replace(existing, file)),
+            None => {
+                self.files.push(file);
+                None
+            }
+        }
+    }
+
+    /// Remove an AI file by its file name, returning it if it existed.
+    pub fn remove_file(&mut self, filename: &str) -> Option<AIFile> {
+        let index = self
+            .files
+            .iter()
+            .position(|file| file.filename.eq_ignore_ascii_case(filename))?;
+        Some(self.files.remove(index))
+    }
+
+    /// Returns true if there are no embedded AI files and no AI error.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.error.is_none()
+    }
 }
@@ -0,0 +1,378 @@
+//! Map statistics for reviewing the balance of multiplayer scenarios.
+//!
+//! `analyze` computes metrics for each active player, like the distance from their start location
+//! to the nearest resources and the amount of resources around them, and a score that indicates
+//! how similar the players' positions are.
+//!
+//! With the `serde` feature enabled, the reports can be serialized, for example to JSON.
+
+use crate::pathfinding::{Movement, Passability, ResourceKind, TerrainPassability};
+use crate::{Scenario, ScenarioObject};
+
+/// Object type IDs of town centers.
+const TOWN_CENTERS: &[i16] = &[71, 109, 141, 142];
+/// Options for the map analysis.
+#[derive(Debug, Clone)]
+pub struct AnalysisOptions {
+    /// The width of each ring around a player's start location, in tiles.
+    pub ring_width: f32,
+    /// The number of rings around a player's start location to count resources in.
+    pub rings: usize,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        Self {
+            ring_width: 10.0,
+            rings: 4,
+        }
+    }
+}
+
+/// Where a player's start location was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum StartSource {
+    /// The position of the player's first town center.
+    TownCenter,
+    /// The player's start location in the scenario player data.
+    PlayerData,
+}
+
+/// Number of resource objects of each kind.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResourceCounts {
+    pub gold: usize,
+    pub stone: usize,
+    pub wood: usize,
+    pub food: usize,
+    pub relics: usize,
+}
+
+impl ResourceCounts {
+    fn add(&mut self, kind: ResourceKind) {
+        match kind {
+            ResourceKind::Gold => self.gold += 1,
+            ResourceKind::Stone => self.stone += 1,
+            ResourceKind::Wood => self.wood += 1,
+            ResourceKind::Food => self.food += 1,
+            ResourceKind::Relic => self.relics += 1,
+        }
+    }
+
+    fn to_vec(&self) -> Vec<f32> {
+        vec![
+            self.gold as f32,
+            self.stone as f32,
+            self.wood as f32,
+            self.food as f32,
+            self.relics as f32,
+        ]
+    }
+}
+
+/// Distance to the nearest resource object of each kind, in tiles. None if the map does not
+/// contain any objects of that kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResourceDistances {
+    pub gold: Option<f32>,
+    pub stone: Option<f32>,
+    pub wood: Option<f32>,
+    pub food: Option<f32>,
+    pub relic: Option<f32>,
+}
+
+impl ResourceDistances {
+    fn update(&mut self, kind: ResourceKind, distance: f32) {
+        let slot = match kind {
+            ResourceKind::Gold => &mut self.gold,
+            ResourceKind::Stone => &mut self.stone,
+            ResourceKind::Wood => &mut self.wood,
+            ResourceKind::Food => &mut self.food,
+            ResourceKind::Relic => &mut self.relic,
+        };
+        match slot {
+            Some(nearest) if *nearest <= distance => {}
+            _ => *slot = Some(distance),
+        }
+    }
+
+    fn to_vec(&self, missing: f32) -> Vec<f32> {
+        vec![
+            self.gold.unwrap_or(missing),
+            self.stone.unwrap_or(missing),
+            self.wood.unwrap_or(missing),
+            self.food.unwrap_or(missing),
+            self.relic.unwrap_or(missing),
+        ]
+    }
+}
+
+/// Resources in a ring around a player's start location.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Ring {
+    /// Inner radius of the ring, in tiles.
+    pub inner: f32,
+    /// Outer radius of the ring, in tiles.
+    pub outer: f32,
+    /// Resource objects in the ring.
+    pub resources: ResourceCounts,
+}
+
+/// Metrics for a single player.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PlayerReport {
+    /// The player number, starting at 1.
+    pub player: usize,
+    /// The start location of the player, in tiles.
+    pub start: (f32, f32),
+    /// Where the start location was taken from.
+    pub start_source: StartSource,
+    /// Distances to the nearest resources.
+    pub nearest: ResourceDistances,
+    /// Resources in rings around the start location.
+    pub rings: Vec<Ring>,
+    /// Number of tiles that land units can reach from the start location.
+    pub reachable_tiles: usize,
+    /// Share of the walkable tiles on the map that can be reached from the start location.
+    pub reachable_ratio: f32,
+}
+
+/// The result of analyzing a scenario map.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MapReport {
+    pub width: u32,
+    pub height: u32,
+    pub water_tiles: usize,
+    pub land_tiles: usize,
+    /// Share of the map that is water.
+    pub water_ratio: f32,
+    /// Resource objects on the whole map.
+    pub resources: ResourceCounts,
+    /// Metrics for each active player that has a start location.
+    pub players: Vec<PlayerReport>,
+    /// How similar the players' metrics are, from 0.0 (very different) to 1.0 (identical).
+    pub symmetry: f32,
+}
+
+/// Compute map statistics for a scenario, using the default options.
+pub fn analyze(scenario: &Scenario) -> MapReport {
+    analyze_with(scenario, &AnalysisOptions::default())
+}
+
+/// Compute map statistics for a scenario.
+pub fn analyze_with(scenario: &Scenario, options: &AnalysisOptions) -> MapReport {
+    let map = scenario.map();
+    let resources: Vec<_> = scenario
+        .objects()
+        .filter_map(|object| {
+            ResourceKind::of_object_type(object.object_type).map(|kind| (kind, object))
+        })
+        .collect();
+
+    let mut total = ResourceCounts::default();
+    for (kind, _) in &resources {
+        total.add(*kind);
+    }
+
+    let passability = TerrainPassability::for_version(scenario.version());
+    let grid = scenario.path_grid();
+    let walkable_count = (0..map.height())
+        .flat_map(|y| (0..map.width()).map(move |x| (x, y)))
+        .filter(|&(x, y)| grid.is_passable(x, y, Movement::Land))
+        .count();
+    let water_tiles = map
+        .terrains()
+        .filter(|t| passability.class(*t) == Passability::Water)
+        .count();
+    let tile_count = map.as_tiles().len();
+
+    let mut players = vec![];
+    for player in 0..scenario.format.scenario_players.len() {
+        let (start, start_source) = match start_location(scenario, player) {
+            Some(start) => start,
+            None => continue,
+        };
+
+        let mut nearest = ResourceDistances::default();
+        let mut rings: Vec<_> = (0..options.rings)
+            .map(|i| Ring {
+                inner: i as f32 * options.ring_width,
+                outer: (i + 1) as f32 * options.ring_width,
+                resources: ResourceCounts::default(),
+            })
+            .collect();
+        for (kind, object) in &resources {
+            let distance = distance(start, object);
+            nearest.update(*kind, distance);
+            let ring = (distance / options.ring_width) as usize;
+            if let Some(ring) = rings.get_mut(ring) {
+                ring.resources.add(*kind);
+            }
+        }
+
+        let reachable_tiles = grid
+            .reachable_from((start.0 as u32, start.1 as u32), Movement::Land)
+            .into_iter()
+            .filter(|r| *r)
+            .count();
+        players.push(PlayerReport {
+            player: player + 1,
+            start,
+            start_source,
+            nearest,
+            rings,
+            reachable_tiles,
+            reachable_ratio: ratio(reachable_tiles, walkable_count),
+        });
+    }
+
+    let diagonal = (map.width() as f32).hypot(map.height() as f32);
+    let symmetry = symmetry_score(&players, diagonal);
+
+    MapReport {
+        width: map.width(),
+        height: map.height(),
+        water_tiles,
+        land_tiles: tile_count - water_tiles,
+        water_ratio: ratio(water_tiles, tile_count),
+        resources: total,
+        players,
+        symmetry,
+    }
+}
+
+fn ratio(part: usize, total: usize) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f32 / total as f32
+    }
+}
+
+fn distance(from: (f32, f32), object: &ScenarioObject) -> f32 {
+    let (x, y, _) = object.position;
+    (x - from.0).hypot(y - from.1)
+}
+
+/// Find the start location of a player: their first town center, or else the location stored
+/// in the scenario player data.
+///
+/// Returns None for inactive players.
+pub(crate) fn start_location(
+    scenario: &Scenario,
+    player: usize,
+) -> Option<((f32, f32), StartSource)> {
+    let format = &scenario.format;
+    let properties = format.tribe_scen.base.player_base_properties.get(player);
+    let active = matches!(properties, Some(properties) if properties.active != 0);
+    if !active {
+        return None;
+    }
+
+    let town_center = format.player_objects.get(player + 1).and_then(|objects| {
+        objects
+            .iter()
+            .find(|object| TOWN_CENTERS.contains(&object.object_type))
+    });
+    if let Some(town_center) = town_center {
+        let (x, y, _) = town_center.position;
+        return Some(((x, y), StartSource::TownCenter));
+    }
+
+    let (x, y) = format.scenario_players[player].location;
+    if x < 0 || y < 0 {
+        return None;
+    }
+    Some(((f32::from(x), f32::from(y)), StartSource::PlayerData))
+}
+
+/// Compare the players' metrics. For each metric, the spread between players is the difference
+/// between the largest and smallest value, relative to the largest value. The score is 1.0 minus
+/// the average spread.
+fn symmetry_score(players: &[PlayerReport], diagonal: f32) -> f32 {
+    if players.len() < 2 {
+        return 1.0;
+    }
+
+    let features: Vec<Vec<f32>> = players
+        .iter()
+        .map(|player| {
+            let mut features = player.nearest.to_vec(diagonal);
+            for ring in &player.rings {
+                features.extend(ring.resources.to_vec());
+            }
+            features.push(player.reachable_ratio);
+            features
+        })
+        .collect();
+
+    let num_features = features[0].len();
+    let mut total_spread = 0.0;
+    for feature in 0..num_features {
+        let values: Vec<f32> = features.iter().map(|values| values[feature]).collect();
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        let min = values.iter().cloned().fold(f32::MAX, f32::min);
+        if max > 0.0 {
+            total_spread += (max - min) / max;
+        }
+    }
+
+    1.0 - total_spread / num_features as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn analyze_real_world_map() {
+        let mut f = File::open("test/scenarios/real_world_amazon.scx").unwrap();
+        let scenario = Scenario::from(&mut f).expect("failed to read");
+        let report = analyze(&scenario);
+
+        assert_eq!(
+            report.land_tiles + report.water_tiles,
+            (report.width * report.height) as usize
+        );
+        assert!(report.water_ratio > 0.0 && report.water_ratio < 1.0);
+        assert!(!report.players.is_empty());
+        for player in &report.players {
+            assert_eq!(player.rings.len(), 4);
+            assert!(player.reachable_tiles > 0);
+            assert!(player.reachable_ratio <= 1.0);
+        }
+        assert!(report.symmetry >= 0.0 && report.symmetry <= 1.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_report() {
+        let mut f = File::open("test/scenarios/real_world_amazon.scx").unwrap();
+        let scenario = Scenario::from(&mut f).expect("failed to read");
+        let json = serde_json::to_string(&analyze(&scenario)).unwrap();
+        assert!(json.contains("\"symmetry\""));
+        assert!(json.contains("\"town_center\""));
+    }
+
+    #[test]
+    fn players_on_real_world_map() {
+        let mut f = File::open("test/scenarios/real_world_amazon.scx").unwrap();
+        let scenario = Scenario::from(&mut f).expect("failed to read");
+        let start = |player| {
+            let ((x, y), source) = start_location(&scenario, player).unwrap();
+            assert_eq!(source, StartSource::TownCenter);
+            (x as u32, y as u32)
+        };
+        assert_eq!(start(0), (55, 41));
+        assert_eq!(start(1), (205, 137));
+        assert_eq!(start(7), (205, 72));
+    }
+}
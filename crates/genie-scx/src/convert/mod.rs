@@ -26,6 +26,41 @@ impl std::fmt::Display for ConvertError {
 
 impl std::error::Error for ConvertError {}
 
+/// A scenario conversion, so different conversions can be applied by generic code, like when
+/// converting all scenarios in a campaign.
+pub trait Converter {
+    /// Convert a scenario in place.
+    fn convert(&self, scen: &mut Scenario) -> Result<(), ConvertError>;
+}
+
+/// A conversion that leaves scenarios unchanged, for when only the file version should change.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoConversion;
+
+impl Converter for NoConversion {
+    fn convert(&self, _scen: &mut Scenario) -> Result<(), ConvertError> {
+        Ok(())
+    }
+}
+
+impl Converter for AoCToWK {
+    fn convert(&self, scen: &mut Scenario) -> Result<(), ConvertError> {
+        AoCToWK::convert(self, scen)
+    }
+}
+
+impl Converter for HDToWK {
+    fn convert(&self, scen: &mut Scenario) -> Result<(), ConvertError> {
+        HDToWK::convert(self, scen)
+    }
+}
+
+impl Converter for AutoToWK {
+    fn convert(&self, scen: &mut Scenario) -> Result<(), ConvertError> {
+        AutoToWK::convert(self, scen)
+    }
+}
+
 /// Convert an AoC or HD Edition scenario file to a WololoKingdoms one.
 ///
 /// It will auto-detect the version of the file, and output a WK compatible scenario.
@@ -3,11 +3,21 @@
 #![allow(clippy::cyclomatic_complexity)]
 
 use crate::{
-    ai::AIInfo, bitmap::Bitmap, header::SCXHeader, map::Map, player::*, triggers::TriggerSystem,
-    types::*, util::*, victory::*, Error, Result, VersionBundle,
+    ai::AIInfo,
+    bitmap::Bitmap,
+    header::SCXHeader,
+    map::Map,
+    player::*,
+    triggers::{EffectType, Trigger, TriggerEffect, TriggerSystem},
+    types::*,
+    userpatch::{check_triggers, CompatibilityIssue},
+    util::*,
+    victory::*,
+    Error, Result, VersionBundle,
 };
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::io::{Read, Write};
 
@@ -21,7 +31,7 @@ macro_rules! cmp_float {
     };
 }
 
-fn cmp_scx_version(a: SCXVersion, b: SCXVersion) -> Ordering {
+pub(crate) fn cmp_scx_version(a: SCXVersion, b: SCXVersion) -> Ordering {
     match a[0].cmp(&b[0]) {
         Ordering::Equal => {}
         ord => return ord,
@@ -33,6 +43,20 @@ fn cmp_scx_version(a: SCXVersion, b: SCXVersion) -> Ordering {
     a[3].cmp(&b[3])
 }
 
+/// Get the part of a disabled IDs list that is actually in use.
+fn used_disables(list: &[i32], num: i32) -> &[i32] {
+    let num = (num.max(0) as usize).min(list.len());
+    &list[0..num]
+}
+
+/// Check that a player index refers to one of the 16 players that scenario data is stored for.
+fn check_player_index(player: usize) -> Result<()> {
+    if player >= 16 {
+        return Err(Error::InvalidPlayerError(player));
+    }
+    Ok(())
+}
+
 // pub enum LostInformation {
 //     DisabledTechs(i32, i32),
 //     DisabledUnits(i32, i32),
@@ -60,6 +84,21 @@ pub struct ScenarioObject {
 }
 
 impl ScenarioObject {
+    /// Create an object of the given type at the given (x, y) position.
+    ///
+    /// The object's ID is -1 until it is added to a scenario.
+    pub fn new(object_type: i16, position: (f32, f32)) -> Self {
+        Self {
+            position: (position.0, position.1, 0.0),
+            id: -1,
+            object_type,
+            state: 2,
+            angle: 0.0,
+            frame: 0,
+            garrisoned_in: None,
+        }
+    }
+
     pub fn from<R: Read>(input: &mut R, version: SCXVersion) -> Result<Self> {
         let position = (
             input.read_f32::<LE>()?,
@@ -130,7 +169,7 @@ pub(crate) struct RGEScen {
     player_names: Vec<Option<String>>,
     /// Name IDs for each player.
     player_string_table: Vec<i32>,
-    player_base_properties: Vec<PlayerBaseProperties>,
+    pub(crate) player_base_properties: Vec<PlayerBaseProperties>,
     victory_conquest: bool,
     /// File name of this scenario.
     pub(crate) name: String,
@@ -477,6 +516,32 @@ impl RGEScen {
 
         Ok(())
     }
+
+    pub fn player_ai_name(&self, player: usize) -> Option<&str> {
+        self.player_ai_rules.get(player)?.as_deref()
+    }
+
+    pub fn player_ai_source(&self, player: usize) -> Option<&str> {
+        self.player_files.get(player)?.ai_rules.as_deref()
+    }
+
+    pub fn player_ai_type(&self, player: usize) -> Option<i8> {
+        self.ai_rules_types.get(player).copied()
+    }
+
+    pub fn set_player_ai(
+        &mut self,
+        player: usize,
+        name: Option<&str>,
+        source: Option<&str>,
+        rules_type: i8,
+    ) -> Result<()> {
+        check_player_index(player)?;
+        self.player_ai_rules[player] = name.map(ToString::to_string);
+        self.player_files[player].ai_rules = source.map(ToString::to_string);
+        self.ai_rules_types[player] = rules_type;
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -579,7 +644,7 @@ impl TribeScen {
         let mp_victory_type = if version >= 1.13 {
             input.read_i32::<LE>()?
         } else {
-            4
+            VictoryMode::Custom.into()
         };
         let victory_score = if version >= 1.13 {
             input.read_i32::<LE>()?
@@ -758,7 +823,17 @@ impl TribeScen {
         })
     }
 
-    pub fn write_to<W: Write>(&self, output: &mut W, version: f32) -> Result<()> {
+    /// Write scenario data.
+    ///
+    /// If `excess_disables_emulated` is true, disabled technologies, units, and buildings that do
+    /// not fit in this version are left out. Otherwise, an error is returned if there are too
+    /// many.
+    pub fn write_to<W: Write>(
+        &self,
+        output: &mut W,
+        version: f32,
+        excess_disables_emulated: bool,
+    ) -> Result<()> {
         self.base.write_to(output, version)?;
 
         if version <= 1.13 {
@@ -836,18 +911,15 @@ impl TribeScen {
             output.write_i32::<LE>(if self.teams_locked { 1 } else { 0 })?;
         }
 
-        let max_disabled_buildings = if version >= 1.25 { 30 } else { 20 };
-        if version >= 1.18 {
-            let most = *self.num_disabled_buildings.iter().max().unwrap_or(&0);
-            if most > max_disabled_buildings {
-                return Err(Error::TooManyDisabledBuildingsError(
-                    most,
-                    max_disabled_buildings,
-                ));
-            }
+        let limits = DisabledLimits::from_data_version(version);
+        if !excess_disables_emulated {
+            self.check_disabled_limits(limits)?;
+        }
+        let clamp = |num: i32, limit: usize| num.min(limit as i32);
 
+        if version >= 1.18 {
             for num in &self.num_disabled_techs {
-                output.write_i32::<LE>(*num)?;
+                output.write_i32::<LE>(clamp(*num, limits.techs))?;
             }
             for player_disabled_techs in &self.disabled_techs {
                 for i in 0..30 {
@@ -856,7 +928,7 @@ impl TribeScen {
             }
 
             for num in &self.num_disabled_units {
-                output.write_i32::<LE>(*num)?;
+                output.write_i32::<LE>(clamp(*num, limits.units))?;
             }
             for player_disabled_units in &self.disabled_units {
                 for i in 0..30 {
@@ -865,25 +937,14 @@ impl TribeScen {
             }
 
             for num in &self.num_disabled_buildings {
-                output.write_i32::<LE>(*num)?;
+                output.write_i32::<LE>(clamp(*num, limits.buildings))?;
             }
             for player_disabled_buildings in &self.disabled_buildings {
-                for i in 0..max_disabled_buildings as usize {
+                for i in 0..limits.buildings {
                     output.write_i32::<LE>(*player_disabled_buildings.get(i).unwrap_or(&-1))?;
                 }
             }
         } else if version > 1.03 {
-            let most = *self.num_disabled_techs.iter().max().unwrap_or(&0);
-            if most > 20 {
-                return Err(Error::TooManyDisabledTechsError(most));
-            }
-            if self.num_disabled_units.iter().any(|&n| n > 0) {
-                return Err(Error::CannotDisableUnitsError);
-            }
-            if self.num_disabled_buildings.iter().any(|&n| n > 0) {
-                return Err(Error::CannotDisableBuildingsError);
-            }
-
             // Old scenarios only allowed disabling up to 20 techs per player.
             for player_disabled_techs in &self.disabled_techs {
                 for i in 0..20 {
@@ -892,15 +953,6 @@ impl TribeScen {
             }
         } else {
             // <= 1.03 did not support disabling anything
-            if self.num_disabled_techs.iter().any(|&n| n > 0) {
-                return Err(Error::CannotDisableTechsError);
-            }
-            if self.num_disabled_units.iter().any(|&n| n > 0) {
-                return Err(Error::CannotDisableUnitsError);
-            }
-            if self.num_disabled_buildings.iter().any(|&n| n > 0) {
-                return Err(Error::CannotDisableBuildingsError);
-            }
         }
 
         if version > 1.04 {
@@ -940,6 +992,162 @@ impl TribeScen {
         Ok(())
     }
 
+    /// Check that the disabled technologies, units, and buildings fit within the given limits.
+    fn check_disabled_limits(&self, limits: DisabledLimits) -> Result<()> {
+        let most_techs = *self.num_disabled_techs.iter().max().unwrap_or(&0);
+        if most_techs > limits.techs as i32 {
+            return Err(if limits.techs == 0 {
+                Error::CannotDisableTechsError
+            } else {
+                Error::TooManyDisabledTechsError(most_techs, limits.techs as i32)
+            });
+        }
+        let most_units = *self.num_disabled_units.iter().max().unwrap_or(&0);
+        if most_units > limits.units as i32 {
+            return Err(if limits.units == 0 {
+                Error::CannotDisableUnitsError
+            } else {
+                Error::TooManyDisabledUnitsError(most_units, limits.units as i32)
+            });
+        }
+        let most_buildings = *self.num_disabled_buildings.iter().max().unwrap_or(&0);
+        if most_buildings > limits.buildings as i32 {
+            return Err(if limits.buildings == 0 {
+                Error::CannotDisableBuildingsError
+            } else {
+                Error::TooManyDisabledBuildingsError(most_buildings, limits.buildings as i32)
+            });
+        }
+        Ok(())
+    }
+
+    /// Create a trigger that disables the technologies, units, and buildings that do not fit
+    /// within the given limits at the start of the game.
+    ///
+    /// The trigger uses the UserPatch Enable/Disable Object and Enable/Disable Technology effects,
+    /// so it only works in versions that support them.
+    ///
+    /// Returns None if everything fits.
+    pub fn excess_disables_trigger(&self, limits: DisabledLimits) -> Option<Trigger> {
+        let mut trigger = Trigger::new("Disable Objects");
+        let mut has_effects = false;
+        for player in 0..16 {
+            let source_player_id = player as i32 + 1;
+            for tech in self.disabled_techs(player).iter().skip(limits.techs) {
+                let mut effect = TriggerEffect::new(EffectType::EnableDisableTechnology);
+                effect.set_amount(0);
+                effect.set_technology_id(*tech);
+                effect.set_source_player_id(source_player_id);
+                trigger.push_effect(effect);
+                has_effects = true;
+            }
+            let units = self.disabled_units(player).iter().skip(limits.units);
+            let buildings = self
+                .disabled_buildings(player)
+                .iter()
+                .skip(limits.buildings);
+            for object_type in units.chain(buildings) {
+                let mut effect = TriggerEffect::new(EffectType::EnableDisableObject);
+                effect.set_amount(0);
+                effect.set_unit_type(*object_type);
+                effect.set_source_player_id(source_player_id);
+                trigger.push_effect(effect);
+                has_effects = true;
+            }
+        }
+
+        if has_effects {
+            Some(trigger)
+        } else {
+            None
+        }
+    }
+
+    pub fn global_victory(&self) -> GlobalVictory {
+        GlobalVictory {
+            mode: self.mp_victory_type.into(),
+            score: self.victory_score,
+            time_limit: self.victory_time,
+            custom: CustomVictory {
+                conquest: self.victory.conquest != 0,
+                relics: self.victory.artifacts,
+                explored: self.victory.exploration,
+                ruins: self.victory.ruins,
+                discoveries: self.victory.discoveries,
+                gold: self.victory.gold,
+                all_required: self.victory_all_flag,
+            },
+        }
+    }
+
+    pub fn set_global_victory(&mut self, victory: GlobalVictory) {
+        self.mp_victory_type = victory.mode.into();
+        self.victory_score = victory.score;
+        self.victory_time = victory.time_limit;
+        self.victory.conquest = if victory.custom.conquest { 1 } else { 0 };
+        self.victory.artifacts = victory.custom.relics;
+        self.victory.exploration = victory.custom.explored;
+        self.victory.ruins = victory.custom.ruins;
+        self.victory.discoveries = victory.custom.discoveries;
+        self.victory.gold = victory.custom.gold;
+        self.victory_all_flag = victory.custom.all_required;
+    }
+
+    pub fn legacy_victory_info(&self, player: usize) -> &[LegacyVictoryInfo] {
+        &self.legacy_victory_info[player]
+    }
+
+    pub fn disabled_techs(&self, player: usize) -> &[i32] {
+        match (
+            self.disabled_techs.get(player),
+            self.num_disabled_techs.get(player),
+        ) {
+            (Some(list), Some(num)) => used_disables(list, *num),
+            _ => &[],
+        }
+    }
+
+    pub fn set_disabled_techs(&mut self, player: usize, techs: &[i32]) -> Result<()> {
+        check_player_index(player)?;
+        self.num_disabled_techs[player] = techs.len() as i32;
+        self.disabled_techs[player] = techs.to_vec();
+        Ok(())
+    }
+
+    pub fn disabled_units(&self, player: usize) -> &[i32] {
+        match (
+            self.disabled_units.get(player),
+            self.num_disabled_units.get(player),
+        ) {
+            (Some(list), Some(num)) => used_disables(list, *num),
+            _ => &[],
+        }
+    }
+
+    pub fn set_disabled_units(&mut self, player: usize, units: &[i32]) -> Result<()> {
+        check_player_index(player)?;
+        self.num_disabled_units[player] = units.len() as i32;
+        self.disabled_units[player] = units.to_vec();
+        Ok(())
+    }
+
+    pub fn disabled_buildings(&self, player: usize) -> &[i32] {
+        match (
+            self.disabled_buildings.get(player),
+            self.num_disabled_buildings.get(player),
+        ) {
+            (Some(list), Some(num)) => used_disables(list, *num),
+            _ => &[],
+        }
+    }
+
+    pub fn set_disabled_buildings(&mut self, player: usize, buildings: &[i32]) -> Result<()> {
+        check_player_index(player)?;
+        self.num_disabled_buildings[player] = buildings.len() as i32;
+        self.disabled_buildings[player] = buildings.to_vec();
+        Ok(())
+    }
+
     pub fn version(&self) -> f32 {
         self.base.version
     }
@@ -948,6 +1156,21 @@ impl TribeScen {
         // Convert String to &str: https://stackoverflow.com/a/31234028
         self.base.description.as_ref().map(|s| &**s)
     }
+
+    /// Get the description, hints, victory and loss messages, history, and scouting text.
+    pub(crate) fn messages(&self) -> impl Iterator<Item = &str> {
+        let base = &self.base;
+        vec![
+            &base.description,
+            &base.hints,
+            &base.win_message,
+            &base.loss_message,
+            &base.history,
+            &base.scout,
+        ]
+        .into_iter()
+        .filter_map(|message| message.as_deref())
+    }
 }
 
 #[derive(Debug)]
@@ -967,20 +1190,31 @@ pub struct SCXFormat {
     /// Objects data.
     pub(crate) player_objects: Vec<Vec<ScenarioObject>>,
     /// Player data.
-    scenario_players: Vec<ScenarioPlayerData>,
+    pub(crate) scenario_players: Vec<ScenarioPlayerData>,
     /// Triggers (only in AoK and up).
     pub(crate) triggers: Option<TriggerSystem>,
     /// AI information (AoK and up).
-    ai_info: Option<AIInfo>,
+    pub(crate) ai_info: Option<AIInfo>,
 }
 
 impl SCXFormat {
     /// Extract version bundle information from a parsed SCX file.
     pub fn version(&self) -> VersionBundle {
+        let data = self.tribe_scen.version();
+        let uses_userpatch_effects = data <= 1.22
+            && self
+                .triggers
+                .as_ref()
+                .is_some_and(|triggers| !check_triggers(triggers).is_empty());
         VersionBundle {
             format: self.version,
             header: self.header.version,
-            data: self.tribe_scen.version(),
+            data,
+            userpatch: if uses_userpatch_effects {
+                Some(1.5)
+            } else {
+                None
+            },
             ..VersionBundle::aoc()
         }
     }
@@ -1090,7 +1324,17 @@ impl SCXFormat {
         let mut output = DeflateEncoder::new(output, Compression::default());
         output.write_i32::<LE>(self.next_object_id)?;
 
-        self.tribe_scen.write_to(&mut output, version.data)?;
+        // Disables that do not fit in the requested version are emulated using a trigger. The
+        // effects that can disable objects are only available in UserPatch.
+        let excess_disables_trigger = if version.has_triggers() && version.has_userpatch_effects() {
+            self.tribe_scen
+                .excess_disables_trigger(version.disabled_limits())
+        } else {
+            None
+        };
+
+        self.tribe_scen
+            .write_to(&mut output, version.data, excess_disables_trigger.is_some())?;
         self.map.write_to(&mut output)?;
 
         output.write_i32::<LE>(self.player_objects.len() as i32)?;
@@ -1112,10 +1356,20 @@ impl SCXFormat {
 
         if cmp_scx_version(version.format, *b"1.13") == Ordering::Greater {
             let def = TriggerSystem::default();
-            let triggers = match self.triggers {
-                Some(ref tr) => tr,
-                None => &def,
+            let mut triggers = match self.triggers {
+                Some(ref tr) => Cow::Borrowed(tr),
+                None => Cow::Borrowed(&def),
             };
+            if let Some(trigger) = excess_disables_trigger {
+                triggers.to_mut().push(trigger);
+            }
+            if !version.has_userpatch_effects() {
+                if let Some(CompatibilityIssue::UserPatchEffect { effect_type, .. }) =
+                    check_triggers(&triggers).into_iter().next()
+                {
+                    return Err(Error::UserPatchEffectError(effect_type));
+                }
+            }
             triggers.write_to(&mut output, version.triggers)?;
         }
 
@@ -1139,7 +1393,12 @@ impl SCXFormat {
 #[cfg(test)]
 mod tests {
     use super::SCXFormat;
-    use crate::VersionBundle;
+    use crate::userpatch::{check_map, check_triggers};
+    use crate::{
+        AIErrorCode, AIFile, AIInfo, CompatibilityIssue, ConditionType, CustomVictory, EffectType,
+        Error, GlobalVictory, ObjectAttribute, Scenario, Trigger, TriggerEffect, VersionBundle,
+        VictoryMode,
+    };
     use std::fs::File;
 
     /// Source: http://aoe.heavengames.com/dl-php/showfile.php?fileid=42
@@ -1222,6 +1481,240 @@ mod tests {
             .expect("failed to write");
     }
 
+    #[test]
+    fn aoe1_victory_to_triggers() {
+        let mut f = File::open("test/scenarios/A New Emporer.scn").unwrap();
+        let format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+
+        let trigger = format.scenario_players[1]
+            .victory
+            .to_trigger(2)
+            .expect("should convert Destroy Player condition");
+        let conditions: Vec<_> = trigger.conditions().collect();
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(
+            conditions[0].condition_type(),
+            i32::from(ConditionType::PlayerDefeated)
+        );
+        assert_eq!(conditions[0].player_id(), 4);
+        let effects: Vec<_> = trigger.effects().collect();
+        assert_eq!(effects[0].source_player_id(), 2);
+
+        let trigger = format.scenario_players[2]
+            .victory
+            .to_trigger(3)
+            .expect("should convert Attribute condition");
+        let condition = trigger.conditions().next().unwrap();
+        assert_eq!(
+            condition.condition_type(),
+            i32::from(ConditionType::AccumulateAttribute)
+        );
+        assert_eq!(condition.resource(), 2);
+        assert_eq!(condition.amount(), 1000);
+    }
+
+    #[test]
+    fn convert_aoe1_victory() {
+        let mut f = File::open("test/scenarios/A New Emporer.scn").unwrap();
+        let mut scenario = Scenario::from(&mut f).expect("failed to read");
+        let num_entries = scenario.player_victory(1).unwrap().entries().len();
+        assert!(num_entries > 0);
+
+        // The scenario's own version can not store triggers, so nothing is converted.
+        let version = scenario.version().clone();
+        assert!(matches!(
+            scenario.convert_legacy_victory(&version),
+            Err(Error::CannotStoreTriggersError)
+        ));
+        assert_eq!(
+            scenario.player_victory(1).unwrap().entries().len(),
+            num_entries
+        );
+        assert!(scenario.triggers().is_none());
+
+        let converted = scenario
+            .convert_legacy_victory(&VersionBundle::aoc())
+            .expect("failed to convert");
+        assert!(converted > 0);
+        assert!(scenario.player_victory(1).unwrap().entries().is_empty());
+        assert_eq!(scenario.triggers().unwrap().len(), converted);
+    }
+
+    #[test]
+    fn global_victory_reserialize() {
+        let mut f = File::open("test/scenarios/Age of Heroes b1-3-5.scx").unwrap();
+        let mut format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        let victory = GlobalVictory {
+            mode: VictoryMode::Custom,
+            score: 5000,
+            time_limit: 3000,
+            custom: CustomVictory {
+                conquest: false,
+                relics: 5,
+                explored: 80,
+                all_required: true,
+                ..Default::default()
+            },
+        };
+        format.tribe_scen.set_global_victory(victory.clone());
+
+        let mut out = vec![];
+        format
+            .write_to(&mut out, &format.version())
+            .expect("failed to write");
+        let mut f = std::io::Cursor::new(out);
+        let format2 = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        assert_eq!(format2.tribe_scen.global_victory(), victory);
+
+        // Victory modes that are not known are kept as is.
+        format.tribe_scen.mp_victory_type = 7;
+        assert_eq!(
+            format.tribe_scen.global_victory().mode,
+            VictoryMode::Other(7)
+        );
+        let mut out = vec![];
+        format
+            .write_to(&mut out, &format.version())
+            .expect("failed to write");
+        let mut f = std::io::Cursor::new(out);
+        let format2 = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        assert_eq!(format2.tribe_scen.mp_victory_type, 7);
+    }
+
+    #[test]
+    fn embedded_ai_files_reserialize() {
+        let mut f = File::open("test/scenarios/Age of Heroes b1-3-5.scx").unwrap();
+        let mut format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+
+        let mut error = vec![];
+        error.extend_from_slice(b"Test.per");
+        error.resize(257, 0);
+        error.extend_from_slice(&12i32.to_le_bytes());
+        error.extend_from_slice(b"Missing closing parenthesis");
+        error.resize(257 + 4 + 128, 0);
+        error.extend_from_slice(&8i32.to_le_bytes());
+        let mut bytes = vec![0, 0, 0, 0, 1, 0, 0, 0];
+        bytes.extend_from_slice(&error);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        let mut ai_info = AIInfo::from(&mut std::io::Cursor::new(bytes))
+            .expect("failed to read")
+            .unwrap();
+        ai_info.insert_file(AIFile::new(
+            "Test.per",
+            "(defrule (true) => (disable-self))",
+        ));
+        ai_info.insert_file(AIFile::new("Other.ai", ""));
+        assert!(ai_info
+            .insert_file(AIFile::new("test.PER", "(defrule (true) => (resign))"))
+            .is_some());
+        format.ai_info = Some(ai_info);
+
+        let mut out = vec![];
+        format
+            .write_to(&mut out, &format.version())
+            .expect("failed to write");
+        let mut f = std::io::Cursor::new(out);
+        let format2 = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        let mut ai_info = format2.ai_info.expect("missing ai info");
+
+        let error = ai_info.error().expect("missing ai error");
+        assert_eq!(error.filename(), "Test.per");
+        assert_eq!(error.line_number(), 12);
+        assert_eq!(error.description(), "Missing closing parenthesis");
+        assert_eq!(error.error_code(), AIErrorCode::MissingClosingParenthesis);
+
+        assert_eq!(ai_info.files().count(), 2);
+        let file = ai_info.file("TEST.per").expect("missing ai file");
+        assert_eq!(file.filename(), "test.PER");
+        assert_eq!(file.ai_name(), "test");
+        assert_eq!(file.content(), "(defrule (true) => (resign))");
+        assert!(ai_info.remove_file("Other.ai").is_some());
+        assert!(ai_info.file("Other.ai").is_none());
+    }
+
+    #[test]
+    fn player_ai_out_of_range() {
+        let mut f = File::open("test/scenarios/Age of Heroes b1-3-5.scx").unwrap();
+        let mut format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        let base = &mut format.tribe_scen.base;
+        base.set_player_ai(15, Some("Promisory"), None, 1).unwrap();
+        assert_eq!(base.player_ai_name(15), Some("Promisory"));
+        assert_eq!(base.player_ai_type(15), Some(1));
+
+        assert!(matches!(
+            base.set_player_ai(16, Some("Promisory"), None, 1),
+            Err(Error::InvalidPlayerError(16))
+        ));
+        assert_eq!(base.player_ai_name(16), None);
+        assert_eq!(base.player_ai_source(16), None);
+        assert_eq!(base.player_ai_type(16), None);
+    }
+
+    #[test]
+    fn userpatch_effects_reserialize() {
+        let mut f = File::open("test/scenarios/Age of Heroes b1-3-5.scx").unwrap();
+        let mut format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        // This scenario already uses some UserPatch effects.
+        assert_eq!(format.version(), VersionBundle::userpatch_15());
+        let mut out = vec![];
+        assert!(matches!(
+            format.write_to(&mut out, &VersionBundle::aoc()),
+            Err(Error::UserPatchEffectError(_))
+        ));
+        // HD Edition does not support UserPatch effects either.
+        let mut out = vec![];
+        assert!(matches!(
+            format.write_to(&mut out, &VersionBundle::hd_edition()),
+            Err(Error::UserPatchEffectError(_))
+        ));
+
+        let mut trigger = Trigger::new("UserPatch");
+        let mut effect = TriggerEffect::change_object_attribute(ObjectAttribute::MaxRange, 12);
+        effect.set_objects(vec![1, 2]);
+        trigger.push_effect(effect);
+        trigger.push_effect(TriggerEffect::new(EffectType::ChangeObjectHP));
+        format.triggers.as_mut().unwrap().push(trigger);
+        format.map.tile_mut(3, 4).unwrap().terrain = 60;
+
+        let mut out = vec![];
+        format
+            .write_to(&mut out, &VersionBundle::userpatch_15())
+            .expect("failed to write");
+        let mut f = std::io::Cursor::new(out);
+        let format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+
+        let triggers = format.triggers.as_ref().unwrap();
+        let trigger = triggers.triggers().last().unwrap();
+        let effect = trigger.effects().next().unwrap();
+        assert_eq!(effect.object_attribute(), Some(ObjectAttribute::MaxRange));
+        assert_eq!(effect.amount(), 12);
+        assert_eq!(effect.objects(), &[1, 2]);
+
+        let issues = check_triggers(triggers);
+        // Only UserPatch effects are flagged, and not vanilla ones like Change Object HP.
+        assert!(issues.iter().all(|issue| matches!(
+            issue,
+            CompatibilityIssue::UserPatchEffect { effect_type, .. }
+                if EffectType::try_from(*effect_type).unwrap().is_userpatch()
+        )));
+        assert_eq!(
+            issues.last(),
+            Some(&CompatibilityIssue::UserPatchEffect {
+                trigger: triggers.len() - 1,
+                effect: 0,
+                effect_type: EffectType::ChangeObjectAttribute.into(),
+            })
+        );
+        assert_eq!(
+            check_map(&format.map),
+            vec![CompatibilityIssue::ExtendedTerrain {
+                x: 3,
+                y: 4,
+                terrain: 60
+            }]
+        );
+    }
+
     /// Source: http://aoe.heavengames.com/dl-php/showfile.php?fileid=880
     #[test]
     fn aoe1_ror_scx() {
@@ -1254,6 +1747,95 @@ mod tests {
             .expect("failed to write");
     }
 
+    #[test]
+    fn aoc_scx_triggers_reserialize() {
+        let mut f = File::open("test/scenarios/Age of Heroes b1-3-5.scx").unwrap();
+        let format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        assert!(!format.triggers.as_ref().unwrap().is_empty());
+        let mut out = vec![];
+        format
+            .write_to(&mut out, &format.version())
+            .expect("failed to write");
+
+        let mut f = std::io::Cursor::new(out);
+        let format2 = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        assert_eq!(
+            format!("{:#?}", format.triggers),
+            format!("{:#?}", format2.triggers),
+            "should produce exactly the same triggers"
+        );
+    }
+
+    #[test]
+    fn emulate_excess_disabled_buildings() {
+        let mut f = File::open("test/scenarios/Age of Heroes b1-3-5.scx").unwrap();
+        let mut format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        let num_triggers = format.triggers.as_ref().unwrap().len();
+        let buildings: Vec<i32> = (100..125).collect();
+        format
+            .tribe_scen
+            .set_disabled_buildings(2, &buildings)
+            .unwrap();
+
+        // Vanilla AoC can not emulate the excess disables.
+        let mut out = vec![];
+        let result = format.write_to(&mut out, &VersionBundle::aoc());
+        assert!(matches!(
+            result,
+            Err(Error::TooManyDisabledBuildingsError(25, 20))
+        ));
+
+        // Neither can HD Edition, even though it stores more disables natively.
+        let buildings: Vec<i32> = (100..135).collect();
+        format
+            .tribe_scen
+            .set_disabled_buildings(2, &buildings)
+            .unwrap();
+        let mut out = vec![];
+        let result = format.write_to(&mut out, &VersionBundle::hd_edition());
+        assert!(matches!(
+            result,
+            Err(Error::TooManyDisabledBuildingsError(35, 30))
+        ));
+        let buildings: Vec<i32> = (100..125).collect();
+        format
+            .tribe_scen
+            .set_disabled_buildings(2, &buildings)
+            .unwrap();
+
+        let mut out = vec![];
+        format
+            .write_to(&mut out, &VersionBundle::userpatch_15())
+            .expect("failed to write");
+
+        let mut f = std::io::Cursor::new(out);
+        let format2 = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        assert_eq!(format2.tribe_scen.disabled_buildings(2), &buildings[0..20]);
+        let triggers = format2.triggers.as_ref().unwrap();
+        assert_eq!(triggers.len(), num_triggers + 1);
+        let trigger = triggers.triggers().last().unwrap();
+        let effects: Vec<_> = trigger.effects().collect();
+        assert_eq!(effects.len(), 5);
+        assert_eq!(effects[0].unit_type(), 120);
+        assert_eq!(effects[0].source_player_id(), 3);
+    }
+
+    #[test]
+    fn cannot_emulate_disables_without_triggers() {
+        let mut f = File::open("test/scenarios/Jeremiah Johnson (Update).scx").unwrap();
+        let mut format = SCXFormat::load_scenario(&mut f).expect("failed to read");
+        format.tribe_scen.set_disabled_units(0, &[4]).unwrap();
+        assert!(matches!(
+            format.tribe_scen.set_disabled_units(16, &[4]),
+            Err(Error::InvalidPlayerError(16))
+        ));
+        assert_eq!(format.tribe_scen.disabled_units(16), &[] as &[i32]);
+
+        let mut out = vec![];
+        let result = format.write_to(&mut out, &format.version());
+        assert!(matches!(result, Err(Error::CannotDisableUnitsError)));
+    }
+
     #[test]
     fn hd_aoe2scenario() {
         let mut f = File::open("test/scenarios/Year_of_the_Pig.aoe2scenario").unwrap();
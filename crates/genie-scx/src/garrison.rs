@@ -0,0 +1,321 @@
+//! Garrison relationships between scenario objects.
+//!
+//! Objects refer to the object they are garrisoned in through `ScenarioObject::garrisoned_in`.
+//! This module adds ways to traverse and edit those references.
+
+use crate::format::cmp_scx_version;
+use crate::{SCXVersion, Scenario, ScenarioObject, VersionBundle};
+use std::cmp::Ordering;
+
+/// Error type for garrison edits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GarrisonError {
+    /// There is no object with this ID.
+    MissingObject(i32),
+    /// The unit and the container are owned by different players.
+    OwnerMismatch { unit: i32, container: i32 },
+    /// The unit would end up garrisoned in itself, directly or through other objects.
+    Cycle { unit: i32, container: i32 },
+}
+
+impl std::fmt::Display for GarrisonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GarrisonError::MissingObject(id) => write!(f, "object {} does not exist", id),
+            GarrisonError::OwnerMismatch { unit, container } => write!(
+                f,
+                "object {} and container {} have different owners",
+                unit, container
+            ),
+            GarrisonError::Cycle { unit, container } => write!(
+                f,
+                "object {} can not be garrisoned in {}, because {} is inside it",
+                unit, container, container
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GarrisonError {}
+
+/// An object and the objects garrisoned in it, recursively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GarrisonTree {
+    /// The ID of the object.
+    pub id: i32,
+    /// The objects garrisoned in this object.
+    pub children: Vec<GarrisonTree>,
+}
+
+impl GarrisonTree {
+    /// Count the objects garrisoned in this object, recursively.
+    pub fn len(&self) -> usize {
+        self.children.iter().map(|child| 1 + child.len()).sum()
+    }
+
+    /// Returns whether no objects are garrisoned in this object.
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+/// Data that is lost when writing a scenario to a different version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionWarning {
+    /// The scenario version does not store garrisons, so this object will no longer be garrisoned
+    /// in its container.
+    DroppedGarrison { object: i32, container: i32 },
+}
+
+impl std::fmt::Display for ConversionWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConversionWarning::DroppedGarrison { object, container } => write!(
+                f,
+                "object {} will no longer be garrisoned in {}",
+                object, container
+            ),
+        }
+    }
+}
+
+/// Returns whether scenarios of this format version store garrisons.
+fn has_garrisons(version: SCXVersion) -> bool {
+    cmp_scx_version(version, *b"1.12") == Ordering::Greater
+}
+
+impl ScenarioObject {
+    /// Get the ID of the object this object is garrisoned in, if any.
+    pub fn container(&self) -> Option<i32> {
+        match self.garrisoned_in {
+            Some(id) if id >= 0 => Some(id),
+            _ => None,
+        }
+    }
+}
+
+impl Scenario {
+    /// Find an object by its ID.
+    pub fn object(&self, id: i32) -> Option<&ScenarioObject> {
+        self.objects().find(|object| object.id == id)
+    }
+
+    fn object_mut(&mut self, id: i32) -> Option<&mut ScenarioObject> {
+        self.objects_mut().find(|object| object.id == id)
+    }
+
+    /// Get the owner of an object, where 0 is Gaia and 1 is player 1.
+    pub fn object_owner(&self, id: i32) -> Option<usize> {
+        self.format
+            .player_objects
+            .iter()
+            .position(|objects| objects.iter().any(|object| object.id == id))
+    }
+
+    /// Get the objects that are garrisoned directly in a container.
+    pub fn garrisoned_objects(&self, container: i32) -> impl Iterator<Item = &ScenarioObject> {
+        self.objects()
+            .filter(move |object| object.container() == Some(container))
+    }
+
+    /// Get the tree of objects that are garrisoned in a container, including objects that are
+    /// garrisoned in those objects.
+    ///
+    /// Returns None if the container does not exist.
+    pub fn garrison_tree(&self, container: i32) -> Option<GarrisonTree> {
+        self.object(container)?;
+        let mut visited = vec![container];
+        Some(self.build_garrison_tree(container, &mut visited))
+    }
+
+    fn build_garrison_tree(&self, id: i32, visited: &mut Vec<i32>) -> GarrisonTree {
+        let children = self
+            .garrisoned_objects(id)
+            .map(|object| object.id)
+            .collect::<Vec<_>>();
+        let mut tree = GarrisonTree {
+            id,
+            children: vec![],
+        };
+        for child in children {
+            // Malformed scenarios may contain garrison cycles.
+            if visited.contains(&child) {
+                continue;
+            }
+            visited.push(child);
+            tree.children.push(self.build_garrison_tree(child, visited));
+        }
+        tree
+    }
+
+    /// Find objects that are garrisoned in an object that does not exist, or in themselves.
+    pub fn orphaned_garrisons(&self) -> Vec<&ScenarioObject> {
+        self.objects()
+            .filter(|object| match object.container() {
+                Some(container) => container == object.id || self.object(container).is_none(),
+                None => false,
+            })
+            .collect()
+    }
+
+    /// Garrison a unit in a container. The unit is moved to the container's position.
+    ///
+    /// The unit and the container must have the same owner, and the container must not already
+    /// be inside the unit.
+    pub fn garrison(&mut self, unit: i32, container: i32) -> Result<(), GarrisonError> {
+        let unit_owner = self
+            .object_owner(unit)
+            .ok_or(GarrisonError::MissingObject(unit))?;
+        let container_owner = self
+            .object_owner(container)
+            .ok_or(GarrisonError::MissingObject(container))?;
+        if unit_owner != container_owner {
+            return Err(GarrisonError::OwnerMismatch { unit, container });
+        }
+
+        let mut ancestor = Some(container);
+        let mut depth = 0;
+        while let Some(id) = ancestor {
+            if id == unit {
+                return Err(GarrisonError::Cycle { unit, container });
+            }
+            ancestor = self.object(id).and_then(|object| object.container());
+            // Stop at existing cycles, they can not contain the unit.
+            depth += 1;
+            if depth > self.objects().count() {
+                break;
+            }
+        }
+
+        let position = self.object(container).unwrap().position;
+        let object = self.object_mut(unit).unwrap();
+        object.garrisoned_in = Some(container);
+        object.position = position;
+        Ok(())
+    }
+
+    /// Remove a unit from the object it is garrisoned in. The unit stays at the container's
+    /// position.
+    ///
+    /// Returns the ID of the container, or None if the unit was not garrisoned.
+    pub fn ungarrison(&mut self, unit: i32) -> Result<Option<i32>, GarrisonError> {
+        let object = self
+            .object_mut(unit)
+            .ok_or(GarrisonError::MissingObject(unit))?;
+        let container = object.container();
+        object.garrisoned_in = None;
+        Ok(container)
+    }
+
+    /// Find data that would be lost when writing this scenario to a different version.
+    /// `write_to_version` returns the same warnings.
+    ///
+    /// Garrisons are kept in memory when converting a scenario, but versions older than 1.13 can
+    /// not store them.
+    pub fn conversion_warnings(&self, version: &VersionBundle) -> Vec<ConversionWarning> {
+        if has_garrisons(version.format) {
+            return vec![];
+        }
+        self.objects()
+            .filter_map(|object| {
+                object
+                    .container()
+                    .map(|container| ConversionWarning::DroppedGarrison {
+                        object: object.id,
+                        container,
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn scenario() -> Scenario {
+        let mut f = File::open("test/scenarios/real_world_amazon.scx").unwrap();
+        Scenario::from(&mut f).expect("failed to read")
+    }
+
+    #[test]
+    fn garrison_tree() {
+        let mut scenario = scenario();
+        let ship = scenario
+            .add_object(1, ScenarioObject::new(539, (10.5, 10.5)))
+            .unwrap();
+        let ram = scenario
+            .add_object(1, ScenarioObject::new(35, (20.5, 20.5)))
+            .unwrap();
+        let soldier = scenario
+            .add_object(1, ScenarioObject::new(4, (30.5, 30.5)))
+            .unwrap();
+        let enemy = scenario
+            .add_object(2, ScenarioObject::new(4, (40.5, 40.5)))
+            .unwrap();
+
+        scenario.garrison(ram, ship).unwrap();
+        scenario.garrison(soldier, ram).unwrap();
+        assert_eq!(scenario.object(soldier).unwrap().position.0, 10.5);
+        assert_eq!(
+            scenario.garrison(ship, soldier),
+            Err(GarrisonError::Cycle {
+                unit: ship,
+                container: soldier
+            })
+        );
+        assert_eq!(
+            scenario.garrison(enemy, ship),
+            Err(GarrisonError::OwnerMismatch {
+                unit: enemy,
+                container: ship
+            })
+        );
+
+        let tree = scenario.garrison_tree(ship).unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.children[0].id, ram);
+        assert_eq!(tree.children[0].children[0].id, soldier);
+
+        assert_eq!(scenario.ungarrison(soldier), Ok(Some(ram)));
+        assert_eq!(scenario.object(soldier).unwrap().garrisoned_in, None);
+        assert_eq!(scenario.garrison_tree(ship).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn orphans_and_old_versions() {
+        let mut scenario = scenario();
+        let unit = scenario
+            .add_object(1, ScenarioObject::new(4, (30.5, 30.5)))
+            .unwrap();
+        scenario.object_mut(unit).unwrap().garrisoned_in = Some(999_999);
+        assert_eq!(scenario.orphaned_garrisons().len(), 1);
+
+        let old = VersionBundle {
+            format: *b"1.10",
+            ..VersionBundle::aoc()
+        };
+        assert_eq!(
+            scenario.conversion_warnings(&old),
+            vec![ConversionWarning::DroppedGarrison {
+                object: unit,
+                container: 999_999
+            }]
+        );
+        assert!(scenario
+            .conversion_warnings(&VersionBundle::aoc())
+            .is_empty());
+
+        // Writing returns the same warnings.
+        let mut output = vec![];
+        assert_eq!(
+            scenario.write_to_version(&mut output, &old).unwrap(),
+            scenario.conversion_warnings(&old)
+        );
+        assert!(scenario
+            .write_to_version(&mut vec![], &VersionBundle::aoc())
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,336 @@
+//! Conversion between maps and images.
+//!
+//! Maps can be built from a grayscale heightmap and an indexed-color terrain image, and exported
+//! back to those images, so they can be edited in external image editors. Objects like trees can
+//! be placed using a grayscale mask image.
+//!
+//! This module works on raw pixel buffers, so it does not depend on any image format. Use an image
+//! library to load and save the pixels.
+
+use crate::{Map, ScenarioObject};
+
+/// The highest elevation level used when converting heightmaps and when editing elevation.
+pub const MAX_ELEVATION: i8 = 7;
+
+/// An 8-bit grayscale image, with one byte per pixel, stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrayImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// An indexed-color image, with one palette index per pixel, stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    /// RGB colors for each palette index.
+    pub palette: Vec<[u8; 3]>,
+    pub pixels: Vec<u8>,
+}
+
+/// Maps RGB colors in a terrain image to terrain IDs.
+#[derive(Debug, Clone, Default)]
+pub struct TerrainColors {
+    colors: Vec<([u8; 3], i8)>,
+}
+
+impl TerrainColors {
+    /// Create an empty color table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a color to a terrain ID.
+    ///
+    /// If the color or the terrain ID was already mapped, the old mapping is replaced.
+    pub fn insert(&mut self, color: [u8; 3], terrain: i8) {
+        self.colors.retain(|&(c, t)| c != color && t != terrain);
+        self.colors.push((color, terrain));
+    }
+
+    /// Get the terrain ID for a color.
+    pub fn terrain(&self, color: [u8; 3]) -> Option<i8> {
+        self.colors
+            .iter()
+            .find(|(c, _)| *c == color)
+            .map(|(_, t)| *t)
+    }
+
+    /// Get the color for a terrain ID.
+    pub fn color(&self, terrain: i8) -> Option<[u8; 3]> {
+        self.colors
+            .iter()
+            .find(|(_, t)| *t == terrain)
+            .map(|(c, _)| *c)
+    }
+}
+
+/// Error type for map image conversions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapImageError {
+    /// The images do not have the same size, or their pixel buffers do not match their size.
+    SizeMismatch,
+    /// A pixel in the terrain image refers to a palette index that does not exist.
+    InvalidPaletteIndex(u8),
+    /// The terrain image contains a color that is not in the color table.
+    UnknownColor([u8; 3]),
+    /// The map contains a terrain ID that is not in the color table.
+    UnknownTerrain(i8),
+}
+
+impl std::fmt::Display for MapImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MapImageError::SizeMismatch => write!(f, "image sizes do not match"),
+            MapImageError::InvalidPaletteIndex(index) => {
+                write!(f, "palette index {} does not exist", index)
+            }
+            MapImageError::UnknownColor([r, g, b]) => {
+                write!(f, "no terrain for color #{:02x}{:02x}{:02x}", r, g, b)
+            }
+            MapImageError::UnknownTerrain(terrain) => {
+                write!(f, "no color for terrain {}", terrain)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapImageError {}
+
+/// Convert a grayscale value to an elevation level.
+fn quantize(value: u8) -> i8 {
+    ((f32::from(value) * f32::from(MAX_ELEVATION)) / 255.0).round() as i8
+}
+
+/// Convert an elevation level to a grayscale value.
+fn dequantize(elevation: i8) -> u8 {
+    let elevation = elevation.clamp(0, MAX_ELEVATION);
+    ((f32::from(elevation) * 255.0) / f32::from(MAX_ELEVATION)).round() as u8
+}
+
+fn check_size(width: u32, height: u32, len: usize) -> Result<(), MapImageError> {
+    if width as usize * height as usize == len {
+        Ok(())
+    } else {
+        Err(MapImageError::SizeMismatch)
+    }
+}
+
+impl Map {
+    /// Build a map from a grayscale heightmap and an indexed-color terrain image.
+    ///
+    /// Heightmap values are quantized to elevation levels 0 through `MAX_ELEVATION`. Tiles that
+    /// would be more than one level above a neighbour are lowered, so the result follows the
+    /// game's elevation rules.
+    pub fn from_images(
+        heightmap: &GrayImage,
+        terrain: &IndexedImage,
+        colors: &TerrainColors,
+    ) -> Result<Self, MapImageError> {
+        check_size(heightmap.width, heightmap.height, heightmap.pixels.len())?;
+        check_size(terrain.width, terrain.height, terrain.pixels.len())?;
+        if (heightmap.width, heightmap.height) != (terrain.width, terrain.height) {
+            return Err(MapImageError::SizeMismatch);
+        }
+
+        let mut map = Map::new(terrain.width, terrain.height);
+        let pixels = heightmap.pixels.iter().zip(terrain.pixels.iter());
+        for (tile, (&height, &index)) in map.tiles_mut().zip(pixels) {
+            let color = *terrain
+                .palette
+                .get(usize::from(index))
+                .ok_or(MapImageError::InvalidPaletteIndex(index))?;
+            tile.terrain = colors
+                .terrain(color)
+                .ok_or(MapImageError::UnknownColor(color))?;
+            tile.elevation = quantize(height);
+        }
+        map.fix_elevation();
+
+        Ok(map)
+    }
+
+    /// Export the elevation of this map as a grayscale heightmap.
+    pub fn to_heightmap(&self) -> GrayImage {
+        GrayImage {
+            width: self.width(),
+            height: self.height(),
+            pixels: self
+                .tiles()
+                .map(|tile| dequantize(tile.elevation))
+                .collect(),
+        }
+    }
+
+    /// Export the terrain of this map as an indexed-color image.
+    ///
+    /// The palette contains the colors of the terrains that are used on the map.
+    pub fn to_terrain_image(&self, colors: &TerrainColors) -> Result<IndexedImage, MapImageError> {
+        let mut used = vec![];
+        let mut pixels = Vec::with_capacity(self.width() as usize * self.height() as usize);
+        for tile in self.tiles() {
+            let index = match used.iter().position(|&t| t == tile.terrain) {
+                Some(index) => index,
+                None => {
+                    used.push(tile.terrain);
+                    used.len() - 1
+                }
+            };
+            // Indexed images can only have 256 colors, but there are fewer terrains than that.
+            pixels.push(index as u8);
+        }
+
+        let palette = used
+            .into_iter()
+            .map(|terrain| {
+                colors
+                    .color(terrain)
+                    .ok_or(MapImageError::UnknownTerrain(terrain))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(IndexedImage {
+            width: self.width(),
+            height: self.height(),
+            palette,
+            pixels,
+        })
+    }
+}
+
+/// Create objects of the given type for every non-black pixel in a mask image.
+///
+/// Objects are placed in the center of the tile for the pixel. Their IDs are -1; they are
+/// assigned when adding the objects to a scenario with `Scenario::add_object`.
+pub fn objects_from_mask(mask: &GrayImage, object_type: i16) -> Vec<ScenarioObject> {
+    let width = mask.width.max(1) as usize;
+    mask.pixels
+        .iter()
+        .enumerate()
+        .filter(|(_, &value)| value != 0)
+        .map(|(index, _)| {
+            let (x, y) = (index % width, index / width);
+            ScenarioObject::new(object_type, (x as f32 + 0.5, y as f32 + 0.5))
+        })
+        .collect()
+}
+
+/// Create a mask image of the given size with a white pixel for every tile that contains an
+/// object of the given type.
+pub fn objects_to_mask<'a>(
+    objects: impl IntoIterator<Item = &'a ScenarioObject>,
+    object_type: i16,
+    width: u32,
+    height: u32,
+) -> GrayImage {
+    let mut pixels = vec![0; width as usize * height as usize];
+    for object in objects {
+        if object.object_type != object_type {
+            continue;
+        }
+        let (x, y, _) = object.position;
+        if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+            continue;
+        }
+        pixels[y as usize * width as usize + x as usize] = 255;
+    }
+    GrayImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors() -> TerrainColors {
+        let mut colors = TerrainColors::new();
+        colors.insert([0, 128, 0], 0);
+        colors.insert([0, 0, 255], 1);
+        colors.insert([255, 255, 0], 2);
+        colors
+    }
+
+    #[test]
+    fn map_round_trips_through_images() {
+        let mut map = Map::new(8, 6);
+        map.fill_rect((0, 0), (2, 5), 1);
+        map.fill_rect((6, 0), (7, 5), 2);
+        map.set_elevation(4, 3, 3);
+
+        let heightmap = map.to_heightmap();
+        let terrain = map.to_terrain_image(&colors()).unwrap();
+        assert_eq!(terrain.palette.len(), 3);
+
+        let map2 = Map::from_images(&heightmap, &terrain, &colors()).unwrap();
+        assert!(map
+            .tiles()
+            .zip(map2.tiles())
+            .all(|(a, b)| { a.terrain == b.terrain && a.elevation == b.elevation }));
+    }
+
+    #[test]
+    fn heightmap_is_quantized_and_made_valid() {
+        let heightmap = GrayImage {
+            width: 3,
+            height: 1,
+            pixels: vec![0, 255, 100],
+        };
+        let terrain = IndexedImage {
+            width: 3,
+            height: 1,
+            palette: vec![[0, 128, 0]],
+            pixels: vec![0, 0, 0],
+        };
+        let map = Map::from_images(&heightmap, &terrain, &colors()).unwrap();
+        let elevations: Vec<_> = map.tiles().map(|tile| tile.elevation).collect();
+        assert_eq!(elevations, vec![0, 1, 2]);
+        assert!(map.is_elevation_valid());
+    }
+
+    #[test]
+    fn unknown_colors_and_terrains() {
+        let heightmap = GrayImage {
+            width: 1,
+            height: 1,
+            pixels: vec![0],
+        };
+        let terrain = IndexedImage {
+            width: 1,
+            height: 1,
+            palette: vec![[1, 2, 3]],
+            pixels: vec![0],
+        };
+        assert_eq!(
+            Map::from_images(&heightmap, &terrain, &colors()).unwrap_err(),
+            MapImageError::UnknownColor([1, 2, 3])
+        );
+
+        let mut map = Map::new(2, 2);
+        map.paint(0, 0, 0, 9);
+        assert_eq!(
+            map.to_terrain_image(&colors()).unwrap_err(),
+            MapImageError::UnknownTerrain(9)
+        );
+    }
+
+    #[test]
+    fn objects_round_trip_through_mask() {
+        let mask = GrayImage {
+            width: 3,
+            height: 2,
+            pixels: vec![0, 255, 0, 10, 0, 0],
+        };
+        let objects = objects_from_mask(&mask, 411);
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].position, (1.5, 0.5, 0.0));
+        assert_eq!(objects[1].position, (0.5, 1.5, 0.0));
+
+        let mask2 = objects_to_mask(&objects, 411, 3, 2);
+        assert_eq!(mask2.pixels, vec![0, 255, 0, 255, 0, 0]);
+    }
+}
@@ -3,26 +3,42 @@
 //! This crate aims to support every single scenario that exists. If a scenario file from any Age
 //! of Empires 1 or Age of Empires 2 version does not work, please upload it and file an issue!
 mod ai;
+pub mod analysis;
 mod bitmap;
 pub mod convert;
 mod format;
+mod garrison;
 mod header;
+pub mod heightmap;
 mod map;
+pub mod pathfinding;
 mod player;
+pub mod search;
 mod triggers;
 mod types;
+mod userpatch;
 mod util;
 mod victory;
 
 use format::SCXFormat;
 use std::io::{self, Read, Write};
 
+pub use ai::{AIErrorCode, AIErrorInfo, AIFile, AIInfo, ParseAIErrorCodeError};
 pub use format::ScenarioObject;
+pub use garrison::{ConversionWarning, GarrisonError, GarrisonTree};
 pub use header::{DLCOptions, SCXHeader};
 pub use map::{Map, Tile};
-pub use triggers::{Trigger, TriggerCondition, TriggerEffect, TriggerSystem};
+pub use triggers::{
+    ConditionType, EffectType, ObjectAttribute, ParseConditionTypeError, ParseEffectTypeError,
+    ParseObjectAttributeError, Trigger, TriggerCondition, TriggerEffect, TriggerSystem,
+};
 pub use types::*;
+pub use userpatch::{CompatibilityIssue, VANILLA_MAX_TERRAIN};
 pub use util::{DecodeStringError, EncodeStringError};
+pub use victory::{
+    CustomVictory, GlobalVictory, LegacyVictoryInfo, VictoryConditions, VictoryEntry,
+    VictoryPointEntry,
+};
 
 /// Error type for SCX methods, containing all types of errors that may occur while reading or
 /// writing scenario files.
@@ -34,11 +50,14 @@ pub enum Error {
     UnsupportedFormatVersionError(SCXVersion),
     /// Attempted to write a scenario with disabled technologies, to a version that doesn't support
     /// this many disabled technologies.
-    TooManyDisabledTechsError(i32),
+    TooManyDisabledTechsError(i32, i32),
     /// Attempted to write a scenario with disabled technologies, to a version that doesn't support
     /// disabling technologies.
     CannotDisableTechsError,
     /// Attempted to write a scenario with disabled units, to a version that doesn't support
+    /// this many disabled units.
+    TooManyDisabledUnitsError(i32, i32),
+    /// Attempted to write a scenario with disabled units, to a version that doesn't support
     /// disabling units.
     CannotDisableUnitsError,
     /// Attempted to write a scenario with disabled buildings, to a version that doesn't support
@@ -47,6 +66,16 @@ pub enum Error {
     /// Attempted to write a scenario with disabled buildings, to a version that doesn't support
     /// disabling buildings.
     CannotDisableBuildingsError,
+    /// Attempted to write a scenario with a UserPatch trigger effect, to a version that doesn't
+    /// support UserPatch effects.
+    UserPatchEffectError(i32),
+    /// Attempted to change data for a player index that scenarios can not store data for.
+    InvalidPlayerError(usize),
+    /// Attempted to add triggers to a scenario for a version that doesn't support triggers.
+    CannotStoreTriggersError,
+    /// Attempted to assign an embedded AI file to a player, but the scenario does not contain an
+    /// AI file with that name.
+    MissingAIFileError(String),
     /// Failed to decode a string from the scenario file, probably because of a wrong encoding.
     DecodeStringError(DecodeStringError),
     /// Failed to encode a string into the scenario file, probably because of a wrong encoding.
@@ -59,6 +88,8 @@ pub enum Error {
     ParseDLCPackageError(ParseDLCPackageError),
     /// The given ID is not a known starting age in AoE1 or AoE2.
     ParseStartingAgeError(ParseStartingAgeError),
+    /// The given ID is not a known AI error code.
+    ParseAIErrorCodeError(ParseAIErrorCodeError),
     /// An error occurred while reading or writing.
     IoError(io::Error),
 }
@@ -101,6 +132,7 @@ error_impl_from!(ParseDiplomaticStanceError);
 error_impl_from!(ParseDataSetError);
 error_impl_from!(ParseDLCPackageError);
 error_impl_from!(ParseStartingAgeError);
+error_impl_from!(ParseAIErrorCodeError);
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -109,10 +141,15 @@ impl std::fmt::Display for Error {
             Error::UnsupportedFormatVersionError(version) => {
                 write!(f, "unsupported format version {:?}", version)
             }
-            Error::TooManyDisabledTechsError(n) => write!(
+            Error::TooManyDisabledTechsError(n, max) => write!(
                 f,
-                "too many disabled techs: got {}, but requested version supports up to 20",
-                n
+                "too many disabled techs: got {}, but requested version supports up to {}",
+                n, max
+            ),
+            Error::TooManyDisabledUnitsError(n, max) => write!(
+                f,
+                "too many disabled units: got {}, but requested version supports up to {}",
+                n, max
             ),
             Error::TooManyDisabledBuildingsError(n, max) => write!(
                 f,
@@ -128,6 +165,16 @@ impl std::fmt::Display for Error {
             Error::CannotDisableBuildingsError => {
                 write!(f, "requested version does not support disabling buildings")
             }
+            Error::UserPatchEffectError(effect_type) => write!(
+                f,
+                "requested version does not support UserPatch trigger effect {}",
+                effect_type
+            ),
+            Error::InvalidPlayerError(player) => write!(f, "invalid player index {}", player),
+            Error::CannotStoreTriggersError => {
+                write!(f, "requested version does not support triggers")
+            }
+            Error::MissingAIFileError(name) => write!(f, "missing AI file {}", name),
             Error::IoError(err) => write!(f, "{}", err),
             Error::DecodeStringError(err) => write!(f, "{}", err),
             Error::EncodeStringError(err) => write!(f, "{}", err),
@@ -135,6 +182,7 @@ impl std::fmt::Display for Error {
             Error::ParseDataSetError(err) => write!(f, "{}", err),
             Error::ParseDLCPackageError(err) => write!(f, "{}", err),
             Error::ParseStartingAgeError(err) => write!(f, "{}", err),
+            Error::ParseAIErrorCodeError(err) => write!(f, "{}", err),
         }
     }
 }
@@ -163,12 +211,17 @@ impl Scenario {
         self.format.write_to(output, self.version())
     }
 
+    /// Write the scenario file in a different version.
+    ///
+    /// Some data can not be stored in older versions. The returned warnings describe what was
+    /// lost; `conversion_warnings` finds the same warnings without writing anything.
     pub fn write_to_version<W: Write>(
         &self,
         output: &mut W,
         version: &VersionBundle,
-    ) -> Result<()> {
-        self.format.write_to(output, version)
+    ) -> Result<Vec<ConversionWarning>> {
+        self.format.write_to(output, version)?;
+        Ok(self.conversion_warnings(version))
     }
 
     /// Get the format version of this SCX file.
@@ -228,6 +281,19 @@ impl Scenario {
             .flatten()
     }
 
+    /// Add an object to the scenario, assigning it a new unique ID.
+    ///
+    /// `player` is the owner of the object, where 0 is Gaia and 1 is player 1. Returns the ID of
+    /// the new object, or None if the scenario does not contain data for this player.
+    pub fn add_object(&mut self, player: usize, mut object: ScenarioObject) -> Option<i32> {
+        let list = self.format.player_objects.get_mut(player)?;
+        let id = self.format.next_object_id;
+        self.format.next_object_id += 1;
+        object.id = id;
+        list.push(object);
+        Some(id)
+    }
+
     pub fn map(&self) -> &Map {
         &self.format.map
     }
@@ -236,6 +302,221 @@ impl Scenario {
         &mut self.format.map
     }
 
+    /// Get the global victory settings.
+    pub fn victory(&self) -> GlobalVictory {
+        self.format.tribe_scen.global_victory()
+    }
+
+    /// Set the global victory settings.
+    pub fn set_victory(&mut self, victory: GlobalVictory) {
+        self.format.tribe_scen.set_global_victory(victory)
+    }
+
+    /// Get the victory conditions for a player.
+    ///
+    /// `player` is the player index, from 0 for player 1. Returns None if the scenario does not
+    /// contain data for this player.
+    pub fn player_victory(&self, player: usize) -> Option<&VictoryConditions> {
+        self.format
+            .scenario_players
+            .get(player)
+            .map(|data| &data.victory)
+    }
+
+    /// Get a mutable reference to the victory conditions for a player.
+    pub fn player_victory_mut(&mut self, player: usize) -> Option<&mut VictoryConditions> {
+        self.format
+            .scenario_players
+            .get_mut(player)
+            .map(|data| &mut data.victory)
+    }
+
+    /// Get the AoE1 victory info for a player.
+    ///
+    /// `player` is the player index, from 0 for player 1 up to 15 for player 16.
+    pub fn legacy_victory_info(&self, player: usize) -> &[LegacyVictoryInfo] {
+        self.format.tribe_scen.legacy_victory_info(player)
+    }
+
+    /// Convert per-player victory conditions, as used by AoE1, to triggers.
+    ///
+    /// For each player that has victory conditions, a trigger is added that declares victory for
+    /// that player when all of their conditions are met, and the player's victory conditions
+    /// are removed. Players with conditions that can not be expressed as triggers are left
+    /// untouched.
+    ///
+    /// `version` is the version that the scenario will be written as. If that version can not
+    /// store triggers, a `CannotStoreTriggersError` is returned and the victory conditions are
+    /// left untouched.
+    ///
+    /// Returns the number of players whose victory conditions were converted.
+    pub fn convert_legacy_victory(&mut self, version: &VersionBundle) -> Result<usize> {
+        if !version.has_triggers() {
+            return Err(Error::CannotStoreTriggersError);
+        }
+
+        let mut converted = 0;
+        let mut new_triggers = vec![];
+        for (index, player) in self.format.scenario_players.iter_mut().enumerate() {
+            if let Some(trigger) = player.victory.to_trigger(index as i32 + 1) {
+                player.victory.entries_mut().clear();
+                new_triggers.push(trigger);
+                converted += 1;
+            }
+        }
+
+        if !new_triggers.is_empty() {
+            let triggers = self
+                .format
+                .triggers
+                .get_or_insert_with(TriggerSystem::default);
+            for trigger in new_triggers {
+                triggers.push(trigger);
+            }
+        }
+
+        Ok(converted)
+    }
+
+    /// Get the IDs of the technologies that are disabled for a player.
+    ///
+    /// `player` is the player index, from 0 for player 1 up to 15 for player 16. Returns an empty
+    /// list for other indices.
+    pub fn disabled_techs(&self, player: usize) -> &[i32] {
+        self.format.tribe_scen.disabled_techs(player)
+    }
+
+    /// Set the IDs of the technologies that are disabled for a player.
+    ///
+    /// If the version this scenario is written as can not store this many disabled technologies,
+    /// but supports UserPatch trigger effects, the excess is disabled by a trigger at the start of
+    /// the game. Otherwise, writing the scenario fails.
+    ///
+    /// Returns an `InvalidPlayerError` if `player` is not between 0 and 15.
+    pub fn set_disabled_techs(&mut self, player: usize, techs: &[i32]) -> Result<()> {
+        self.format.tribe_scen.set_disabled_techs(player, techs)
+    }
+
+    /// Get the IDs of the units that are disabled for a player.
+    ///
+    /// `player` is the player index, from 0 for player 1 up to 15 for player 16. Returns an empty
+    /// list for other indices.
+    pub fn disabled_units(&self, player: usize) -> &[i32] {
+        self.format.tribe_scen.disabled_units(player)
+    }
+
+    /// Set the IDs of the units that are disabled for a player.
+    ///
+    /// If the version this scenario is written as can not store this many disabled units, but
+    /// supports UserPatch trigger effects, the excess is disabled by a trigger at the start of the
+    /// game. Otherwise, writing the scenario fails.
+    ///
+    /// Returns an `InvalidPlayerError` if `player` is not between 0 and 15.
+    pub fn set_disabled_units(&mut self, player: usize, units: &[i32]) -> Result<()> {
+        self.format.tribe_scen.set_disabled_units(player, units)
+    }
+
+    /// Get the IDs of the buildings that are disabled for a player.
+    ///
+    /// `player` is the player index, from 0 for player 1 up to 15 for player 16. Returns an empty
+    /// list for other indices.
+    pub fn disabled_buildings(&self, player: usize) -> &[i32] {
+        self.format.tribe_scen.disabled_buildings(player)
+    }
+
+    /// Set the IDs of the buildings that are disabled for a player.
+    ///
+    /// If the version this scenario is written as can not store this many disabled buildings,
+    /// but supports UserPatch trigger effects, the excess is disabled by a trigger at the start of
+    /// the game. Otherwise, writing the scenario fails.
+    ///
+    /// Returns an `InvalidPlayerError` if `player` is not between 0 and 15.
+    pub fn set_disabled_buildings(&mut self, player: usize, buildings: &[i32]) -> Result<()> {
+        self.format
+            .tribe_scen
+            .set_disabled_buildings(player, buildings)
+    }
+
+    /// Get the AI files embedded in this scenario.
+    ///
+    /// AI files are only stored in scenario format versions 1.18 through 1.99.
+    pub fn ai_info(&self) -> Option<&AIInfo> {
+        self.format.ai_info.as_ref()
+    }
+
+    /// Get a mutable reference to the AI files embedded in this scenario, creating an empty set
+    /// if the scenario did not have any.
+    pub fn ai_info_mut(&mut self) -> &mut AIInfo {
+        self.format.ai_info.get_or_insert_with(AIInfo::default)
+    }
+
+    /// Get the error that the game reported while compiling the embedded AI files, if any.
+    pub fn ai_error(&self) -> Option<&AIErrorInfo> {
+        self.format.ai_info.as_ref().and_then(AIInfo::error)
+    }
+
+    /// Get the name of the AI selected for a player.
+    ///
+    /// `player` is the player index, from 0 for player 1 up to 15 for player 16. Returns `None`
+    /// for other indices.
+    pub fn player_ai_name(&self, player: usize) -> Option<&str> {
+        self.format.tribe_scen.base.player_ai_name(player)
+    }
+
+    /// Get the source of the custom AI script stored for a player.
+    pub fn player_ai_source(&self, player: usize) -> Option<&str> {
+        self.format.tribe_scen.base.player_ai_source(player)
+    }
+
+    /// Get the AI rules type for a player: 0 for custom AI scripts, 1 for the game's standard AIs.
+    pub fn player_ai_type(&self, player: usize) -> Option<i8> {
+        self.format.tribe_scen.base.player_ai_type(player)
+    }
+
+    /// Select one of the game's standard AIs, like "Promisory", for a player.
+    ///
+    /// Returns `Error::InvalidPlayerError` if `player` is not a valid player index.
+    pub fn set_player_standard_ai(&mut self, player: usize, name: &str) -> Result<()> {
+        self.format
+            .tribe_scen
+            .base
+            .set_player_ai(player, Some(name), None, 1)
+    }
+
+    /// Select a custom AI script for a player.
+    ///
+    /// This stores the script source in the player data. Use `set_player_embedded_ai` to select
+    /// an AI file that is already embedded in the scenario.
+    pub fn set_player_custom_ai(&mut self, player: usize, file: &AIFile) -> Result<()> {
+        self.format.tribe_scen.base.set_player_ai(
+            player,
+            Some(file.ai_name()),
+            Some(file.content()),
+            0,
+        )
+    }
+
+    /// Select one of the AI files embedded in this scenario for a player.
+    pub fn set_player_embedded_ai(&mut self, player: usize, filename: &str) -> Result<()> {
+        let file = self
+            .ai_info()
+            .and_then(|ai_info| ai_info.file(filename))
+            .cloned()
+            .ok_or_else(|| Error::MissingAIFileError(filename.to_string()))?;
+        self.set_player_custom_ai(player, &file)
+    }
+
+    /// Find UserPatch features in this scenario that vanilla AoC would misinterpret, like
+    /// UserPatch-only trigger effects and extended terrain IDs.
+    pub fn vanilla_compatibility_issues(&self) -> Vec<CompatibilityIssue> {
+        let mut issues = vec![];
+        if let Some(triggers) = &self.format.triggers {
+            issues.extend(userpatch::check_triggers(triggers));
+        }
+        issues.extend(userpatch::check_map(&self.format.map));
+        issues
+    }
+
     pub fn triggers(&self) -> Option<&TriggerSystem> {
         self.format.triggers.as_ref()
     }
@@ -1,9 +1,14 @@
+use crate::heightmap::MAX_ELEVATION;
 use crate::Result;
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::collections::VecDeque;
 use std::io::{Read, Write};
 
+/// Number of tiles that are decoded or encoded at a time.
+const TILE_CHUNK_SIZE: usize = 1024;
+
 /// A map tile.
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Tile {
     /// The terrain.
     pub terrain: i8,
@@ -20,26 +25,39 @@ pub struct Map {
     width: u32,
     /// Height of this map in tiles.
     height: u32,
-    /// Matrix of tiles on this map.
-    tiles: Vec<Vec<Tile>>,
+    /// Tiles on this map, stored row by row.
+    tiles: Vec<Tile>,
 }
 
 impl Map {
+    /// Create a flat map of the given size, filled with terrain 0.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![Tile::default(); width as usize * height as usize],
+        }
+    }
+
     pub fn from<R: Read>(input: &mut R) -> Result<Self> {
         let width = input.read_u32::<LE>()?;
         let height = input.read_u32::<LE>()?;
 
-        let mut tiles = Vec::with_capacity(height as usize);
-        for _ in 0..height {
-            let mut row = Vec::with_capacity(width as usize);
-            for _ in 0..width {
-                row.push(Tile {
-                    terrain: input.read_i8()?,
-                    elevation: input.read_i8()?,
-                    zone: input.read_i8()?,
-                });
-            }
-            tiles.push(row);
+        let mut remaining = width as usize * height as usize;
+        // The size comes from the file, so only reserve a bounded amount up front and let the
+        // vector grow as tiles are actually read.
+        let mut tiles = Vec::with_capacity(remaining.min(TILE_CHUNK_SIZE * 64));
+        let mut buffer = [0; TILE_CHUNK_SIZE * 3];
+        while remaining > 0 {
+            let count = remaining.min(TILE_CHUNK_SIZE);
+            let bytes = &mut buffer[..count * 3];
+            input.read_exact(bytes)?;
+            tiles.extend(bytes.chunks_exact(3).map(|tile| Tile {
+                terrain: tile[0] as i8,
+                elevation: tile[1] as i8,
+                zone: tile[2] as i8,
+            }));
+            remaining -= count;
         }
 
         Ok(Self {
@@ -53,17 +71,19 @@ impl Map {
         output.write_u32::<LE>(self.width)?;
         output.write_u32::<LE>(self.height)?;
 
-        assert_eq!(self.tiles.len(), self.height as usize);
-        for row in &self.tiles {
-            assert_eq!(row.len(), self.width as usize);
-        }
+        assert_eq!(self.tiles.len(), self.width as usize * self.height as usize);
 
-        for row in &self.tiles {
-            for tile in row {
-                output.write_i8(tile.terrain)?;
-                output.write_i8(tile.elevation)?;
-                output.write_i8(tile.zone)?;
+        let mut buffer = Vec::with_capacity(TILE_CHUNK_SIZE * 3);
+        for chunk in self.tiles.chunks(TILE_CHUNK_SIZE) {
+            buffer.clear();
+            for tile in chunk {
+                buffer.extend_from_slice(&[
+                    tile.terrain as u8,
+                    tile.elevation as u8,
+                    tile.zone as u8,
+                ]);
             }
+            output.write_all(&buffer)?;
         }
 
         Ok(())
@@ -79,33 +99,499 @@ impl Map {
         self.height
     }
 
+    /// Get the index of the tile at the given coordinates in the tile buffer, without checking
+    /// that the coordinates are in bounds.
+    fn offset(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// Get the index of the tile at the given coordinates in the tile buffer.
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(self.offset(x, y))
+        } else {
+            None
+        }
+    }
+
     /// Get a tile at the given coordinates.
     ///
     /// If the coordinates are out of bounds, returns None.
     pub fn tile(&self, x: u32, y: u32) -> Option<&Tile> {
-        self.tiles
-            .get(y as usize)
-            .and_then(|row| row.get(x as usize))
+        self.index(x, y).map(|index| &self.tiles[index])
     }
 
     /// Get a mutable reference to the tile at the given coordinates.
     ///
     /// If the coordinates are out of bounds, returns None.
     pub fn tile_mut(&mut self, x: u32, y: u32) -> Option<&mut Tile> {
-        self.tiles
-            .get_mut(y as usize)
-            .and_then(|row| row.get_mut(x as usize))
+        match self.index(x, y) {
+            Some(index) => Some(&mut self.tiles[index]),
+            None => None,
+        }
     }
 
     /// Iterate over all the tiles.
     pub fn tiles(&self) -> impl Iterator<Item = &Tile> {
-        self.tiles.iter().map(|row| row.iter()).flatten()
+        self.tiles.iter()
     }
 
     /// Iterate over all the tiles, with mutable references.
     ///
     /// This is handy if you want to replace terrains throughout the entire map, for example.
     pub fn tiles_mut(&mut self) -> impl Iterator<Item = &mut Tile> {
-        self.tiles.iter_mut().map(|row| row.iter_mut()).flatten()
+        self.tiles.iter_mut()
+    }
+
+    /// Get all the tiles as a slice, stored row by row.
+    pub fn as_tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    /// Get all the tiles as a mutable slice, stored row by row.
+    pub fn as_tiles_mut(&mut self) -> &mut [Tile] {
+        &mut self.tiles
+    }
+
+    /// Get a row of tiles.
+    ///
+    /// If the row is out of bounds, returns None.
+    pub fn row(&self, y: u32) -> Option<&[Tile]> {
+        let start = self.index(0, y)?;
+        Some(&self.tiles[start..start + self.width as usize])
+    }
+
+    /// Get a mutable row of tiles.
+    ///
+    /// If the row is out of bounds, returns None.
+    pub fn row_mut(&mut self, y: u32) -> Option<&mut [Tile]> {
+        let start = self.index(0, y)?;
+        let end = start + self.width as usize;
+        Some(&mut self.tiles[start..end])
+    }
+
+    /// Iterate over the rows of tiles.
+    pub fn rows(&self) -> impl Iterator<Item = &[Tile]> {
+        self.tiles.chunks_exact(self.width.max(1) as usize)
+    }
+
+    /// Iterate over the tiles in a column.
+    ///
+    /// If the column is out of bounds, the iterator is empty.
+    pub fn column(&self, x: u32) -> impl Iterator<Item = &Tile> {
+        let len = if x < self.width { self.height } else { 0 };
+        self.tiles
+            .iter()
+            .skip(x as usize)
+            .step_by(self.width.max(1) as usize)
+            .take(len as usize)
+    }
+
+    /// Iterate over the terrain IDs of all tiles, row by row.
+    pub fn terrains(&self) -> impl Iterator<Item = i8> + '_ {
+        self.tiles.iter().map(|tile| tile.terrain)
+    }
+
+    /// Iterate over the elevations of all tiles, row by row.
+    pub fn elevations(&self) -> impl Iterator<Item = i8> + '_ {
+        self.tiles.iter().map(|tile| tile.elevation)
+    }
+
+    /// Set the terrain IDs of all tiles, row by row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `terrains` does not contain exactly one value per tile.
+    pub fn set_terrains(&mut self, terrains: &[i8]) {
+        assert_eq!(terrains.len(), self.tiles.len());
+        for (tile, terrain) in self.tiles.iter_mut().zip(terrains) {
+            tile.terrain = *terrain;
+        }
+    }
+
+    /// Set the elevations of all tiles, row by row.
+    ///
+    /// This does not check that the elevations follow the game's elevation rules.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `elevations` does not contain exactly one value per tile.
+    pub fn set_elevations(&mut self, elevations: &[i8]) {
+        assert_eq!(elevations.len(), self.tiles.len());
+        for (tile, elevation) in self.tiles.iter_mut().zip(elevations) {
+            tile.elevation = *elevation;
+        }
+    }
+
+    /// Get the coordinates of the tiles surrounding the given tile, including diagonal neighbours.
+    fn neighbours(&self, x: u32, y: u32) -> impl Iterator<Item = (u32, u32)> {
+        let (width, height) = (i64::from(self.width), i64::from(self.height));
+        let (x, y) = (i64::from(x), i64::from(y));
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+            .filter(move |&(nx, ny)| {
+                (nx, ny) != (x, y) && nx >= 0 && ny >= 0 && nx < width && ny < height
+            })
+            .map(|(nx, ny)| (nx as u32, ny as u32))
+    }
+
+    /// Replace the terrain of the area of connected tiles with the same terrain as the tile at
+    /// the given coordinates, like the paint bucket tool in image editors.
+    ///
+    /// Returns the number of tiles that were changed.
+    pub fn flood_fill(&mut self, x: u32, y: u32, terrain: i8) -> usize {
+        let target = match self.tile(x, y) {
+            Some(tile) if tile.terrain != terrain => tile.terrain,
+            _ => return 0,
+        };
+
+        let mut changed = 0;
+        let mut queue = VecDeque::new();
+        queue.push_back((x, y));
+        while let Some((x, y)) = queue.pop_front() {
+            match self.tile_mut(x, y) {
+                Some(tile) if tile.terrain == target => tile.terrain = terrain,
+                _ => continue,
+            }
+            changed += 1;
+            if x > 0 {
+                queue.push_back((x - 1, y));
+            }
+            if y > 0 {
+                queue.push_back((x, y - 1));
+            }
+            queue.push_back((x + 1, y));
+            queue.push_back((x, y + 1));
+        }
+        changed
+    }
+
+    /// Paint terrain using a round brush with the given radius, centered on the given tile.
+    ///
+    /// A radius of 0 paints a single tile.
+    pub fn paint(&mut self, x: u32, y: u32, radius: u32, terrain: i8) {
+        let radius = i64::from(radius);
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+                let (tx, ty) = (i64::from(x) + dx, i64::from(y) + dy);
+                if tx < 0 || ty < 0 {
+                    continue;
+                }
+                if let Some(tile) = self.tile_mut(tx as u32, ty as u32) {
+                    tile.terrain = terrain;
+                }
+            }
+        }
+    }
+
+    /// Fill a rectangle with terrain. Both corners are inclusive.
+    ///
+    /// Parts of the rectangle that are outside the map are ignored.
+    pub fn fill_rect(&mut self, from: (u32, u32), to: (u32, u32), terrain: i8) {
+        let (x1, x2) = (
+            from.0.min(to.0),
+            from.0.max(to.0).min(self.width.saturating_sub(1)),
+        );
+        let (y1, y2) = (
+            from.1.min(to.1),
+            from.1.max(to.1).min(self.height.saturating_sub(1)),
+        );
+        for y in y1..=y2 {
+            for x in x1..=x2 {
+                if let Some(tile) = self.tile_mut(x, y) {
+                    tile.terrain = terrain;
+                }
+            }
+        }
+    }
+
+    /// Fill a polygon with terrain.
+    ///
+    /// `points` are the corners of the polygon, in tile coordinates. A tile is filled if its
+    /// center is inside the polygon.
+    pub fn fill_polygon(&mut self, points: &[(f32, f32)], terrain: i8) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let contains = |px: f32, py: f32| {
+            let mut inside = false;
+            let mut j = points.len() - 1;
+            for i in 0..points.len() {
+                let (xi, yi) = points[i];
+                let (xj, yj) = points[j];
+                if (yi > py) != (yj > py) && px < (xj - xi) * (py - yi) / (yj - yi) + xi {
+                    inside = !inside;
+                }
+                j = i;
+            }
+            inside
+        };
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if contains(x as f32 + 0.5, y as f32 + 0.5) {
+                    let index = self.offset(x, y);
+                    self.tiles[index].terrain = terrain;
+                }
+            }
+        }
+    }
+
+    /// Set the elevation of a tile, adjusting the surrounding tiles so that neighbouring tiles
+    /// differ by at most one elevation level, like the in-game editor does.
+    ///
+    /// Elevations are clamped to the range 0 through `heightmap::MAX_ELEVATION`.
+    pub fn set_elevation(&mut self, x: u32, y: u32, elevation: i8) {
+        let elevation = elevation.clamp(0, MAX_ELEVATION);
+        match self.tile_mut(x, y) {
+            Some(tile) => tile.elevation = elevation,
+            None => return,
+        }
+
+        let mut queue = VecDeque::new();
+        queue.push_back((x, y));
+        while let Some((x, y)) = queue.pop_front() {
+            let elevation = self.tiles[self.offset(x, y)].elevation;
+            let neighbours: Vec<_> = self.neighbours(x, y).collect();
+            for (nx, ny) in neighbours {
+                let index = self.offset(nx, ny);
+                let tile = &mut self.tiles[index];
+                let clamped = tile
+                    .elevation
+                    .max(elevation.saturating_sub(1))
+                    .min(elevation.saturating_add(1));
+                if clamped != tile.elevation {
+                    tile.elevation = clamped;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+
+    /// Raise the elevation of a tile by one level, adjusting the surrounding tiles.
+    pub fn raise(&mut self, x: u32, y: u32) {
+        if let Some(tile) = self.tile(x, y) {
+            let elevation = tile.elevation.saturating_add(1);
+            self.set_elevation(x, y, elevation);
+        }
+    }
+
+    /// Lower the elevation of a tile by one level, adjusting the surrounding tiles.
+    pub fn lower(&mut self, x: u32, y: u32) {
+        if let Some(tile) = self.tile(x, y) {
+            let elevation = tile.elevation.saturating_sub(1);
+            self.set_elevation(x, y, elevation);
+        }
+    }
+
+    /// Check that all neighbouring tiles differ by at most one elevation level.
+    pub fn is_elevation_valid(&self) -> bool {
+        (0..self.height).all(|y| {
+            (0..self.width).all(|x| {
+                let elevation = self.tiles[self.offset(x, y)].elevation;
+                self.neighbours(x, y).all(|(nx, ny)| {
+                    let other = self.tiles[self.offset(nx, ny)].elevation;
+                    (i16::from(other) - i16::from(elevation)).abs() <= 1
+                })
+            })
+        })
+    }
+
+    /// Smooth out the elevation of the map.
+    ///
+    /// Each tile's elevation is set to the rounded average of itself and its neighbours. Then,
+    /// tiles that are more than one level above a neighbour are lowered until the map is valid.
+    pub fn smooth_elevation(&mut self) {
+        let mut averaged = Vec::with_capacity(self.tiles.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = i32::from(self.tiles[self.offset(x, y)].elevation);
+                let mut count = 1;
+                for (nx, ny) in self.neighbours(x, y) {
+                    sum += i32::from(self.tiles[self.offset(nx, ny)].elevation);
+                    count += 1;
+                }
+                averaged.push(((sum as f32) / (count as f32)).round() as i8);
+            }
+        }
+        self.set_elevations(&averaged);
+
+        self.fix_elevation();
+    }
+
+    /// Lower tiles that are more than one level above one of their neighbours, until all
+    /// neighbouring tiles differ by at most one level.
+    pub(crate) fn fix_elevation(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let lowest = self
+                        .neighbours(x, y)
+                        .map(|(nx, ny)| self.tiles[self.offset(nx, ny)].elevation)
+                        .min();
+                    let index = self.offset(x, y);
+                    let tile = &mut self.tiles[index];
+                    if let Some(lowest) = lowest {
+                        if tile.elevation > lowest.saturating_add(1) {
+                            tile.elevation = lowest.saturating_add(1);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Map;
+    use crate::heightmap::MAX_ELEVATION;
+
+    fn map(width: u32, height: u32) -> Map {
+        Map::new(width, height)
+    }
+
+    fn count_terrain(map: &Map, terrain: i8) -> usize {
+        map.tiles().filter(|tile| tile.terrain == terrain).count()
+    }
+
+    #[test]
+    fn huge_size_without_tiles() {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 30]);
+        assert!(matches!(
+            Map::from(&mut std::io::Cursor::new(bytes)),
+            Err(crate::Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn rows_columns_and_bulk_access() {
+        let mut map = map(3, 2);
+        map.set_terrains(&[0, 1, 2, 3, 4, 5]);
+        map.set_elevations(&[1, 1, 1, 2, 2, 2]);
+        assert_eq!(map.tile(2, 1).unwrap().terrain, 5);
+        assert!(map.tile(3, 0).is_none());
+        assert_eq!(map.row(1).unwrap()[0].terrain, 3);
+        assert!(map.row(2).is_none());
+        map.row_mut(0).unwrap()[1].terrain = 9;
+        let column: Vec<_> = map.column(1).map(|tile| tile.terrain).collect();
+        assert_eq!(column, vec![9, 4]);
+        assert_eq!(map.column(3).count(), 0);
+        assert_eq!(map.rows().count(), 2);
+        assert_eq!(map.terrains().collect::<Vec<_>>(), vec![0, 9, 2, 3, 4, 5]);
+        assert_eq!(map.elevations().sum::<i8>(), 9);
+    }
+
+    #[test]
+    fn map_reserialize() {
+        let mut map = map(40, 30);
+        map.paint(10, 10, 5, 2);
+        map.set_elevation(20, 20, 3);
+        let mut out = vec![];
+        map.write_to(&mut out).unwrap();
+        assert_eq!(out.len(), 8 + 40 * 30 * 3);
+        let map2 = Map::from(&mut std::io::Cursor::new(out)).unwrap();
+        assert_eq!(map.as_tiles(), map2.as_tiles());
+    }
+
+    #[test]
+    fn flood_fill_stops_at_other_terrain() {
+        let mut map = map(8, 8);
+        for y in 0..8 {
+            map.tile_mut(4, y).unwrap().terrain = 2;
+        }
+        assert_eq!(map.flood_fill(0, 0, 1), 32);
+        assert_eq!(count_terrain(&map, 1), 32);
+        assert_eq!(map.tile(5, 0).unwrap().terrain, 0);
+        assert_eq!(map.flood_fill(0, 0, 1), 0);
+    }
+
+    #[test]
+    fn paint_with_radius() {
+        let mut map = map(10, 10);
+        map.paint(5, 5, 0, 1);
+        assert_eq!(count_terrain(&map, 1), 1);
+        map.paint(5, 5, 1, 1);
+        assert_eq!(count_terrain(&map, 1), 5);
+        map.paint(0, 0, 2, 2);
+        assert_eq!(count_terrain(&map, 2), 6);
+    }
+
+    #[test]
+    fn fill_rect_and_polygon() {
+        let mut map = map(10, 10);
+        map.fill_rect((2, 3), (0, 1), 1);
+        assert_eq!(count_terrain(&map, 1), 9);
+        map.fill_rect((8, 8), (20, 20), 2);
+        assert_eq!(count_terrain(&map, 2), 4);
+
+        let mut map = self::map(10, 10);
+        map.fill_polygon(&[(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)], 3);
+        assert_eq!(count_terrain(&map, 3), 45);
+        assert_eq!(map.tile(0, 8).unwrap().terrain, 3);
+        assert_eq!(map.tile(9, 9).unwrap().terrain, 0);
+    }
+
+    #[test]
+    fn raise_and_lower_keep_elevation_valid() {
+        let mut map = map(12, 12);
+        map.set_elevation(6, 6, 4);
+        assert!(map.is_elevation_valid());
+        assert_eq!(map.tile(6, 6).unwrap().elevation, 4);
+        assert_eq!(map.tile(5, 5).unwrap().elevation, 3);
+        assert_eq!(map.tile(3, 6).unwrap().elevation, 1);
+        assert_eq!(map.tile(0, 0).unwrap().elevation, 0);
+
+        map.raise(6, 6);
+        assert!(map.is_elevation_valid());
+        assert_eq!(map.tile(6, 6).unwrap().elevation, 5);
+
+        map.lower(7, 7);
+        map.lower(7, 7);
+        map.lower(7, 7);
+        assert!(map.is_elevation_valid());
+        assert_eq!(map.tile(7, 7).unwrap().elevation, 1);
+        assert_eq!(map.tile(6, 6).unwrap().elevation, 2);
+
+        map.set_elevation(0, 0, -3);
+        assert_eq!(map.tile(0, 0).unwrap().elevation, 0);
+        map.lower(0, 0);
+        assert_eq!(map.tile(0, 0).unwrap().elevation, 0);
+
+        map.set_elevation(0, 0, i8::MAX);
+        assert_eq!(map.tile(0, 0).unwrap().elevation, MAX_ELEVATION);
+        map.raise(0, 0);
+        assert_eq!(map.tile(0, 0).unwrap().elevation, MAX_ELEVATION);
+        assert!(map.is_elevation_valid());
+
+        // Out of range elevations read from a file do not overflow.
+        let mut map = Map::new(1, 1);
+        map.tile_mut(0, 0).unwrap().elevation = i8::MIN;
+        map.lower(0, 0);
+        assert_eq!(map.tile(0, 0).unwrap().elevation, 0);
+        map.tile_mut(0, 0).unwrap().elevation = i8::MAX;
+        map.raise(0, 0);
+        assert_eq!(map.tile(0, 0).unwrap().elevation, MAX_ELEVATION);
+    }
+
+    #[test]
+    fn smoothing_makes_elevation_valid() {
+        let mut map = map(6, 6);
+        map.tile_mut(2, 2).unwrap().elevation = 7;
+        map.tile_mut(3, 3).unwrap().elevation = 5;
+        assert!(!map.is_elevation_valid());
+        map.smooth_elevation();
+        assert!(map.is_elevation_valid());
+        assert!(map.tile(2, 2).unwrap().elevation <= 2);
     }
 }
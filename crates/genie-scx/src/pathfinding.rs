@@ -0,0 +1,572 @@
+//! Terrain passability and pathfinding over scenario maps.
+//!
+//! `TerrainPassability` classifies terrain IDs for a game version. A `PathGrid` combines that
+//! with the map and blocking objects, and answers reachability and shortest path queries on the
+//! tile grid. `Scenario::can_walk_to` and `Scenario::can_walk_between_players` answer the same
+//! questions for players' start locations.
+
+use crate::analysis::start_location;
+use crate::{Map, Scenario, ScenarioObject, VersionBundle};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// How units can move over a terrain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Passability {
+    /// Only land units can move here.
+    Land,
+    /// Only ships can move here.
+    Water,
+    /// Both land units and ships can move here, like shallows.
+    Shore,
+    /// No units can move here, like forests and cliffs.
+    Impassable,
+}
+
+/// The kind of unit that is moving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    Land,
+    Water,
+}
+
+impl Passability {
+    /// Returns whether units with the given kind of movement can move over this terrain.
+    pub fn allows(self, movement: Movement) -> bool {
+        matches!(
+            (self, movement),
+            (Passability::Shore, _)
+                | (Passability::Land, Movement::Land)
+                | (Passability::Water, Movement::Water)
+        )
+    }
+}
+
+/// Terrain IDs of water in AoE1.
+const AOE_WATER: &[u8] = &[1, 22];
+/// Terrain IDs of shallows in AoE1.
+const AOE_SHORE: &[u8] = &[4];
+/// Terrain IDs of forests in AoE1.
+const AOE_IMPASSABLE: &[u8] = &[10, 13, 19, 20];
+
+/// Terrain IDs of water in AoE2.
+const AOE2_WATER: &[u8] = &[1, 15, 22, 23, 28, 57, 58];
+/// Terrain IDs of shallows in AoE2.
+const AOE2_SHORE: &[u8] = &[4, 54];
+/// Terrain IDs of forests in AoE2. 48 and up are HD Edition forests.
+const AOE2_IMPASSABLE: &[u8] = &[10, 13, 17, 18, 19, 20, 21, 48, 49, 50, 55];
+
+/// Object type IDs of cliffs.
+const CLIFFS: &[i16] = &[264, 265, 266, 267, 268, 269, 270, 271, 272];
+/// Object type IDs of walls, palisades, and gates.
+const WALLS: &[i16] = &[
+    72, 117, 155, 370, 487, 488, 490, 491, 659, 660, 661, 662, 663, 664, 665, 666, 667, 668, 669,
+    670, 671, 672, 673, 674,
+];
+
+/// Object type IDs of gold mines.
+const GOLD: &[i16] = &[66];
+/// Object type IDs of stone mines.
+const STONE: &[i16] = &[102];
+/// Object type IDs of relics.
+const RELICS: &[i16] = &[285];
+/// Object type IDs of trees.
+const TREES: &[i16] = &[
+    284, 348, 349, 350, 351, 399, 400, 401, 402, 403, 404, 405, 406, 407, 408, 409, 410, 411, 413,
+    414, 415,
+];
+/// Object type IDs of food sources: forage bushes, deer, boar, sheep, turkeys, and fish.
+const FOOD: &[i16] = &[48, 53, 59, 65, 69, 450, 451, 455, 456, 457, 458, 594, 833];
+
+/// Kinds of resources that objects can provide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Gold,
+    Stone,
+    Wood,
+    Food,
+    Relic,
+}
+
+impl ResourceKind {
+    /// Get the kind of resource that an object type provides, if any.
+    pub fn of_object_type(object_type: i16) -> Option<Self> {
+        if GOLD.contains(&object_type) {
+            Some(ResourceKind::Gold)
+        } else if STONE.contains(&object_type) {
+            Some(ResourceKind::Stone)
+        } else if TREES.contains(&object_type) {
+            Some(ResourceKind::Wood)
+        } else if FOOD.contains(&object_type) {
+            Some(ResourceKind::Food)
+        } else if RELICS.contains(&object_type) {
+            Some(ResourceKind::Relic)
+        } else {
+            None
+        }
+    }
+}
+
+/// Maps terrain IDs to passability classes.
+#[derive(Debug, Clone)]
+pub struct TerrainPassability {
+    classes: Vec<Passability>,
+}
+
+impl TerrainPassability {
+    fn with(water: &[u8], shore: &[u8], impassable: &[u8]) -> Self {
+        let mut classes = vec![Passability::Land; 256];
+        for &terrain in water {
+            classes[usize::from(terrain)] = Passability::Water;
+        }
+        for &terrain in shore {
+            classes[usize::from(terrain)] = Passability::Shore;
+        }
+        for &terrain in impassable {
+            classes[usize::from(terrain)] = Passability::Impassable;
+        }
+        Self { classes }
+    }
+
+    /// Terrain passability in AoE1 and Rise of Rome.
+    pub fn aoe() -> Self {
+        Self::with(AOE_WATER, AOE_SHORE, AOE_IMPASSABLE)
+    }
+
+    /// Terrain passability in AoE2: AoK, AoC, UserPatch, and HD Edition.
+    pub fn aoe2() -> Self {
+        Self::with(AOE2_WATER, AOE2_SHORE, AOE2_IMPASSABLE)
+    }
+
+    /// Get the terrain passability for a scenario version.
+    pub fn for_version(version: &VersionBundle) -> Self {
+        // AoE1 scenario formats are the ones without triggers.
+        if version.has_triggers() {
+            Self::aoe2()
+        } else {
+            Self::aoe()
+        }
+    }
+
+    /// Get the passability class of a terrain.
+    pub fn class(&self, terrain: i8) -> Passability {
+        self.classes[usize::from(terrain as u8)]
+    }
+
+    /// Change the passability class of a terrain, for example to support mods.
+    pub fn set_class(&mut self, terrain: i8, class: Passability) {
+        self.classes[usize::from(terrain as u8)] = class;
+    }
+}
+
+/// Returns whether an object type blocks movement, like trees, cliffs, mines, and walls.
+pub fn is_obstacle(object_type: i16) -> bool {
+    CLIFFS.contains(&object_type)
+        || WALLS.contains(&object_type)
+        || matches!(
+            ResourceKind::of_object_type(object_type),
+            Some(ResourceKind::Gold) | Some(ResourceKind::Stone) | Some(ResourceKind::Wood)
+        )
+}
+
+/// A node in the A* open list.
+#[derive(PartialEq)]
+struct Node {
+    estimate: f32,
+    index: usize,
+}
+
+impl Eq for Node {}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so the BinaryHeap pops the lowest estimate first.
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A grid of tile passability, for pathfinding.
+#[derive(Debug, Clone)]
+pub struct PathGrid {
+    width: u32,
+    height: u32,
+    classes: Vec<Passability>,
+    blocked: Vec<bool>,
+}
+
+impl PathGrid {
+    /// Create a grid for a map, without any blocking objects.
+    pub fn new(map: &Map, passability: &TerrainPassability) -> Self {
+        Self {
+            width: map.width(),
+            height: map.height(),
+            classes: map.terrains().map(|t| passability.class(t)).collect(),
+            blocked: vec![false; map.as_tiles().len()],
+        }
+    }
+
+    /// Create a grid for a scenario, using the terrain passability for its version, and with
+    /// the tiles of all obstacle objects blocked.
+    pub fn from_scenario(scenario: &Scenario) -> Self {
+        let passability = TerrainPassability::for_version(scenario.version());
+        let mut grid = Self::new(scenario.map(), &passability);
+        grid.block_objects(scenario.objects().filter(|o| is_obstacle(o.object_type)));
+        grid
+    }
+
+    /// Mark the tiles that the given objects are on as blocked.
+    pub fn block_objects<'a>(&mut self, objects: impl IntoIterator<Item = &'a ScenarioObject>) {
+        for object in objects {
+            let (x, y, _) = object.position;
+            if x >= 0.0 && y >= 0.0 {
+                self.set_blocked(x as u32, y as u32, true);
+            }
+        }
+    }
+
+    /// Mark a tile as blocked or not blocked.
+    pub fn set_blocked(&mut self, x: u32, y: u32, blocked: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.blocked[index] = blocked;
+        }
+    }
+
+    /// Get the width of the grid.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the grid.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the passability class of the terrain of a tile.
+    pub fn class(&self, x: u32, y: u32) -> Option<Passability> {
+        self.index(x, y).map(|index| self.classes[index])
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    fn coords(&self, index: usize) -> (u32, u32) {
+        let width = self.width as usize;
+        ((index % width) as u32, (index / width) as u32)
+    }
+
+    fn passable_index(&self, index: usize, movement: Movement) -> bool {
+        !self.blocked[index] && self.classes[index].allows(movement)
+    }
+
+    /// Returns whether a unit with the given movement can be on a tile.
+    pub fn is_passable(&self, x: u32, y: u32, movement: Movement) -> bool {
+        matches!(self.index(x, y), Some(index) if self.passable_index(index, movement))
+    }
+
+    /// Get the tiles that can be reached from a tile in one step, with the cost of the step.
+    ///
+    /// Diagonal steps are only allowed if both adjacent orthogonal tiles are passable, so units
+    /// can not squeeze between two diagonally placed obstacles. The `goal` tile is treated as
+    /// passable if it is only blocked by an object; its terrain must still allow the movement.
+    fn steps(&self, index: usize, movement: Movement, goal: Option<usize>) -> Vec<(usize, f32)> {
+        let (x, y) = self.coords(index);
+        let (x, y) = (i64::from(x), i64::from(y));
+        let mut steps = Vec::with_capacity(8);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let passable = |x: i64, y: i64| {
+                    if x < 0 || y < 0 {
+                        return false;
+                    }
+                    match self.index(x as u32, y as u32) {
+                        Some(index) if Some(index) == goal => self.classes[index].allows(movement),
+                        Some(index) => self.passable_index(index, movement),
+                        None => false,
+                    }
+                };
+                if !passable(x + dx, y + dy) {
+                    continue;
+                }
+                if dx != 0 && dy != 0 && !(passable(x + dx, y) && passable(x, y + dy)) {
+                    continue;
+                }
+                let next = self.index((x + dx) as u32, (y + dy) as u32).unwrap();
+                let cost = if dx != 0 && dy != 0 {
+                    std::f32::consts::SQRT_2
+                } else {
+                    1.0
+                };
+                steps.push((next, cost));
+            }
+        }
+        steps
+    }
+
+    /// Find all tiles that can be reached from a tile.
+    ///
+    /// The start tile itself is always included, even if it is blocked, so this can be used
+    /// starting from a building. Returns a vector with one entry per tile, row by row.
+    pub fn reachable_from(&self, from: (u32, u32), movement: Movement) -> Vec<bool> {
+        let mut visited = vec![false; self.classes.len()];
+        let start = match self.index(from.0, from.1) {
+            Some(index) => index,
+            None => return visited,
+        };
+
+        let mut queue = VecDeque::new();
+        visited[start] = true;
+        queue.push_back(start);
+        while let Some(index) = queue.pop_front() {
+            for (next, _) in self.steps(index, movement, None) {
+                if !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        visited
+    }
+
+    /// Returns whether a unit can move from one tile to another.
+    ///
+    /// The start and goal tiles may be blocked by objects, so this can be used to check whether
+    /// a unit can walk from one building to another, or pick up a relic. The terrain of the goal
+    /// tile must allow the movement.
+    pub fn is_reachable(&self, from: (u32, u32), to: (u32, u32), movement: Movement) -> bool {
+        self.find_path(from, to, movement).is_some()
+    }
+
+    /// Find the shortest path between two tiles using A*.
+    ///
+    /// Returns the tiles on the path, including the start and goal tiles, or None if the goal
+    /// can not be reached. The start and goal tiles may be blocked by objects, but the terrain of
+    /// the goal tile must allow the movement.
+    pub fn find_path(
+        &self,
+        from: (u32, u32),
+        to: (u32, u32),
+        movement: Movement,
+    ) -> Option<Vec<(u32, u32)>> {
+        let start = self.index(from.0, from.1)?;
+        let goal = self.index(to.0, to.1)?;
+
+        let heuristic = |index: usize| {
+            let (x, y) = self.coords(index);
+            let dx = (f64::from(x) - f64::from(to.0)).abs() as f32;
+            let dy = (f64::from(y) - f64::from(to.1)).abs() as f32;
+            // Octile distance.
+            dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy)
+        };
+
+        let mut costs = vec![f32::INFINITY; self.classes.len()];
+        let mut came_from = vec![usize::MAX; self.classes.len()];
+        let mut open = BinaryHeap::new();
+        costs[start] = 0.0;
+        open.push(Node {
+            estimate: heuristic(start),
+            index: start,
+        });
+
+        while let Some(Node { index, estimate }) = open.pop() {
+            if index == goal {
+                let mut path = vec![self.coords(goal)];
+                let mut current = goal;
+                while current != start {
+                    current = came_from[current];
+                    path.push(self.coords(current));
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if estimate > costs[index] + heuristic(index) {
+                // Stale entry, a cheaper path to this tile was already found.
+                continue;
+            }
+
+            // The goal tile may be blocked by an object, for example if it contains a relic.
+            let steps = self.steps(index, movement, Some(goal));
+            for (next, step_cost) in steps {
+                let cost = costs[index] + step_cost;
+                if cost < costs[next] {
+                    costs[next] = cost;
+                    came_from[next] = index;
+                    open.push(Node {
+                        estimate: cost + heuristic(next),
+                        index: next,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Scenario {
+    /// Create a pathfinding grid for this scenario's map, with the tiles of all obstacle objects
+    /// blocked.
+    pub fn path_grid(&self) -> PathGrid {
+        PathGrid::from_scenario(self)
+    }
+
+    /// Check whether land units of a player can walk from their start location to a tile, for
+    /// example to pick up a relic.
+    ///
+    /// `player` is the player index, from 0 for player 1. Returns None if the player does not
+    /// have a start location.
+    pub fn can_walk_to(&self, player: usize, to: (u32, u32)) -> Option<bool> {
+        let ((x, y), _) = start_location(self, player)?;
+        Some(
+            self.path_grid()
+                .is_reachable((x as u32, y as u32), to, Movement::Land),
+        )
+    }
+
+    /// Check whether land units of a player can walk to the start location of another player.
+    ///
+    /// Players are indices, from 0 for player 1. Returns None if either player does not have a
+    /// start location.
+    pub fn can_walk_between_players(&self, from: usize, to: usize) -> Option<bool> {
+        let ((x, y), _) = start_location(self, to)?;
+        self.can_walk_to(from, (x as u32, y as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: &[&str]) -> PathGrid {
+        let mut map = Map::new(rows[0].len() as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                map.tile_mut(x as u32, y as u32).unwrap().terrain = match c {
+                    '~' => 1,
+                    ':' => 4,
+                    '#' => 10,
+                    _ => 0,
+                };
+            }
+        }
+        PathGrid::new(&map, &TerrainPassability::aoe2())
+    }
+
+    #[test]
+    fn classifies_terrain() {
+        let passability = TerrainPassability::aoe2();
+        assert_eq!(passability.class(0), Passability::Land);
+        assert_eq!(passability.class(22), Passability::Water);
+        assert_eq!(passability.class(4), Passability::Shore);
+        assert_eq!(passability.class(20), Passability::Impassable);
+        assert!(Passability::Shore.allows(Movement::Water));
+        assert!(!Passability::Land.allows(Movement::Water));
+    }
+
+    #[test]
+    fn finds_paths_around_forests() {
+        let grid = grid(&[
+            ".....", //
+            "####.", //
+            ".....", //
+            ".####", //
+            ".....",
+        ]);
+        let path = grid
+            .find_path((0, 0), (4, 4), Movement::Land)
+            .expect("no path");
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(4, 4)));
+        assert!(path.contains(&(4, 1)));
+        assert!(path.contains(&(0, 3)));
+        assert!(path
+            .iter()
+            .all(|&(x, y)| grid.is_passable(x, y, Movement::Land)));
+    }
+
+    #[test]
+    fn water_separates_land() {
+        let mut grid = grid(&[
+            "..~..", //
+            "..~..", //
+            "..:..", //
+            "..~..",
+        ]);
+        assert!(grid.is_reachable((0, 0), (4, 0), Movement::Land));
+        assert!(grid.is_reachable((2, 0), (2, 3), Movement::Water));
+        assert!(!grid.is_reachable((0, 0), (2, 0), Movement::Water));
+        grid.set_blocked(2, 2, true);
+        assert!(!grid.is_reachable((0, 0), (4, 0), Movement::Land));
+        assert!(!grid.is_reachable((2, 0), (2, 3), Movement::Water));
+        assert_eq!(
+            grid.reachable_from((0, 0), Movement::Land)
+                .iter()
+                .filter(|r| **r)
+                .count(),
+            8
+        );
+    }
+
+    #[test]
+    fn no_corner_cutting_but_blocked_goals_are_reachable() {
+        let mut grid = grid(&[
+            ".#", //
+            "#.",
+        ]);
+        assert!(!grid.is_reachable((0, 0), (1, 1), Movement::Land));
+        grid = self::grid(&["...", "...", "..."]);
+        grid.set_blocked(2, 2, true);
+        assert!(grid.is_reachable((0, 0), (2, 2), Movement::Land));
+    }
+
+    #[test]
+    fn water_goals_are_not_reachable_over_land() {
+        let mut grid = grid(&["..~"]);
+        assert!(!grid.is_reachable((0, 0), (2, 0), Movement::Land));
+        assert!(grid.find_path((0, 0), (2, 0), Movement::Land).is_none());
+        // An object on the water does not make it a valid goal either.
+        grid.set_blocked(2, 0, true);
+        assert!(!grid.is_reachable((0, 0), (2, 0), Movement::Land));
+        assert!(grid.is_reachable((0, 0), (1, 0), Movement::Land));
+    }
+
+    #[test]
+    fn walk_between_players_on_real_world_map() {
+        let mut f = std::fs::File::open("test/scenarios/real_world_amazon.scx").unwrap();
+        let scenario = Scenario::from(&mut f).expect("failed to read");
+        assert_eq!(scenario.can_walk_between_players(0, 1), Some(true));
+        assert_eq!(scenario.can_walk_between_players(1, 7), Some(true));
+        assert_eq!(scenario.can_walk_between_players(0, 15), None);
+        // A water tile away from the shore.
+        assert_eq!(scenario.can_walk_to(0, (135, 41)), Some(false));
+
+        // The start locations of players 1, 2, and 8. Players 2 and 8 are only 65 tiles apart,
+        // but obstacles between them force a long detour.
+        let (p1, p2, p8) = ((55, 41), (205, 137), (205, 72));
+        let grid = scenario.path_grid();
+        let path = grid.find_path(p2, p8, Movement::Land).expect("no path");
+        assert_eq!(path.len(), 446);
+        assert_eq!(
+            grid.find_path(p1, p2, Movement::Land)
+                .map(|path| path.len()),
+            Some(324)
+        );
+    }
+}
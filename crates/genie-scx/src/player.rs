@@ -78,12 +78,12 @@ impl PlayerStartResources {
 pub struct ScenarioPlayerData {
     name: Option<String>,
     view: (f32, f32),
-    location: (i16, i16),
+    pub(crate) location: (i16, i16),
     allied_victory: bool,
     relations: Vec<i8>,
     unit_diplomacy: Vec<i32>,
     color: Option<i32>,
-    victory: VictoryConditions,
+    pub(crate) victory: VictoryConditions,
 }
 
 impl ScenarioPlayerData {
@@ -0,0 +1,444 @@
+//! A search index over many scenario files.
+//!
+//! `SearchIndex` stores a compact summary of each scenario: the object types each player owns, a
+//! terrain histogram, the trigger effect types, and the words in its messages and triggers.
+//! `Query` filters the indexed scenarios using that summary, without reading the files again.
+//!
+//! ## Usage
+//!
+//! ```rust,ignore
+//! use genie_scx::search::{Query, SearchIndex};
+//! let mut index = SearchIndex::new();
+//! index.add("Jeremiah Johnson.scx", &scenario);
+//! // Scenarios with Jaguar Warriors owned by Gaia, that mention wonders.
+//! let query = Query::new().object(725, Some(0)).text("wonder");
+//! for entry in index.search(&query) {
+//!     println!("{}", entry.name());
+//! }
+//! ```
+
+use crate::Scenario;
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+
+/// Identifies search index files.
+const MAGIC: &[u8; 4] = b"SIDX";
+/// The version of the search index file format.
+const FORMAT_VERSION: u32 = 1;
+
+/// The summary of one scenario in a search index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    name: String,
+    /// Object type counts for each player, sorted by object type. 0 is Gaia.
+    objects: Vec<Vec<(i16, u32)>>,
+    /// Tile counts for each terrain, sorted by terrain ID.
+    terrains: Vec<(i8, u32)>,
+    /// Effect counts for each effect type, sorted by effect type.
+    effects: Vec<(i32, u32)>,
+    /// Lowercase words in the scenario's text, sorted.
+    words: Vec<String>,
+}
+
+/// Count the occurrences of each value, sorted by value.
+fn histogram<T: Ord>(values: impl Iterator<Item = T>) -> Vec<(T, u32)> {
+    let mut counts = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    counts.into_iter().collect()
+}
+
+/// Look up a value in a histogram.
+fn count<T: Ord>(histogram: &[(T, u32)], value: &T) -> u32 {
+    match histogram.binary_search_by(|(v, _)| v.cmp(value)) {
+        Ok(index) => histogram[index].1,
+        Err(_) => 0,
+    }
+}
+
+/// Split text into lowercase words.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+impl IndexEntry {
+    /// Summarize a scenario.
+    pub fn new(name: impl Into<String>, scenario: &Scenario) -> Self {
+        let objects = scenario
+            .format
+            .player_objects
+            .iter()
+            .map(|objects| histogram(objects.iter().map(|object| object.object_type)))
+            .collect();
+        let terrains = histogram(scenario.map().terrains());
+
+        let mut text = BTreeSet::new();
+        for message in scenario.format.tribe_scen.messages() {
+            text.extend(words(message));
+        }
+        let mut effects = vec![];
+        if let Some(triggers) = scenario.triggers() {
+            for trigger in triggers.triggers() {
+                for string in trigger.name().iter().chain(trigger.description().iter()) {
+                    text.extend(words(string));
+                }
+                for effect in trigger.effects() {
+                    effects.push(effect.effect_type());
+                    if let Some(chat_text) = effect.chat_text() {
+                        text.extend(words(chat_text));
+                    }
+                }
+            }
+        }
+
+        Self {
+            name: name.into(),
+            objects,
+            terrains,
+            effects: histogram(effects.into_iter()),
+            words: text.into_iter().collect(),
+        }
+    }
+
+    /// Get the name of the scenario, usually its file path.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Count the objects of a type. `owner` is the player that owns the objects, where 0 is Gaia
+    /// and 1 is player 1, or None to count objects of all players.
+    pub fn object_count(&self, object_type: i16, owner: Option<usize>) -> u32 {
+        match owner {
+            Some(owner) => self
+                .objects
+                .get(owner)
+                .map(|objects| count(objects, &object_type))
+                .unwrap_or(0),
+            None => self
+                .objects
+                .iter()
+                .map(|objects| count(objects, &object_type))
+                .sum(),
+        }
+    }
+
+    /// Count the tiles of a terrain.
+    pub fn terrain_count(&self, terrain: i8) -> u32 {
+        count(&self.terrains, &terrain)
+    }
+
+    /// Count the trigger effects of a type.
+    pub fn effect_count(&self, effect_type: i32) -> u32 {
+        count(&self.effects, &effect_type)
+    }
+
+    /// Get the lowercase words that appear in the scenario's messages and triggers, sorted.
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// Returns whether a word appears in the scenario's text. Matching is case-insensitive, and
+    /// words that start with `word` also match, so "wonder" matches "Wonders".
+    pub fn has_word(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        let index = match self.words.binary_search(&word) {
+            Ok(_) => return true,
+            Err(index) => index,
+        };
+        matches!(self.words.get(index), Some(next) if next.starts_with(&word))
+    }
+
+    /// Returns whether this scenario matches all the filters in a query.
+    pub fn matches(&self, query: &Query) -> bool {
+        query
+            .objects
+            .iter()
+            .all(|&(object_type, owner)| self.object_count(object_type, owner) > 0)
+            && query
+                .terrains
+                .iter()
+                .all(|&terrain| self.terrain_count(terrain) > 0)
+            && query
+                .effects
+                .iter()
+                .all(|&effect_type| self.effect_count(effect_type) > 0)
+            && query.words.iter().all(|word| self.has_word(word))
+    }
+
+    fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let name = read_string(input)?;
+        let num_players = input.read_u32::<LE>()?;
+        let mut objects = vec![];
+        for _ in 0..num_players {
+            let num_types = input.read_u32::<LE>()?;
+            let mut counts = vec![];
+            for _ in 0..num_types {
+                counts.push((input.read_i16::<LE>()?, input.read_u32::<LE>()?));
+            }
+            objects.push(counts);
+        }
+        let num_terrains = input.read_u32::<LE>()?;
+        let mut terrains = vec![];
+        for _ in 0..num_terrains {
+            terrains.push((input.read_i8()?, input.read_u32::<LE>()?));
+        }
+        let num_effects = input.read_u32::<LE>()?;
+        let mut effects = vec![];
+        for _ in 0..num_effects {
+            effects.push((input.read_i32::<LE>()?, input.read_u32::<LE>()?));
+        }
+        let num_words = input.read_u32::<LE>()?;
+        let mut words = vec![];
+        for _ in 0..num_words {
+            words.push(read_string(input)?);
+        }
+        Ok(Self {
+            name,
+            objects,
+            terrains,
+            effects,
+            words,
+        })
+    }
+
+    fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        write_string(output, &self.name)?;
+        output.write_u32::<LE>(self.objects.len() as u32)?;
+        for counts in &self.objects {
+            output.write_u32::<LE>(counts.len() as u32)?;
+            for &(object_type, count) in counts {
+                output.write_i16::<LE>(object_type)?;
+                output.write_u32::<LE>(count)?;
+            }
+        }
+        output.write_u32::<LE>(self.terrains.len() as u32)?;
+        for &(terrain, count) in &self.terrains {
+            output.write_i8(terrain)?;
+            output.write_u32::<LE>(count)?;
+        }
+        output.write_u32::<LE>(self.effects.len() as u32)?;
+        for &(effect_type, count) in &self.effects {
+            output.write_i32::<LE>(effect_type)?;
+            output.write_u32::<LE>(count)?;
+        }
+        output.write_u32::<LE>(self.words.len() as u32)?;
+        for word in &self.words {
+            write_string(output, word)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_string<R: Read>(input: &mut R) -> io::Result<String> {
+    let len = input.read_u32::<LE>()?;
+    // The length comes from the file, so only allocate as much as is actually there.
+    let mut bytes = vec![];
+    input.take(u64::from(len)).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_string<W: Write>(output: &mut W, string: &str) -> io::Result<()> {
+    output.write_u32::<LE>(string.len() as u32)?;
+    output.write_all(string.as_bytes())
+}
+
+/// A set of filters for searching an index. A scenario matches if it matches all filters.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    objects: Vec<(i16, Option<usize>)>,
+    terrains: Vec<i8>,
+    effects: Vec<i32>,
+    words: Vec<String>,
+}
+
+impl Query {
+    /// Create a query that matches every scenario.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match scenarios containing an object of this type. `owner` is the player that must own
+    /// the object, where 0 is Gaia and 1 is player 1, or None to allow any owner.
+    pub fn object(mut self, object_type: i16, owner: Option<usize>) -> Self {
+        self.objects.push((object_type, owner));
+        self
+    }
+
+    /// Only match scenarios whose map uses this terrain.
+    pub fn terrain(mut self, terrain: i8) -> Self {
+        self.terrains.push(terrain);
+        self
+    }
+
+    /// Only match scenarios containing a trigger effect of this type.
+    pub fn effect(mut self, effect_type: i32) -> Self {
+        self.effects.push(effect_type);
+        self
+    }
+
+    /// Only match scenarios whose messages or triggers contain all the words in `text`.
+    pub fn text(mut self, text: &str) -> Self {
+        self.words.extend(words(text));
+        self
+    }
+}
+
+/// A search index over many scenarios.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl SearchIndex {
+    /// Create an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read an index file.
+    pub fn from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a scenario search index",
+            ));
+        }
+        let version = input.read_u32::<LE>()?;
+        if version != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported search index version {}", version),
+            ));
+        }
+        let num_entries = input.read_u32::<LE>()?;
+        let mut entries = vec![];
+        for _ in 0..num_entries {
+            entries.push(IndexEntry::read_from(input)?);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Write the index to a file.
+    pub fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        output.write_all(MAGIC)?;
+        output.write_u32::<LE>(FORMAT_VERSION)?;
+        output.write_u32::<LE>(self.entries.len() as u32)?;
+        for entry in &self.entries {
+            entry.write_to(output)?;
+        }
+        Ok(())
+    }
+
+    /// Add a scenario to the index. If a scenario with the same name was already indexed, it is
+    /// replaced.
+    pub fn add(&mut self, name: impl Into<String>, scenario: &Scenario) {
+        let entry = IndexEntry::new(name, scenario);
+        self.entries.retain(|existing| existing.name != entry.name);
+        self.entries.push(entry);
+    }
+
+    /// Remove a scenario from the index. Returns whether it was indexed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.len() != len
+    }
+
+    /// Get the number of indexed scenarios.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the indexed scenarios.
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.iter()
+    }
+
+    /// Find the scenarios that match a query.
+    pub fn search<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = &'a IndexEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.matches(query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EffectType, Trigger, TriggerEffect};
+    use std::fs::File;
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::new();
+        for name in &["real_world_amazon.scx", "Age of Heroes b1-3-5.scx"] {
+            let mut f = File::open(format!("test/scenarios/{}", name)).unwrap();
+            let mut scenario = Scenario::from(&mut f).expect("failed to read");
+            if *name == "real_world_amazon.scx" {
+                scenario.add_object(0, crate::ScenarioObject::new(725, (1.5, 1.5)));
+                let mut trigger = Trigger::new("Build a Wonder");
+                let mut effect = TriggerEffect::new(EffectType::SendChat);
+                effect.set_chat_text(Some("The Jaguars await!".to_string()));
+                trigger.push_effect(effect);
+                scenario.triggers_mut().unwrap().push(trigger);
+            }
+            index.add(*name, &scenario);
+        }
+        index
+    }
+
+    fn names<'a>(index: &'a SearchIndex, query: &'a Query) -> Vec<&'a str> {
+        index.search(query).map(|entry| entry.name()).collect()
+    }
+
+    #[test]
+    fn search_scenarios() {
+        let index = index();
+        assert_eq!(index.len(), 2);
+        assert_eq!(names(&index, &Query::new()).len(), 2);
+
+        let jaguars = Query::new().object(725, Some(0));
+        assert_eq!(names(&index, &jaguars), vec!["real_world_amazon.scx"]);
+        assert!(names(&index, &Query::new().object(725, Some(1))).is_empty());
+
+        let wonder = Query::new().text("WONDER jaguar");
+        assert_eq!(names(&index, &wonder), vec!["real_world_amazon.scx"]);
+
+        let chat = Query::new().effect(i32::from(EffectType::SendChat));
+        assert!(names(&index, &chat).contains(&"real_world_amazon.scx"));
+    }
+
+    #[test]
+    fn index_round_trips() {
+        let index = index();
+        let mut bytes = vec![];
+        index.write_to(&mut bytes).unwrap();
+        let read = SearchIndex::from(&mut &bytes[..]).unwrap();
+        assert_eq!(read, index);
+        assert!(SearchIndex::from(&mut &b"nope"[..]).is_err());
+    }
+
+    #[test]
+    fn huge_string_length() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(b"short");
+        let err = SearchIndex::from(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -3,7 +3,316 @@ use crate::Result;
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use std::io::{Read, Write};
 
-#[derive(Debug)]
+/// Could not parse a condition type because given number is an unknown condition type ID.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseConditionTypeError(i32);
+
+impl std::fmt::Display for ParseConditionTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unknown trigger condition type {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseConditionTypeError {}
+
+/// A trigger condition type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionType {
+    BringObjectToArea = 1,
+    BringObjectToObject = 2,
+    OwnObjects = 3,
+    OwnFewerObjects = 4,
+    ObjectsInArea = 5,
+    DestroyObject = 6,
+    CaptureObject = 7,
+    AccumulateAttribute = 8,
+    ResearchTechnology = 9,
+    Timer = 10,
+    ObjectSelected = 11,
+    AISignal = 12,
+    PlayerDefeated = 13,
+    ObjectHasTarget = 14,
+    ObjectVisible = 15,
+    ObjectNotVisible = 16,
+    ResearchingTechnology = 17,
+    UnitsGarrisoned = 18,
+    DifficultyLevel = 19,
+}
+
+impl ConditionType {
+    pub fn try_from(n: i32) -> std::result::Result<Self, ParseConditionTypeError> {
+        use ConditionType::*;
+        let condition_type = match n {
+            1 => BringObjectToArea,
+            2 => BringObjectToObject,
+            3 => OwnObjects,
+            4 => OwnFewerObjects,
+            5 => ObjectsInArea,
+            6 => DestroyObject,
+            7 => CaptureObject,
+            8 => AccumulateAttribute,
+            9 => ResearchTechnology,
+            10 => Timer,
+            11 => ObjectSelected,
+            12 => AISignal,
+            13 => PlayerDefeated,
+            14 => ObjectHasTarget,
+            15 => ObjectVisible,
+            16 => ObjectNotVisible,
+            17 => ResearchingTechnology,
+            18 => UnitsGarrisoned,
+            19 => DifficultyLevel,
+            n => return Err(ParseConditionTypeError(n)),
+        };
+        Ok(condition_type)
+    }
+}
+
+impl From<ConditionType> for i32 {
+    fn from(condition_type: ConditionType) -> i32 {
+        condition_type as i32
+    }
+}
+
+/// Could not parse an effect type because given number is an unknown effect type ID.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseEffectTypeError(i32);
+
+impl std::fmt::Display for ParseEffectTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unknown trigger effect type {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseEffectTypeError {}
+
+/// A trigger effect type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectType {
+    ChangeDiplomacy = 1,
+    ResearchTechnology = 2,
+    SendChat = 3,
+    PlaySound = 4,
+    SendTribute = 5,
+    UnlockGate = 6,
+    LockGate = 7,
+    ActivateTrigger = 8,
+    DeactivateTrigger = 9,
+    AIScriptGoal = 10,
+    CreateObject = 11,
+    TaskObject = 12,
+    DeclareVictory = 13,
+    KillObject = 14,
+    RemoveObject = 15,
+    ChangeView = 16,
+    Unload = 17,
+    ChangeOwnership = 18,
+    Patrol = 19,
+    DisplayInstructions = 20,
+    ClearInstructions = 21,
+    FreezeUnit = 22,
+    UseAdvancedButtons = 23,
+    DamageObject = 24,
+    PlaceFoundation = 25,
+    ChangeObjectName = 26,
+    ChangeObjectHP = 27,
+    ChangeObjectAttack = 28,
+    StopUnit = 29,
+    /// Order the selected objects to move to a location, attacking enemies on the way.
+    /// (UserPatch 1.5, HD Edition)
+    AttackMove = 30,
+    /// Change the armor of the selected objects by `amount`. (UserPatch 1.5, HD Edition)
+    ChangeObjectArmor = 31,
+    /// Change the range of the selected objects by `amount`. (UserPatch 1.5, HD Edition)
+    ChangeObjectRange = 32,
+    /// Change the movement speed of the selected objects by `amount`. (UserPatch 1.5, HD
+    /// Edition)
+    ChangeObjectSpeed = 33,
+    /// Heal the selected objects by `amount` hit points. (UserPatch 1.5, HD Edition)
+    HealObject = 34,
+    /// Move the selected objects to a location. (UserPatch 1.5, HD Edition)
+    TeleportObject = 35,
+    /// Change the stance of the selected objects. (UserPatch 1.5, HD Edition)
+    ChangeObjectStance = 36,
+    /// Enable or disable training or building an object type. (UserPatch 1.5)
+    ///
+    /// The `amount` property is 0 to disable the object, or 1 to enable it.
+    EnableDisableObject = 38,
+    /// Enable or disable researching a technology. (UserPatch 1.5)
+    ///
+    /// The `amount` property is 0 to disable the technology, or 1 to enable it.
+    EnableDisableTechnology = 39,
+    /// Change an attribute of the selected objects. (UserPatch 1.5)
+    ///
+    /// The `resource` property holds the `ObjectAttribute` to change, and the `amount` property
+    /// holds the new value.
+    ChangeObjectAttribute = 40,
+}
+
+impl EffectType {
+    /// Returns whether this effect type is only understood by UserPatch 1.5 and not by vanilla
+    /// AoC.
+    ///
+    /// Some of these effects also exist in HD Edition, using the same IDs.
+    pub fn is_userpatch(self) -> bool {
+        use EffectType::*;
+        matches!(
+            self,
+            AttackMove
+                | ChangeObjectArmor
+                | ChangeObjectRange
+                | ChangeObjectSpeed
+                | HealObject
+                | TeleportObject
+                | ChangeObjectStance
+                | EnableDisableObject
+                | EnableDisableTechnology
+                | ChangeObjectAttribute
+        )
+    }
+}
+
+impl EffectType {
+    pub fn try_from(n: i32) -> std::result::Result<Self, ParseEffectTypeError> {
+        use EffectType::*;
+        let effect_type = match n {
+            1 => ChangeDiplomacy,
+            2 => ResearchTechnology,
+            3 => SendChat,
+            4 => PlaySound,
+            5 => SendTribute,
+            6 => UnlockGate,
+            7 => LockGate,
+            8 => ActivateTrigger,
+            9 => DeactivateTrigger,
+            10 => AIScriptGoal,
+            11 => CreateObject,
+            12 => TaskObject,
+            13 => DeclareVictory,
+            14 => KillObject,
+            15 => RemoveObject,
+            16 => ChangeView,
+            17 => Unload,
+            18 => ChangeOwnership,
+            19 => Patrol,
+            20 => DisplayInstructions,
+            21 => ClearInstructions,
+            22 => FreezeUnit,
+            23 => UseAdvancedButtons,
+            24 => DamageObject,
+            25 => PlaceFoundation,
+            26 => ChangeObjectName,
+            27 => ChangeObjectHP,
+            28 => ChangeObjectAttack,
+            29 => StopUnit,
+            30 => AttackMove,
+            31 => ChangeObjectArmor,
+            32 => ChangeObjectRange,
+            33 => ChangeObjectSpeed,
+            34 => HealObject,
+            35 => TeleportObject,
+            36 => ChangeObjectStance,
+            38 => EnableDisableObject,
+            39 => EnableDisableTechnology,
+            40 => ChangeObjectAttribute,
+            n => return Err(ParseEffectTypeError(n)),
+        };
+        Ok(effect_type)
+    }
+}
+
+impl From<EffectType> for i32 {
+    fn from(effect_type: EffectType) -> i32 {
+        effect_type as i32
+    }
+}
+
+/// Could not parse an object attribute because given number is an unknown attribute ID.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseObjectAttributeError(i32);
+
+impl std::fmt::Display for ParseObjectAttributeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid object attribute {} (must be 0-6 or 8-23)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseObjectAttributeError {}
+
+/// An object attribute that can be changed by the UserPatch "Change Object Attribute" effect.
+///
+/// The IDs are the same as the ones used by technology effects in the game data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectAttribute {
+    HitPoints = 0,
+    LineOfSight = 1,
+    GarrisonCapacity = 2,
+    SizeX = 3,
+    SizeY = 4,
+    MovementSpeed = 5,
+    RotationSpeed = 6,
+    Armor = 8,
+    Attack = 9,
+    AttackReloadTime = 10,
+    AccuracyPercent = 11,
+    MaxRange = 12,
+    WorkRate = 13,
+    CarryCapacity = 14,
+    BaseArmor = 15,
+    ProjectileUnit = 16,
+    IconGraphicAngle = 17,
+    TerrainDefenseBonus = 18,
+    SmartProjectiles = 19,
+    MinRange = 20,
+    ResourceStorage = 21,
+    BlastWidth = 22,
+    SearchRadius = 23,
+}
+
+impl ObjectAttribute {
+    pub fn try_from(n: i32) -> std::result::Result<Self, ParseObjectAttributeError> {
+        use ObjectAttribute::*;
+        let attribute = match n {
+            0 => HitPoints,
+            1 => LineOfSight,
+            2 => GarrisonCapacity,
+            3 => SizeX,
+            4 => SizeY,
+            5 => MovementSpeed,
+            6 => RotationSpeed,
+            8 => Armor,
+            9 => Attack,
+            10 => AttackReloadTime,
+            11 => AccuracyPercent,
+            12 => MaxRange,
+            13 => WorkRate,
+            14 => CarryCapacity,
+            15 => BaseArmor,
+            16 => ProjectileUnit,
+            17 => IconGraphicAngle,
+            18 => TerrainDefenseBonus,
+            19 => SmartProjectiles,
+            20 => MinRange,
+            21 => ResourceStorage,
+            22 => BlastWidth,
+            23 => SearchRadius,
+            n => return Err(ParseObjectAttributeError(n)),
+        };
+        Ok(attribute)
+    }
+}
+
+impl From<ObjectAttribute> for i32 {
+    fn from(attribute: ObjectAttribute) -> i32 {
+        attribute as i32
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TriggerCondition {
     condition_type: i32,
     properties: Vec<i32>,
@@ -32,6 +341,14 @@ impl TriggerCondition {
         })
     }
 
+    /// Create a new trigger condition of the given type, with all properties unset.
+    pub fn new(condition_type: ConditionType) -> Self {
+        Self {
+            condition_type: condition_type.into(),
+            properties: vec![-1; 18],
+        }
+    }
+
     pub fn write_to<W: Write>(&self, output: &mut W, version: f64) -> Result<()> {
         output.write_i32::<LE>(self.condition_type)?;
         if version > 1.0 {
@@ -48,6 +365,11 @@ impl TriggerCondition {
         Ok(())
     }
 
+    /// Get the type ID of this condition.
+    pub fn condition_type(&self) -> i32 {
+        self.condition_type
+    }
+
     pub fn amount(&self) -> i32 {
         self.properties[0]
     }
@@ -169,7 +491,7 @@ impl TriggerCondition {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TriggerEffect {
     effect_type: i32,
     properties: Vec<i32>,
@@ -219,16 +541,96 @@ impl TriggerEffect {
         })
     }
 
-    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<()> {
+    /// Create a new trigger effect of the given type, with all properties unset.
+    pub fn new(effect_type: EffectType) -> Self {
+        Self {
+            effect_type: effect_type.into(),
+            properties: vec![-1; 24],
+            chat_text: None,
+            audio_file: None,
+            objects: vec![],
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, output: &mut W, version: f64) -> Result<()> {
         output.write_i32::<LE>(self.effect_type)?;
-        output.write_i32::<LE>(self.properties.len() as i32)?;
-        for value in &self.properties {
-            output.write_i32::<LE>(*value)?;
+        let mut properties = self.properties.clone();
+        if version > 1.1 {
+            if !self.objects.is_empty() || properties[4] > 0 {
+                properties[4] = self.objects.len() as i32;
+            }
+        } else {
+            properties[4] = *self.objects.first().unwrap_or(&-1);
+        }
+
+        if version > 1.0 {
+            output.write_i32::<LE>(properties.len() as i32)?;
+            for value in &properties {
+                output.write_i32::<LE>(*value)?;
+            }
+        } else {
+            for i in 0..16 {
+                output.write_i32::<LE>(*properties.get(i).unwrap_or(&-1))?;
+            }
+        }
+
+        write_opt_i32_str(output, &self.chat_text)?;
+        write_opt_i32_str(output, &self.audio_file)?;
+
+        if version > 1.1 {
+            for object in &self.objects {
+                output.write_i32::<LE>(*object)?;
+            }
         }
 
         Ok(())
     }
 
+    /// Get the type ID of this effect.
+    pub fn effect_type(&self) -> i32 {
+        self.effect_type
+    }
+
+    /// Get the object attribute changed by a UserPatch "Change Object Attribute" effect.
+    ///
+    /// Returns None for other effect types.
+    pub fn object_attribute(&self) -> Option<ObjectAttribute> {
+        if self.effect_type == i32::from(EffectType::ChangeObjectAttribute) {
+            ObjectAttribute::try_from(self.resource()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Create a UserPatch "Change Object Attribute" effect that sets `attribute` to `amount`.
+    pub fn change_object_attribute(attribute: ObjectAttribute, amount: i32) -> Self {
+        let mut effect = Self::new(EffectType::ChangeObjectAttribute);
+        effect.set_resource(attribute.into());
+        effect.set_amount(amount);
+        effect
+    }
+
+    /// Get the chat text or instructions shown by this effect.
+    pub fn chat_text(&self) -> Option<&str> {
+        self.chat_text.as_deref()
+    }
+
+    /// Set the chat text or instructions shown by this effect.
+    pub fn set_chat_text(&mut self, chat_text: Option<String>) {
+        self.chat_text = chat_text;
+    }
+
+    /// Get the IDs of the objects this effect applies to.
+    pub fn objects(&self) -> &[i32] {
+        &self.objects
+    }
+
+    /// Set the IDs of the objects this effect applies to.
+    pub fn set_objects(&mut self, objects: Vec<i32>) {
+        self.properties[4] = objects.len() as i32;
+        self.objects = objects;
+    }
+
     pub fn ai_goal(&self) -> i32 {
         self.properties[0]
     }
@@ -399,7 +801,7 @@ impl TriggerEffect {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Trigger {
     enabled: bool,
     looping: bool,
@@ -466,6 +868,96 @@ impl Trigger {
         })
     }
 
+    /// Create a new, enabled trigger without any conditions or effects.
+    pub fn new(name: &str) -> Self {
+        Self {
+            enabled: true,
+            looping: false,
+            name_id: -1,
+            is_objective: false,
+            objective_order: 0,
+            start_time: 0,
+            description: None,
+            name: Some(name.to_string()),
+            effects: vec![],
+            effect_order: vec![],
+            conditions: vec![],
+            condition_order: vec![],
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, output: &mut W, version: f64) -> Result<()> {
+        output.write_i32::<LE>(if self.enabled { 1 } else { 0 })?;
+        output.write_i8(if self.looping { 1 } else { 0 })?;
+        output.write_i32::<LE>(self.name_id)?;
+        output.write_i8(if self.is_objective { 1 } else { 0 })?;
+        output.write_i32::<LE>(self.objective_order)?;
+        output.write_u32::<LE>(self.start_time)?;
+
+        write_opt_i32_str(output, &self.description)?;
+        write_opt_i32_str(output, &self.name)?;
+
+        output.write_i32::<LE>(self.effects.len() as i32)?;
+        for effect in &self.effects {
+            effect.write_to(output, version)?;
+        }
+        for index in &self.effect_order {
+            output.write_i32::<LE>(*index)?;
+        }
+
+        output.write_i32::<LE>(self.conditions.len() as i32)?;
+        for condition in &self.conditions {
+            condition.write_to(output, version)?;
+        }
+        for index in &self.condition_order {
+            output.write_i32::<LE>(*index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the name of this trigger.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Get the description of this trigger, which is shown as its objective text.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Returns whether this trigger is enabled at the start of the game.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Set whether this trigger is enabled at the start of the game.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Returns whether this trigger fires again after its effects were executed.
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Set whether this trigger fires again after its effects were executed.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Append a condition to this trigger.
+    pub fn push_condition(&mut self, condition: TriggerCondition) {
+        self.condition_order.push(self.conditions.len() as i32);
+        self.conditions.push(condition);
+    }
+
+    /// Append an effect to this trigger.
+    pub fn push_effect(&mut self, effect: TriggerEffect) {
+        self.effect_order.push(self.effects.len() as i32);
+        self.effects.push(effect);
+    }
+
     pub fn conditions(&self) -> impl Iterator<Item = &TriggerCondition> {
         self.condition_order
             .iter()
@@ -487,7 +979,7 @@ impl Trigger {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TriggerSystem {
     version: f64,
     objectives_state: i8,
@@ -541,11 +1033,36 @@ impl TriggerSystem {
         if version >= 1.5 {
             output.write_i8(self.objectives_state)?;
         }
-        // num triggers
-        output.write_u32::<LE>(0)?;
+
+        output.write_i32::<LE>(self.triggers.len() as i32)?;
+        for trigger in &self.triggers {
+            trigger.write_to(output, version)?;
+        }
+        if version >= 1.4 {
+            for index in &self.trigger_order {
+                output.write_i32::<LE>(*index)?;
+            }
+        }
+
         Ok(())
     }
 
+    /// Get the number of triggers in this trigger system.
+    pub fn len(&self) -> usize {
+        self.triggers.len()
+    }
+
+    /// Returns whether this trigger system contains no triggers.
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// Append a trigger to this trigger system.
+    pub fn push(&mut self, trigger: Trigger) {
+        self.trigger_order.push(self.triggers.len() as i32);
+        self.triggers.push(trigger);
+    }
+
     pub fn triggers(&self) -> impl Iterator<Item = &Trigger> {
         self.trigger_order
             .iter()
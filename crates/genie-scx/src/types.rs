@@ -220,22 +220,160 @@ impl StartingAge {
     }
 }
 
+/// Could not parse a victory condition because given number is an unknown victory condition ID.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseVictoryConditionError(i32);
+
+impl std::fmt::Display for ParseVictoryConditionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid victory condition {} (must be 0-11)", self.0)
+    }
+}
+
+impl std::error::Error for ParseVictoryConditionError {}
+
+/// A per-player victory condition, as used by AoE1.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VictoryCondition {
+    /// Capture an object.
     Capture = 0,
+    /// Own a number of objects of a type.
     Create = 1,
+    /// Destroy an object.
     Destroy = 2,
+    /// Destroy a number of objects of a type.
     DestroyMultiple = 3,
+    /// Bring an object to an area.
     BringToArea = 4,
+    /// Bring an object near another object.
     BringToObject = 5,
+    /// Accumulate an amount of an attribute, like a resource.
     Attribute = 6,
+    /// Explore a percentage of the map.
     Explore = 7,
+    /// Have a number of objects of a type in an area.
     CreateInArea = 8,
+    /// Destroy all objects of a type.
     DestroyAll = 9,
+    /// Defeat a player.
     DestroyPlayer = 10,
+    /// Reach a number of victory points.
     Points = 11,
 }
 
+impl VictoryCondition {
+    pub fn try_from(n: i32) -> Result<Self, ParseVictoryConditionError> {
+        match n {
+            0 => Ok(VictoryCondition::Capture),
+            1 => Ok(VictoryCondition::Create),
+            2 => Ok(VictoryCondition::Destroy),
+            3 => Ok(VictoryCondition::DestroyMultiple),
+            4 => Ok(VictoryCondition::BringToArea),
+            5 => Ok(VictoryCondition::BringToObject),
+            6 => Ok(VictoryCondition::Attribute),
+            7 => Ok(VictoryCondition::Explore),
+            8 => Ok(VictoryCondition::CreateInArea),
+            9 => Ok(VictoryCondition::DestroyAll),
+            10 => Ok(VictoryCondition::DestroyPlayer),
+            11 => Ok(VictoryCondition::Points),
+            n => Err(ParseVictoryConditionError(n)),
+        }
+    }
+}
+
+impl From<VictoryCondition> for i32 {
+    fn from(condition: VictoryCondition) -> i32 {
+        condition as i32
+    }
+}
+
+/// The global victory mode of a scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VictoryMode {
+    /// Win by destroying all enemies, building a Wonder, or collecting all relics.
+    Standard,
+    /// Win by destroying all enemies.
+    Conquest,
+    /// The player with the highest score when the time limit is reached wins.
+    TimeLimit,
+    /// The first player to reach the required score wins.
+    Score,
+    /// Win by meeting the custom victory conditions.
+    Custom,
+    /// A victory mode ID that is not known to this library. It is kept as is, so the scenario
+    /// can be written back without losing it.
+    Other(i32),
+}
+
+impl From<i32> for VictoryMode {
+    fn from(n: i32) -> Self {
+        match n {
+            0 => VictoryMode::Standard,
+            1 => VictoryMode::Conquest,
+            2 => VictoryMode::TimeLimit,
+            3 => VictoryMode::Score,
+            4 => VictoryMode::Custom,
+            n => VictoryMode::Other(n),
+        }
+    }
+}
+
+impl From<VictoryMode> for i32 {
+    fn from(mode: VictoryMode) -> i32 {
+        match mode {
+            VictoryMode::Standard => 0,
+            VictoryMode::Conquest => 1,
+            VictoryMode::TimeLimit => 2,
+            VictoryMode::Score => 3,
+            VictoryMode::Custom => 4,
+            VictoryMode::Other(n) => n,
+        }
+    }
+}
+
+/// The maximum amount of disabled technologies, units, and buildings per player that a scenario
+/// version can store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisabledLimits {
+    /// Maximum number of disabled technologies per player.
+    pub techs: usize,
+    /// Maximum number of disabled units per player.
+    pub units: usize,
+    /// Maximum number of disabled buildings per player.
+    pub buildings: usize,
+}
+
+impl DisabledLimits {
+    /// Get the limits for a scenario data version.
+    pub(crate) fn from_data_version(version: f32) -> Self {
+        if version >= 1.25 {
+            Self {
+                techs: 30,
+                units: 30,
+                buildings: 30,
+            }
+        } else if version >= 1.18 {
+            Self {
+                techs: 30,
+                units: 30,
+                buildings: 20,
+            }
+        } else if version > 1.03 {
+            Self {
+                techs: 20,
+                units: 0,
+                buildings: 0,
+            }
+        } else {
+            Self {
+                techs: 0,
+                units: 0,
+                buildings: 0,
+            }
+        }
+    }
+}
+
 /// All the versions an SCX file uses in a single struct.
 #[derive(Debug, Clone, PartialEq)]
 pub struct VersionBundle {
@@ -253,6 +391,11 @@ pub struct VersionBundle {
     pub victory: f32,
     /// The version of the trigger system.
     pub triggers: f64,
+    /// The UserPatch version that the scenario is made for, if any.
+    ///
+    /// UserPatch does not change the file format, so this is not stored in the file. When
+    /// reading a scenario, it is set to 1.5 if the scenario uses UserPatch 1.5 trigger effects.
+    pub userpatch: Option<f32>,
 }
 
 impl VersionBundle {
@@ -281,17 +424,28 @@ impl VersionBundle {
             picture: 1,
             victory: 2.0,
             triggers: 1.6,
+            userpatch: None,
         }
     }
 
     /// A version bundle with the parameters UserPatch 1.4 uses by default.
     pub fn userpatch_14() -> Self {
-        Self::aoc()
+        Self {
+            userpatch: Some(1.4),
+            ..Self::aoc()
+        }
     }
 
     /// A version bundle with the parameters UserPatch 1.5 uses by default.
+    ///
+    /// UserPatch 1.5 scenarios use the same format as AoC scenarios, but may contain trigger
+    /// effects and terrains that AoC does not support. Use
+    /// `Scenario::vanilla_compatibility_issues` to find them.
     pub fn userpatch_15() -> Self {
-        Self::userpatch_14()
+        Self {
+            userpatch: Some(1.5),
+            ..Self::aoc()
+        }
     }
 
     /// A version bundle with the parameters HD Edition uses by default.
@@ -304,9 +458,45 @@ impl VersionBundle {
             picture: 3,
             victory: 2.0,
             triggers: 1.6,
+            userpatch: None,
         }
     }
 
+    /// Get the amount of disabled technologies, units, and buildings that can be stored per player
+    /// in a scenario file of this version.
+    ///
+    /// When writing a scenario with more disabled objects than this, the excess is emulated with
+    /// trigger effects, if the version supports UserPatch trigger effects.
+    pub fn disabled_limits(&self) -> DisabledLimits {
+        DisabledLimits::from_data_version(self.data)
+    }
+
+    /// Returns whether this version can store UserPatch 1.5 trigger effects. See
+    /// `EffectType::is_userpatch`.
+    pub fn has_userpatch_effects(&self) -> bool {
+        matches!(self.userpatch, Some(version) if version >= 1.5)
+    }
+
+    /// Returns whether this version supports triggers.
+    pub fn has_triggers(&self) -> bool {
+        !matches!(
+            &self.format,
+            b"1.01"
+                | b"1.02"
+                | b"1.03"
+                | b"1.04"
+                | b"1.05"
+                | b"1.06"
+                | b"1.07"
+                | b"1.08"
+                | b"1.09"
+                | b"1.10"
+                | b"1.11"
+                | b"1.12"
+                | b"1.13"
+        )
+    }
+
     /// Returns whether this version is (likely) for an AoK scenario.
     pub fn is_aok(&self) -> bool {
         match &self.format {
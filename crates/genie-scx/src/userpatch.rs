@@ -0,0 +1,81 @@
+//! Checks for UserPatch-only features in scenarios.
+//!
+//! UserPatch 1.5 scenarios use the same file format as vanilla AoC scenarios, but can contain
+//! trigger effects and terrain IDs that vanilla AoC does not know about. Vanilla AoC does not
+//! reject such scenarios, but misinterprets the unknown data.
+
+use crate::{EffectType, Map, TriggerSystem};
+
+/// The highest terrain ID that vanilla AoC supports.
+pub const VANILLA_MAX_TERRAIN: i8 = 41;
+
+/// A UserPatch feature that vanilla AoC would misinterpret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompatibilityIssue {
+    /// A UserPatch trigger effect type that vanilla AoC does not support. See
+    /// `EffectType::is_userpatch`.
+    ///
+    /// `trigger` and `effect` are indices in display order.
+    UserPatchEffect {
+        trigger: usize,
+        effect: usize,
+        effect_type: i32,
+    },
+    /// A map tile uses a terrain ID that only exists in UserPatch.
+    ExtendedTerrain { x: usize, y: usize, terrain: i8 },
+}
+
+impl std::fmt::Display for CompatibilityIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CompatibilityIssue::UserPatchEffect {
+                trigger,
+                effect,
+                effect_type,
+            } => write!(
+                f,
+                "trigger {} effect {} uses UserPatch effect type {}",
+                trigger, effect, effect_type
+            ),
+            CompatibilityIssue::ExtendedTerrain { x, y, terrain } => {
+                write!(f, "tile ({}, {}) uses UserPatch terrain {}", x, y, terrain)
+            }
+        }
+    }
+}
+
+/// Find UserPatch trigger effects that vanilla AoC does not support.
+pub(crate) fn check_triggers(triggers: &TriggerSystem) -> Vec<CompatibilityIssue> {
+    let mut issues = vec![];
+    for (trigger_index, trigger) in triggers.triggers().enumerate() {
+        for (effect_index, effect) in trigger.effects().enumerate() {
+            let effect_type = EffectType::try_from(effect.effect_type());
+            if matches!(effect_type, Ok(effect_type) if effect_type.is_userpatch()) {
+                issues.push(CompatibilityIssue::UserPatchEffect {
+                    trigger: trigger_index,
+                    effect: effect_index,
+                    effect_type: effect.effect_type(),
+                });
+            }
+        }
+    }
+    issues
+}
+
+/// Find map tiles with terrain IDs that vanilla AoC does not support.
+pub(crate) fn check_map(map: &Map) -> Vec<CompatibilityIssue> {
+    let mut issues = vec![];
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            let terrain = map.tile(x, y).map(|tile| tile.terrain).unwrap_or(0);
+            if terrain > VANILLA_MAX_TERRAIN {
+                issues.push(CompatibilityIssue::ExtendedTerrain {
+                    x: x as usize,
+                    y: y as usize,
+                    terrain,
+                });
+            }
+        }
+    }
+    issues
+}
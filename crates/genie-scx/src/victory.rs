@@ -1,21 +1,70 @@
+use crate::triggers::{ConditionType, EffectType, Trigger, TriggerCondition, TriggerEffect};
+use crate::types::{ParseVictoryConditionError, VictoryCondition, VictoryMode};
 use crate::Result;
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use std::io::{Read, Write};
 
+/// The attribute ID that tracks the percentage of the map a player has explored.
+const ATTRIBUTE_MAP_EXPLORED: i32 = 22;
+
+/// Global victory settings for a scenario.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalVictory {
+    /// The victory mode.
+    pub mode: VictoryMode,
+    /// The score required to win, for [`VictoryMode::Score`].
+    pub score: i32,
+    /// The game time after which the highest scoring player wins, for
+    /// [`VictoryMode::TimeLimit`].
+    pub time_limit: i32,
+    /// The conditions to meet, for [`VictoryMode::Custom`].
+    pub custom: CustomVictory,
+}
+
+/// Custom global victory conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CustomVictory {
+    /// Whether all enemies must be destroyed.
+    pub conquest: bool,
+    /// The number of relics to collect.
+    pub relics: i32,
+    /// The percentage of the map to explore.
+    pub explored: i32,
+    /// The number of ruins to capture. (AoE1)
+    pub ruins: i32,
+    /// The number of discoveries to find. (AoE1)
+    pub discoveries: i32,
+    /// The amount of gold to collect. (AoE1)
+    pub gold: i32,
+    /// Whether all of these conditions must be met, instead of any one of them.
+    pub all_required: bool,
+}
+
 /// AoE1's victory info.
 ///
-/// This was replaced by VictoryConditions in AoE2.
+/// This was replaced by VictoryConditions in AoE2. Scenario files still store it, but the same
+/// conditions are also stored in the players' [`VictoryConditions`].
 #[derive(Debug, Clone, Default)]
 pub struct LegacyVictoryInfo {
+    /// The object type to create or destroy.
     pub object_type: i32,
+    /// Whether all objects of the type are targeted.
     pub all_flag: bool,
+    /// The player to defeat.
     pub player_id: i32,
+    /// The object to bring the source object to.
     pub dest_object_id: i32,
+    /// The area (x0, y0, x1, y1) to bring an object to.
     pub area: (f32, f32, f32, f32),
+    /// The kind of victory condition.
     pub victory_type: i32,
+    /// The amount of objects, or the amount of the attribute.
     pub amount: i32,
+    /// The attribute ID to accumulate.
     pub attribute: i32,
+    /// The object to capture, destroy, or bring somewhere.
     pub object_id: i32,
+    /// The object to bring the source object to.
     pub dest_object_id2: i32,
 }
 
@@ -74,38 +123,67 @@ impl LegacyVictoryInfo {
     }
 }
 
-#[derive(Debug)]
+/// A per-player victory condition, as used by AoE1.
+#[derive(Debug, Clone)]
 pub struct VictoryEntry {
-    command: i8,
-    object_type: i32,
-    player_id: i32,
-    x0: f32,
-    y0: f32,
-    x1: f32,
-    y1: f32,
-    number: i32,
-    count: i32,
-    source_object: i32,
-    target_object: i32,
-    victory_group: i8,
-    ally_flag: i8,
-    state: i8,
+    /// The kind of victory condition, see [`VictoryCondition`].
+    pub command: i8,
+    /// The object type to create or destroy.
+    pub object_type: i32,
+    /// The player whose objects must be destroyed, or who must be defeated.
+    pub player_id: i32,
+    /// The area (x0, y0, x1, y1) objects must be brought to or created in.
+    pub area: (f32, f32, f32, f32),
+    /// The attribute ID to accumulate, for [`VictoryCondition::Attribute`].
+    pub attribute: i32,
+    /// The amount of objects, the amount of the attribute, or the percentage of the map to
+    /// explore.
+    pub amount: i32,
+    /// The object to bring somewhere.
+    pub source_object: i32,
+    /// The object to capture or destroy, or to bring the source object to.
+    pub target_object: i32,
+    /// The group this condition belongs to.
+    pub group: i8,
+    /// Whether allies can meet this condition together.
+    pub ally_flag: i8,
+    /// The state of this condition in a running game.
+    pub state: i8,
 }
 
 impl VictoryEntry {
+    /// Create a victory condition of the given kind, without any targets.
+    pub fn new(condition: VictoryCondition) -> Self {
+        Self {
+            command: i32::from(condition) as i8,
+            object_type: -1,
+            player_id: -1,
+            area: (-1.0, -1.0, -1.0, -1.0),
+            attribute: -1,
+            amount: 0,
+            source_object: -1,
+            target_object: -1,
+            group: 1,
+            ally_flag: 0,
+            state: 0,
+        }
+    }
+
     pub fn from<R: Read>(input: &mut R) -> Result<Self> {
         let command = input.read_i8()?;
         let object_type = input.read_i32::<LE>()?;
         let player_id = input.read_i32::<LE>()?;
-        let x0 = input.read_f32::<LE>()?;
-        let y0 = input.read_f32::<LE>()?;
-        let x1 = input.read_f32::<LE>()?;
-        let y1 = input.read_f32::<LE>()?;
-        let number = input.read_i32::<LE>()?;
-        let count = input.read_i32::<LE>()?;
+        let area = (
+            input.read_f32::<LE>()?,
+            input.read_f32::<LE>()?,
+            input.read_f32::<LE>()?,
+            input.read_f32::<LE>()?,
+        );
+        let attribute = input.read_i32::<LE>()?;
+        let amount = input.read_i32::<LE>()?;
         let source_object = input.read_i32::<LE>()?;
         let target_object = input.read_i32::<LE>()?;
-        let victory_group = input.read_i8()?;
+        let group = input.read_i8()?;
         let ally_flag = input.read_i8()?;
         let state = input.read_i8()?;
 
@@ -113,15 +191,12 @@ impl VictoryEntry {
             command,
             object_type,
             player_id,
-            x0,
-            y0,
-            x1,
-            y1,
-            number,
-            count,
+            area,
+            attribute,
+            amount,
             source_object,
             target_object,
-            victory_group,
+            group,
             ally_flag,
             state,
         })
@@ -131,34 +206,132 @@ impl VictoryEntry {
         output.write_i8(self.command)?;
         output.write_i32::<LE>(self.object_type)?;
         output.write_i32::<LE>(self.player_id)?;
-        output.write_f32::<LE>(self.x0)?;
-        output.write_f32::<LE>(self.y0)?;
-        output.write_f32::<LE>(self.x1)?;
-        output.write_f32::<LE>(self.y1)?;
-        output.write_i32::<LE>(self.number)?;
-        output.write_i32::<LE>(self.count)?;
+        output.write_f32::<LE>(self.area.0)?;
+        output.write_f32::<LE>(self.area.1)?;
+        output.write_f32::<LE>(self.area.2)?;
+        output.write_f32::<LE>(self.area.3)?;
+        output.write_i32::<LE>(self.attribute)?;
+        output.write_i32::<LE>(self.amount)?;
         output.write_i32::<LE>(self.source_object)?;
         output.write_i32::<LE>(self.target_object)?;
-        output.write_i8(self.victory_group)?;
+        output.write_i8(self.group)?;
         output.write_i8(self.ally_flag)?;
         output.write_i8(self.state)?;
         Ok(())
     }
+
+    /// Get the kind of victory condition.
+    pub fn condition(&self) -> std::result::Result<VictoryCondition, ParseVictoryConditionError> {
+        VictoryCondition::try_from(i32::from(self.command))
+    }
+
+    /// Create an equivalent trigger condition for the given player, for use in AoK and up.
+    ///
+    /// Returns None if this victory condition can not be expressed as a trigger condition.
+    pub fn to_trigger_condition(&self, player_id: i32) -> Option<TriggerCondition> {
+        let area = (
+            self.area.0 as i32,
+            self.area.1 as i32,
+            self.area.2 as i32,
+            self.area.3 as i32,
+        );
+        let condition = match self.condition().ok()? {
+            VictoryCondition::Capture => {
+                let mut condition = TriggerCondition::new(ConditionType::CaptureObject);
+                condition.set_primary_object(self.target_object);
+                condition.set_player_id(player_id);
+                condition
+            }
+            VictoryCondition::Create => {
+                let mut condition = TriggerCondition::new(ConditionType::OwnObjects);
+                condition.set_amount(self.amount);
+                condition.set_unit_type(self.object_type);
+                condition.set_player_id(player_id);
+                condition
+            }
+            VictoryCondition::Destroy => {
+                let mut condition = TriggerCondition::new(ConditionType::DestroyObject);
+                condition.set_primary_object(self.target_object);
+                condition
+            }
+            VictoryCondition::BringToArea => {
+                let mut condition = TriggerCondition::new(ConditionType::BringObjectToArea);
+                condition.set_primary_object(self.source_object);
+                condition.set_area(area);
+                condition
+            }
+            VictoryCondition::BringToObject => {
+                let mut condition = TriggerCondition::new(ConditionType::BringObjectToObject);
+                condition.set_primary_object(self.source_object);
+                condition.set_secondary_object(self.target_object);
+                condition
+            }
+            VictoryCondition::Attribute => {
+                let mut condition = TriggerCondition::new(ConditionType::AccumulateAttribute);
+                condition.set_resource(self.attribute);
+                condition.set_amount(self.amount);
+                condition.set_player_id(player_id);
+                condition
+            }
+            VictoryCondition::Explore => {
+                let mut condition = TriggerCondition::new(ConditionType::AccumulateAttribute);
+                condition.set_resource(ATTRIBUTE_MAP_EXPLORED);
+                condition.set_amount(self.amount);
+                condition.set_player_id(player_id);
+                condition
+            }
+            VictoryCondition::CreateInArea => {
+                let mut condition = TriggerCondition::new(ConditionType::ObjectsInArea);
+                condition.set_amount(self.amount);
+                condition.set_unit_type(self.object_type);
+                condition.set_area(area);
+                condition.set_player_id(player_id);
+                condition
+            }
+            VictoryCondition::DestroyAll => {
+                let mut condition = TriggerCondition::new(ConditionType::OwnFewerObjects);
+                condition.set_amount(0);
+                condition.set_unit_type(self.object_type);
+                condition.set_player_id(self.player_id);
+                condition
+            }
+            VictoryCondition::DestroyPlayer => {
+                let mut condition = TriggerCondition::new(ConditionType::PlayerDefeated);
+                condition.set_player_id(self.player_id);
+                condition
+            }
+            // Triggers can not count destroyed objects or victory points.
+            VictoryCondition::DestroyMultiple | VictoryCondition::Points => return None,
+        };
+        Some(condition)
+    }
 }
 
-#[derive(Debug)]
+/// A victory point rule: players get points for accumulating an attribute.
+#[derive(Debug, Clone)]
 pub struct VictoryPointEntry {
-    command: i8,
-    state: i8,
-    attribute: i32,
-    amount: i32,
-    points: i32,
-    current_points: i32,
-    id: i8,
-    group: i8,
-    current_attribute_amount: f32,
-    attribute1: i32,
-    current_attribute_amount1: f32,
+    /// The kind of victory point rule.
+    pub command: i8,
+    /// The state of this rule in a running game.
+    pub state: i8,
+    /// The attribute ID to accumulate.
+    pub attribute: i32,
+    /// The amount of the attribute to accumulate.
+    pub amount: i32,
+    /// The points awarded.
+    pub points: i32,
+    /// The points awarded so far in a running game.
+    pub current_points: i32,
+    /// The ID of this rule.
+    pub id: i8,
+    /// The group this rule belongs to.
+    pub group: i8,
+    /// The amount of the attribute accumulated so far in a running game.
+    pub current_attribute_amount: f32,
+    /// A second attribute ID to accumulate.
+    pub attribute1: i32,
+    /// The amount of the second attribute accumulated so far in a running game.
+    pub current_attribute_amount1: f32,
 }
 
 impl VictoryPointEntry {
@@ -212,7 +385,8 @@ impl VictoryPointEntry {
     }
 }
 
-#[derive(Debug)]
+/// Per-player victory conditions.
+#[derive(Debug, Clone)]
 pub struct VictoryConditions {
     version: f32,
     victory: bool,
@@ -299,6 +473,43 @@ impl VictoryConditions {
 
         Ok(())
     }
+
+    /// Get the victory conditions.
+    pub fn entries(&self) -> &[VictoryEntry] {
+        &self.entries
+    }
+
+    /// Get a mutable reference to the victory conditions, to add or remove conditions.
+    pub fn entries_mut(&mut self) -> &mut Vec<VictoryEntry> {
+        &mut self.entries
+    }
+
+    /// Get the victory point rules.
+    pub fn point_entries(&self) -> &[VictoryPointEntry] {
+        &self.point_entries
+    }
+
+    /// Create a trigger that declares victory for the given player when all of these conditions
+    /// are met.
+    ///
+    /// Returns None if there are no conditions, or if any of the conditions can not be expressed
+    /// as a trigger condition.
+    pub fn to_trigger(&self, player_id: i32) -> Option<Trigger> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let mut trigger = Trigger::new(&format!("Player {} Victory", player_id));
+        for entry in &self.entries {
+            trigger.push_condition(entry.to_trigger_condition(player_id)?);
+        }
+
+        let mut effect = TriggerEffect::new(EffectType::DeclareVictory);
+        effect.set_source_player_id(player_id);
+        trigger.push_effect(effect);
+
+        Some(trigger)
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,382 @@
+//! SLP is the sprite file format for the Genie Engine, used by Age of Empires 1/2 and Star Wars:
+//! Galactic Battlegrounds. An SLP file contains one or more frames, which are run-length encoded
+//! images that use colors from a palette.
+//!
+//! genie-slp reads SLP versions 2.0N, 3.0, and 4.0X/4.1X, and decodes frames to `IndexedImage`s.
+//! Images can be converted to RGBA using a palette. Indexed images can also be encoded back into
+//! SLP files.
+//!
+//! The Definitive Edition of Age of Empires 2 uses SMX files instead, which store the main graphic,
+//! shadow, and outline of a frame in separate layers and support 1024-color palettes. The `smx`
+//! module reads and writes SMX files, and the `smp` module reads the uncompressed SMP files used
+//! by its beta. Layered frames can be converted to and from SLP frames.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use genie_slp::{IndexedImage, Pixel, SLPFrame, SLP};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut image = IndexedImage::new(2, 2);
+//! image.set(0, 0, Pixel::Color(42));
+//! image.set(1, 1, Pixel::PlayerColor(3));
+//!
+//! let mut slp = SLP::default();
+//! slp.add_frame(SLPFrame::new(image, (1, 1)));
+//! let mut bytes = vec![];
+//! slp.write_to(&mut bytes)?;
+//!
+//! let slp = SLP::from(&mut &bytes[..])?;
+//! assert_eq!(slp.frames()[0].image().get(0, 0), Some(Pixel::Color(42)));
+//! # Ok(())
+//! # }
+//! ```
+
+use genie_pal::Palette;
+
+mod read;
+pub mod smp;
+pub mod smx;
+mod write;
+
+pub use read::ReadSLPError;
+pub use write::WriteSLPError;
+
+/// Version identifier for the SLP file format.
+pub type SLPVersion = [u8; 4];
+
+/// The SLP version used by AoE1, AoE2, and SWGB.
+pub const VERSION_2_0: SLPVersion = *b"2.0N";
+/// SLP version 3.0. It uses the same layout as version 2.0N.
+pub const VERSION_3_0: SLPVersion = *b"3.0\0";
+/// SLP version 4.0X, used by the Definitive Editions.
+pub const VERSION_4_0: SLPVersion = *b"4.0X";
+/// SLP version 4.1X, used by the Definitive Editions.
+pub const VERSION_4_1: SLPVersion = *b"4.1X";
+
+/// The largest width and height of a frame. Row edges are stored in 16 bits, and 0x8000 marks
+/// an empty row.
+pub const MAX_FRAME_SIZE: u32 = 0x7FFF;
+
+/// Returns whether an SLP version uses the 4.x header, with direction counts and a separate list
+/// of secondary frames.
+fn is_version_4(version: SLPVersion) -> bool {
+    version == VERSION_4_0 || version == VERSION_4_1
+}
+
+/// A single pixel in an SLP frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Pixel {
+    /// Nothing is drawn.
+    #[default]
+    Transparent,
+    /// A palette index.
+    Color(u8),
+    /// A palette index that is shifted to the colors of the player that owns the unit.
+    PlayerColor(u8),
+    /// A shadow, drawn by darkening whatever is below the sprite.
+    Shadow,
+    /// Player color outline, drawn when the unit is hidden behind a building.
+    PlayerOutline,
+    /// Black outline, drawn when the unit is hidden behind a building.
+    BlackOutline,
+}
+
+/// An image that uses palette indices and special SLP pixel types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<Pixel>,
+}
+
+impl IndexedImage {
+    /// Create a fully transparent image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Pixel::Transparent; width as usize * height as usize],
+        }
+    }
+
+    /// Create an image from a list of pixels, row by row. Returns None if the number of pixels
+    /// does not match the size.
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Pixel>) -> Option<Self> {
+        if pixels.len() == width as usize * height as usize {
+            Some(Self {
+                width,
+                height,
+                pixels,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get all pixels, row by row.
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    /// Iterate over the rows of the image.
+    pub fn rows(&self) -> impl Iterator<Item = &[Pixel]> {
+        let width = self.width as usize;
+        (0..self.height as usize).map(move |y| &self.pixels[y * width..(y + 1) * width])
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Get the pixel at a position, or None if the position is outside the image.
+    pub fn get(&self, x: u32, y: u32) -> Option<Pixel> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
+
+    /// Set the pixel at a position. Returns false if the position is outside the image.
+    pub fn set(&mut self, x: u32, y: u32, pixel: Pixel) -> bool {
+        match self.index(x, y) {
+            Some(index) => {
+                self.pixels[index] = pixel;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Convert the image to 8-bit RGBA pixel data.
+    ///
+    /// Player color pixels and player outlines are drawn using palette index
+    /// `pixel + player_offset`. In the AoE2 palette, for example, the colors for player 1 start at
+    /// index 16. Shadows are drawn as half-transparent black. Colors that are missing from the
+    /// palette are drawn as black.
+    pub fn to_rgba(&self, palette: &Palette, player_offset: u8) -> Vec<u8> {
+        let color = |index: u8| match palette.get(usize::from(index)) {
+            Some(color) => [color.r, color.g, color.b, 0xFF],
+            None => [0, 0, 0, 0xFF],
+        };
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            let bytes = match *pixel {
+                Pixel::Transparent => [0, 0, 0, 0],
+                Pixel::Color(index) => color(index),
+                Pixel::PlayerColor(index) => color(index.wrapping_add(player_offset)),
+                Pixel::Shadow => [0, 0, 0, 0x80],
+                Pixel::PlayerOutline => color(player_offset),
+                Pixel::BlackOutline => [0, 0, 0, 0xFF],
+            };
+            rgba.extend_from_slice(&bytes);
+        }
+        rgba
+    }
+}
+
+/// A single frame in an SLP file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SLPFrame {
+    image: IndexedImage,
+    hotspot: (i32, i32),
+    palette_offset: u32,
+    properties: u32,
+}
+
+impl SLPFrame {
+    /// Create a frame from an image. The hotspot is the point in the image that is placed at the
+    /// position of the unit.
+    pub fn new(image: IndexedImage, hotspot: (i32, i32)) -> Self {
+        Self {
+            image,
+            hotspot,
+            palette_offset: 0,
+            properties: 0,
+        }
+    }
+
+    /// Get the image in this frame.
+    pub fn image(&self) -> &IndexedImage {
+        &self.image
+    }
+
+    /// Get a mutable reference to the image in this frame.
+    pub fn image_mut(&mut self) -> &mut IndexedImage {
+        &mut self.image
+    }
+
+    /// Get the hotspot: the point in the image that is placed at the position of the unit.
+    pub fn hotspot(&self) -> (i32, i32) {
+        self.hotspot
+    }
+
+    /// Set the hotspot.
+    pub fn set_hotspot(&mut self, hotspot: (i32, i32)) {
+        self.hotspot = hotspot;
+    }
+
+    /// Get the palette offset field of this frame.
+    pub fn palette_offset(&self) -> u32 {
+        self.palette_offset
+    }
+
+    /// Set the palette offset field of this frame.
+    pub fn set_palette_offset(&mut self, palette_offset: u32) {
+        self.palette_offset = palette_offset;
+    }
+
+    /// Get the properties field of this frame. Its meaning depends on the SLP version.
+    pub fn properties(&self) -> u32 {
+        self.properties
+    }
+
+    /// Set the properties field of this frame.
+    pub fn set_properties(&mut self, properties: u32) {
+        self.properties = properties;
+    }
+}
+
+/// An SLP sprite file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SLP {
+    version: SLPVersion,
+    /// Comment, only stored in versions 2.0N and 3.0.
+    comment: [u8; 24],
+    /// Frame type flags, only stored in version 4.x.
+    frame_type: u16,
+    /// Number of directions, only stored in version 4.x.
+    num_directions: u16,
+    /// Number of frames per direction, only stored in version 4.x.
+    frames_per_direction: u16,
+    /// Palette ID, only stored in version 4.x.
+    palette_id: u32,
+    frames: Vec<SLPFrame>,
+    /// Shadow or mask frames, only stored in version 4.x.
+    secondary_frames: Vec<SLPFrame>,
+}
+
+impl Default for SLP {
+    fn default() -> Self {
+        Self::new(VERSION_2_0)
+    }
+}
+
+impl SLP {
+    /// Create an empty SLP file.
+    pub fn new(version: SLPVersion) -> Self {
+        Self {
+            version,
+            comment: *b"ArtDesk 1.00 SLP Writer\0",
+            frame_type: 0,
+            num_directions: 0,
+            frames_per_direction: 0,
+            palette_id: 0,
+            frames: vec![],
+            secondary_frames: vec![],
+        }
+    }
+
+    /// Get the SLP format version.
+    pub fn version(&self) -> SLPVersion {
+        self.version
+    }
+
+    /// Set the SLP format version that will be used when writing the file.
+    pub fn set_version(&mut self, version: SLPVersion) {
+        self.version = version;
+    }
+
+    /// Get the number of directions. Only stored in version 4.x.
+    pub fn num_directions(&self) -> u16 {
+        self.num_directions
+    }
+
+    /// Get the number of frames per direction. Only stored in version 4.x.
+    pub fn frames_per_direction(&self) -> u16 {
+        self.frames_per_direction
+    }
+
+    /// Get the ID of the palette to use. Only stored in version 4.x.
+    pub fn palette_id(&self) -> u32 {
+        self.palette_id
+    }
+
+    /// Get the number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Get the frames in this file.
+    pub fn frames(&self) -> &[SLPFrame] {
+        &self.frames
+    }
+
+    /// Get mutable access to the frames in this file.
+    pub fn frames_mut(&mut self) -> &mut Vec<SLPFrame> {
+        &mut self.frames
+    }
+
+    /// Get the secondary frames in this file, like shadows. Only stored in version 4.x.
+    pub fn secondary_frames(&self) -> &[SLPFrame] {
+        &self.secondary_frames
+    }
+
+    /// Add a frame to the end of the file.
+    pub fn add_frame(&mut self, frame: SLPFrame) {
+        self.frames.push(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genie_pal::Color;
+
+    #[test]
+    fn image_to_rgba() {
+        let palette: Palette = (0..=255)
+            .map(|i| Color {
+                r: i,
+                g: 0,
+                b: 255 - i,
+            })
+            .collect();
+        let pixels = vec![
+            Pixel::Transparent,
+            Pixel::Color(3),
+            Pixel::PlayerColor(2),
+            Pixel::Shadow,
+            Pixel::PlayerOutline,
+            Pixel::BlackOutline,
+        ];
+        let image = IndexedImage::from_pixels(3, 2, pixels).unwrap();
+        assert_eq!(image.rows().count(), 2);
+        assert_eq!(
+            image.to_rgba(&palette, 16),
+            vec![
+                0, 0, 0, 0, //
+                3, 0, 252, 255, //
+                18, 0, 237, 255, //
+                0, 0, 0, 128, //
+                16, 0, 239, 255, //
+                0, 0, 0, 255, //
+            ]
+        );
+        assert!(IndexedImage::from_pixels(3, 3, vec![]).is_none());
+    }
+}
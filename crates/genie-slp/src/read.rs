@@ -0,0 +1,491 @@
+use crate::{is_version_4, IndexedImage, Pixel, SLPFrame, SLPVersion, MAX_FRAME_SIZE, SLP};
+use crate::{VERSION_2_0, VERSION_3_0};
+use byteorder::{ByteOrder, LE};
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+/// Frames with these property bits set use 32-bit pixels instead of palette indices.
+const PROPERTIES_32BIT: u32 = 0x07;
+
+#[derive(Debug)]
+pub enum ReadSLPError {
+    IoError(io::Error),
+    /// The file uses an SLP version that is not supported.
+    UnsupportedVersion(SLPVersion),
+    /// The frame uses 32-bit pixels, which are not supported.
+    UnsupportedFrameFormat {
+        frame: usize,
+        properties: u32,
+    },
+    /// The frame has a negative or impossibly large size.
+    InvalidFrameSize {
+        frame: usize,
+    },
+    /// The frame refers to data past the end of the file.
+    OutOfBounds {
+        frame: usize,
+        offset: usize,
+    },
+    /// The frame contains an unknown drawing command.
+    InvalidCommand {
+        frame: usize,
+        row: usize,
+        command: u8,
+    },
+    /// The frame draws pixels outside of the image.
+    RowTooLong {
+        frame: usize,
+        row: usize,
+    },
+}
+
+impl From<io::Error> for ReadSLPError {
+    fn from(err: io::Error) -> ReadSLPError {
+        ReadSLPError::IoError(err)
+    }
+}
+
+impl std::fmt::Display for ReadSLPError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReadSLPError::IoError(err) => write!(f, "{}", err),
+            ReadSLPError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported SLP version {:?}",
+                String::from_utf8_lossy(version)
+            ),
+            ReadSLPError::UnsupportedFrameFormat { frame, properties } => write!(
+                f,
+                "frame {} uses an unsupported pixel format (properties {:#x})",
+                frame, properties
+            ),
+            ReadSLPError::InvalidFrameSize { frame } => {
+                write!(f, "frame {} has an invalid size", frame)
+            }
+            ReadSLPError::OutOfBounds { frame, offset } => write!(
+                f,
+                "frame {} refers to offset {}, past the end of the file",
+                frame, offset
+            ),
+            ReadSLPError::InvalidCommand {
+                frame,
+                row,
+                command,
+            } => write!(
+                f,
+                "invalid command {:#04x} in row {} of frame {}",
+                command, row, frame
+            ),
+            ReadSLPError::RowTooLong { frame, row } => {
+                write!(f, "row {} of frame {} is wider than the frame", row, frame)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReadSLPError {}
+
+type Result<T> = std::result::Result<T, ReadSLPError>;
+
+/// Frame metadata, as stored in the file.
+struct FrameInfo {
+    cmd_table_offset: u32,
+    outline_table_offset: u32,
+    palette_offset: u32,
+    properties: u32,
+    width: i32,
+    height: i32,
+    hotspot_x: i32,
+    hotspot_y: i32,
+}
+
+/// Bounds-checked reads from an SLP file, attributed to a frame for error reporting.
+struct Data<'a> {
+    bytes: &'a [u8],
+    frame: usize,
+}
+
+impl<'a> Data<'a> {
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or(ReadSLPError::OutOfBounds {
+                frame: self.frame,
+                offset,
+            })
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(LE::read_u16(self.slice(offset, 2)?))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(LE::read_u32(self.slice(offset, 4)?))
+    }
+
+    fn frame_info(&self, offset: usize) -> Result<FrameInfo> {
+        let info = self.slice(offset, 32)?;
+        Ok(FrameInfo {
+            cmd_table_offset: LE::read_u32(&info[0..]),
+            outline_table_offset: LE::read_u32(&info[4..]),
+            palette_offset: LE::read_u32(&info[8..]),
+            properties: LE::read_u32(&info[12..]),
+            width: LE::read_i32(&info[16..]),
+            height: LE::read_i32(&info[20..]),
+            hotspot_x: LE::read_i32(&info[24..]),
+            hotspot_y: LE::read_i32(&info[28..]),
+        })
+    }
+}
+
+/// Read the pixel count for commands that store it in the high 4 bits, or in the next byte if
+/// those are 0.
+fn read_count(data: &Data<'_>, command: u8, pos: &mut usize) -> Result<usize> {
+    match command >> 4 {
+        0 => {
+            let count = data.u8(*pos)?;
+            *pos += 1;
+            Ok(usize::from(count))
+        }
+        count => Ok(usize::from(count)),
+    }
+}
+
+/// Decode the run-length encoded rows of a frame.
+fn decode_frame(data: &Data<'_>, info: &FrameInfo) -> Result<IndexedImage> {
+    let frame = data.frame;
+    let (width, height) = match (u32::try_from(info.width), u32::try_from(info.height)) {
+        (Ok(width), Ok(height)) if width <= MAX_FRAME_SIZE && height <= MAX_FRAME_SIZE => {
+            (width, height)
+        }
+        _ => return Err(ReadSLPError::InvalidFrameSize { frame }),
+    };
+    if info.properties & PROPERTIES_32BIT == PROPERTIES_32BIT {
+        return Err(ReadSLPError::UnsupportedFrameFormat {
+            frame,
+            properties: info.properties,
+        });
+    }
+
+    let outline_table = info.outline_table_offset as usize;
+    let cmd_table = info.cmd_table_offset as usize;
+    // The size comes from the file, so check that the row tables exist before allocating.
+    data.slice(outline_table, 4 * height as usize)?;
+    data.slice(cmd_table, 4 * height as usize)?;
+
+    let mut image = IndexedImage::new(width, height);
+    let (width, height) = (width as usize, height as usize);
+
+    for row in 0..height {
+        let left = data.u16(outline_table + row * 4)?;
+        let right = data.u16(outline_table + row * 4 + 2)?;
+        if left & 0x8000 != 0 || right & 0x8000 != 0 {
+            // This row is fully transparent.
+            continue;
+        }
+
+        let pixels = &mut image.pixels[row * width..(row + 1) * width];
+        let mut x = usize::from(left);
+        let mut pos = data.u32(cmd_table + row * 4)? as usize;
+        let mut put = |x: &mut usize, pixel: Pixel| match pixels.get_mut(*x) {
+            Some(target) => {
+                *target = pixel;
+                *x += 1;
+                Ok(())
+            }
+            None => Err(ReadSLPError::RowTooLong { frame, row }),
+        };
+
+        loop {
+            let command = data.u8(pos)?;
+            pos += 1;
+            match command & 0x03 {
+                // Lesser block copy
+                0x00 => {
+                    let count = usize::from(command >> 2);
+                    for &index in data.slice(pos, count)? {
+                        put(&mut x, Pixel::Color(index))?;
+                    }
+                    pos += count;
+                    continue;
+                }
+                // Lesser skip
+                0x01 => {
+                    x += usize::from(command >> 2);
+                    continue;
+                }
+                _ => (),
+            }
+
+            match command & 0x0F {
+                // Greater block copy
+                0x02 => {
+                    let count = (usize::from(command & 0xF0) << 4) + usize::from(data.u8(pos)?);
+                    pos += 1;
+                    for &index in data.slice(pos, count)? {
+                        put(&mut x, Pixel::Color(index))?;
+                    }
+                    pos += count;
+                }
+                // Greater skip
+                0x03 => {
+                    x += (usize::from(command & 0xF0) << 4) + usize::from(data.u8(pos)?);
+                    pos += 1;
+                }
+                // Player color block copy
+                0x06 => {
+                    let count = read_count(data, command, &mut pos)?;
+                    for &index in data.slice(pos, count)? {
+                        put(&mut x, Pixel::PlayerColor(index))?;
+                    }
+                    pos += count;
+                }
+                // Fill
+                0x07 => {
+                    let count = read_count(data, command, &mut pos)?;
+                    let index = data.u8(pos)?;
+                    pos += 1;
+                    for _ in 0..count {
+                        put(&mut x, Pixel::Color(index))?;
+                    }
+                }
+                // Player color fill
+                0x0A => {
+                    let count = read_count(data, command, &mut pos)?;
+                    let index = data.u8(pos)?;
+                    pos += 1;
+                    for _ in 0..count {
+                        put(&mut x, Pixel::PlayerColor(index))?;
+                    }
+                }
+                // Shadow
+                0x0B => {
+                    let count = read_count(data, command, &mut pos)?;
+                    for _ in 0..count {
+                        put(&mut x, Pixel::Shadow)?;
+                    }
+                }
+                0x0E => match command {
+                    // Rendering hints and color table switches don't affect the pixels.
+                    0x0E | 0x1E | 0x2E | 0x3E => (),
+                    0x4E => put(&mut x, Pixel::PlayerOutline)?,
+                    0x6E => put(&mut x, Pixel::BlackOutline)?,
+                    0x5E | 0x7E => {
+                        let count = data.u8(pos)?;
+                        pos += 1;
+                        let pixel = if command == 0x5E {
+                            Pixel::PlayerOutline
+                        } else {
+                            Pixel::BlackOutline
+                        };
+                        for _ in 0..count {
+                            put(&mut x, pixel)?;
+                        }
+                    }
+                    _ => {
+                        return Err(ReadSLPError::InvalidCommand {
+                            frame,
+                            row,
+                            command,
+                        })
+                    }
+                },
+                // End of row
+                0x0F => break,
+                _ => {
+                    return Err(ReadSLPError::InvalidCommand {
+                        frame,
+                        row,
+                        command,
+                    })
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+fn read_frames(bytes: &[u8], offset: usize, count: usize) -> Result<Vec<SLPFrame>> {
+    let mut frames = Vec::with_capacity(count.min(bytes.len() / 32));
+    for frame in 0..count {
+        let data = Data { bytes, frame };
+        let info = data.frame_info(offset + frame * 32)?;
+        let image = decode_frame(&data, &info)?;
+        frames.push(SLPFrame {
+            image,
+            hotspot: (info.hotspot_x, info.hotspot_y),
+            palette_offset: info.palette_offset,
+            properties: info.properties,
+        });
+    }
+    Ok(frames)
+}
+
+impl SLP {
+    /// Read and decode an SLP file.
+    pub fn from<R: Read>(input: &mut R) -> Result<SLP> {
+        let mut bytes = vec![];
+        input.read_to_end(&mut bytes)?;
+        SLP::from_bytes(&bytes)
+    }
+
+    /// Decode an SLP file from a byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SLP> {
+        let header = Data { bytes, frame: 0 }.slice(0, 32).map_err(|_| {
+            ReadSLPError::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "SLP header is incomplete",
+            ))
+        })?;
+        let mut version = [0; 4];
+        version.copy_from_slice(&header[0..4]);
+
+        let mut slp = SLP::new(version);
+        if version == VERSION_2_0 || version == VERSION_3_0 {
+            let num_frames = LE::read_i32(&header[4..]).max(0) as usize;
+            slp.comment.copy_from_slice(&header[8..32]);
+            slp.frames = read_frames(bytes, 32, num_frames)?;
+        } else if is_version_4(version) {
+            let num_frames = usize::from(LE::read_u16(&header[4..]));
+            slp.frame_type = LE::read_u16(&header[6..]);
+            slp.num_directions = LE::read_u16(&header[8..]);
+            slp.frames_per_direction = LE::read_u16(&header[10..]);
+            slp.palette_id = LE::read_u32(&header[12..]);
+            let offset_main = LE::read_u32(&header[16..]) as usize;
+            let offset_secondary = LE::read_u32(&header[20..]) as usize;
+            slp.frames = read_frames(bytes, offset_main, num_frames)?;
+            if offset_secondary != 0 {
+                slp.secondary_frames = read_frames(bytes, offset_secondary, num_frames)?;
+            }
+        } else {
+            return Err(ReadSLPError::UnsupportedVersion(version));
+        }
+
+        Ok(slp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2.0N file with a single 4×3 frame with hotspot (2, 1):
+    ///
+    /// ```text
+    /// . 5 6 S
+    /// . . . .
+    /// O P o .
+    /// ```
+    ///
+    /// where S is a shadow, O a player outline, P player color 3, and o a black outline.
+    fn example() -> Vec<u8> {
+        let mut bytes = b"2.0N\x01\x00\x00\x00ArtDesk 1.00 SLP Writer\0".to_vec();
+        for value in &[76, 64, 0, 0x10, 4, 3, 2, 1] {
+            bytes.extend_from_slice(&u32::to_le_bytes(*value));
+        }
+        // Outline table
+        bytes.extend_from_slice(&[1, 0, 0, 0, 0, 0x80, 0, 0x80, 0, 0, 1, 0]);
+        // Command table
+        for offset in &[88u32, 93, 94] {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        // Row 0: copy 2 pixels, 1 shadow pixel
+        bytes.extend_from_slice(&[0x08, 5, 6, 0x1B, 0x0F]);
+        // Row 1
+        bytes.extend_from_slice(&[0x0F]);
+        // Row 2: player outline, 1 player color pixel, black outline
+        bytes.extend_from_slice(&[0x4E, 0x16, 3, 0x6E, 0x0F]);
+        bytes
+    }
+
+    #[test]
+    fn decode_frame() {
+        let slp = SLP::from(&mut &example()[..]).unwrap();
+        assert_eq!(slp.version(), VERSION_2_0);
+        assert_eq!(slp.len(), 1);
+        let frame = &slp.frames()[0];
+        assert_eq!(frame.hotspot(), (2, 1));
+        assert_eq!(frame.properties(), 0x10);
+
+        use Pixel::*;
+        let image = frame.image();
+        assert_eq!((image.width(), image.height()), (4, 3));
+        assert_eq!(
+            image.pixels(),
+            &[
+                Transparent,
+                Color(5),
+                Color(6),
+                Shadow,
+                Transparent,
+                Transparent,
+                Transparent,
+                Transparent,
+                PlayerOutline,
+                PlayerColor(3),
+                BlackOutline,
+                Transparent,
+            ]
+        );
+    }
+
+    #[test]
+    fn corrupt_files() {
+        let bytes = example();
+        assert!(matches!(
+            SLP::from_bytes(&bytes[..20]),
+            Err(ReadSLPError::IoError(_))
+        ));
+        assert!(matches!(
+            SLP::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReadSLPError::OutOfBounds { frame: 0, .. })
+        ));
+
+        let mut unknown = bytes.clone();
+        unknown[0..4].copy_from_slice(b"1.0N");
+        assert!(matches!(
+            SLP::from_bytes(&unknown),
+            Err(ReadSLPError::UnsupportedVersion(_))
+        ));
+
+        let mut too_long = bytes.clone();
+        // Copy 4 pixels in a row that only has space for 3.
+        too_long[88] = 0x10;
+        assert!(matches!(
+            SLP::from_bytes(&too_long),
+            Err(ReadSLPError::RowTooLong { frame: 0, row: 0 })
+        ));
+
+        let mut bad_command = bytes.clone();
+        bad_command[94] = 0xFE;
+        assert!(matches!(
+            SLP::from_bytes(&bad_command),
+            Err(ReadSLPError::InvalidCommand {
+                row: 2,
+                command: 0xFE,
+                ..
+            })
+        ));
+
+        // A huge frame without any row data fails before its pixels are allocated.
+        let mut huge = bytes[..64].to_vec();
+        huge[48..56].copy_from_slice(&[0xFF, 0x7F, 0, 0, 0xFF, 0x7F, 0, 0]);
+        assert!(matches!(
+            SLP::from_bytes(&huge),
+            Err(ReadSLPError::OutOfBounds { frame: 0, .. })
+        ));
+
+        let mut rgba = bytes;
+        rgba[44] = 0x07;
+        assert!(matches!(
+            SLP::from_bytes(&rgba),
+            Err(ReadSLPError::UnsupportedFrameFormat { frame: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+//! SMP is the uncompressed sprite format used by the Age of Empires 2: Definitive Edition beta.
+//!
+//! SMP frames have the same layers as SMX frames, but every drawn pixel is stored in 4 bytes, and
+//! each row has an offset into the drawing commands. This module can read SMP files; they can be
+//! converted to SMX using `SMP::into_smx`.
+//!
+//! Pixels in SMP files store a palette number, of which only the palette section is kept. The
+//! damage modifier bytes are discarded.
+use crate::smx::{
+    decode_commands, Data, Layer, MainPixel, ReadSMXError, SMXFrame, PLAYER_COLOR, SMX,
+};
+use byteorder::{ByteOrder, LE};
+use std::io::Read;
+
+/// The signature at the start of SMP files.
+pub const SMP_SIGNATURE: [u8; 4] = *b"SMP$";
+
+/// Layer type for main graphics layers.
+const LAYER_MAIN: u32 = 0x02;
+/// Layer type for shadow layers.
+const LAYER_SHADOW: u32 = 0x04;
+/// Layer type for outline layers.
+const LAYER_OUTLINE: u32 = 0x08;
+
+type Result<T> = std::result::Result<T, ReadSMXError>;
+
+/// Layer header, as stored in SMP files.
+struct LayerHeader {
+    width: u32,
+    height: u32,
+    hotspot: (i32, i32),
+    layer_type: u32,
+    /// Offset of the row edge table, relative to the start of the frame.
+    edges: usize,
+    /// Offset of the row command offset table, relative to the start of the frame.
+    commands: usize,
+}
+
+fn read_layer_header(data: &Data<'_>, offset: usize) -> Result<LayerHeader> {
+    let header = data.slice(offset, 32)?;
+    Ok(LayerHeader {
+        width: LE::read_u32(&header[0..]),
+        height: LE::read_u32(&header[4..]),
+        hotspot: (LE::read_i32(&header[8..]), LE::read_i32(&header[12..])),
+        layer_type: LE::read_u32(&header[16..]),
+        edges: LE::read_u32(&header[20..]) as usize,
+        commands: LE::read_u32(&header[24..]) as usize,
+    })
+}
+
+/// Decode a layer that starts in the frame at `frame_offset`. `pixel` decodes the 4 bytes of a
+/// drawn pixel, given the command kind.
+fn read_layer<P>(
+    data: &Data<'_>,
+    frame_offset: usize,
+    header: &LayerHeader,
+    pixel: impl Fn(u8, &[u8]) -> P,
+) -> Result<Layer<P>>
+where
+    P: Copy + Default + PartialEq,
+{
+    let edges = frame_offset + header.edges;
+    let commands = frame_offset + header.commands;
+    let pixels = decode_commands(
+        data,
+        header.width,
+        header.height,
+        0,
+        |row, _| {
+            let left = data.u16(edges + row * 4)?;
+            let right = data.u16(edges + row * 4 + 2)?;
+            if left == 0xFFFF || right == 0xFFFF {
+                return Ok(None);
+            }
+            let start = data.u32(commands + row * 4)? as usize;
+            Ok(Some((left, frame_offset + start)))
+        },
+        |kind, pos| Ok((pixel(kind, data.slice(pos, 4)?), pos + 4)),
+    )?;
+    Ok(Layer::from_pixels(header.width, header.height, header.hotspot, pixels).unwrap())
+}
+
+/// An SMP sprite file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SMP {
+    version: u32,
+    num_animations: u32,
+    frames_per_animation: u32,
+    frames: Vec<SMXFrame>,
+}
+
+impl SMP {
+    /// Read and decode an SMP file.
+    pub fn from<R: Read>(input: &mut R) -> Result<SMP> {
+        let mut bytes = vec![];
+        input.read_to_end(&mut bytes)?;
+        SMP::from_bytes(&bytes)
+    }
+
+    /// Decode an SMP file from a byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SMP> {
+        let header = Data { bytes, frame: 0 }.slice(0, 64).map_err(|_| {
+            ReadSMXError::IoError(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "SMP header is incomplete",
+            ))
+        })?;
+        let mut signature = [0; 4];
+        signature.copy_from_slice(&header[0..4]);
+        if signature != SMP_SIGNATURE {
+            return Err(ReadSMXError::InvalidSignature(signature));
+        }
+        let version = LE::read_u32(&header[4..]);
+        let num_frames = LE::read_u32(&header[8..]) as usize;
+        let num_animations = LE::read_u32(&header[12..]);
+        let frames_per_animation = LE::read_u32(&header[16..]);
+
+        let mut frames = vec![];
+        for frame in 0..num_frames {
+            let data = Data { bytes, frame };
+            let frame_offset = data.u32(64 + frame * 4)? as usize;
+            let num_layers = data.u32(frame_offset)? as usize;
+            let mut smx_frame = SMXFrame::new(0);
+            for layer in 0..num_layers {
+                let header = read_layer_header(&data, frame_offset + 4 + layer * 32)?;
+                match header.layer_type {
+                    LAYER_MAIN => {
+                        let layer = read_layer(&data, frame_offset, &header, |kind, bytes| {
+                            let index = u16::from(bytes[1] & 0b11) << 8 | u16::from(bytes[0]);
+                            if kind == PLAYER_COLOR {
+                                MainPixel::PlayerColor(index)
+                            } else {
+                                MainPixel::Color(index)
+                            }
+                        })?;
+                        smx_frame.set_main(Some(layer));
+                    }
+                    LAYER_SHADOW => {
+                        let layer =
+                            read_layer(&data, frame_offset, &header, |_, bytes| Some(bytes[0]))?;
+                        smx_frame.set_shadow(Some(layer));
+                    }
+                    LAYER_OUTLINE => {
+                        let layer = read_layer(&data, frame_offset, &header, |_, _| true)?;
+                        smx_frame.set_outline(Some(layer));
+                    }
+                    // Other layers, like damage masks, are not supported.
+                    _ => (),
+                }
+            }
+            frames.push(smx_frame);
+        }
+
+        Ok(SMP {
+            version,
+            num_animations,
+            frames_per_animation,
+            frames,
+        })
+    }
+
+    /// Get the version field of the file.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Get the number of animations, usually one for each direction.
+    pub fn num_animations(&self) -> u32 {
+        self.num_animations
+    }
+
+    /// Get the number of frames in each animation.
+    pub fn frames_per_animation(&self) -> u32 {
+        self.frames_per_animation
+    }
+
+    /// Get the number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Get the frames in this file.
+    pub fn frames(&self) -> &[SMXFrame] {
+        &self.frames
+    }
+
+    /// Convert to an SMX file containing the same frames.
+    pub fn into_smx(self) -> SMX {
+        SMX::from_frames(self.frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    /// Build a layer header and the data it points to.
+    fn layer(
+        header: &mut Vec<u8>,
+        data: &mut Vec<u8>,
+        data_offset: usize,
+        size: (u32, u32),
+        layer_type: u32,
+        rows: &[Option<(u16, u16, &[u8])>],
+    ) {
+        let edges = data_offset + data.len();
+        for row in rows {
+            let (left, right) = row.map_or((0xFFFF, 0xFFFF), |(left, right, _)| (left, right));
+            data.write_u16::<LE>(left).unwrap();
+            data.write_u16::<LE>(right).unwrap();
+        }
+        let table = data_offset + data.len();
+        let mut commands = data_offset + data.len() + 4 * rows.len();
+        for row in rows {
+            data.write_u32::<LE>(commands as u32).unwrap();
+            commands += row.map_or(0, |(_, _, bytes)| bytes.len());
+        }
+        for (_, _, bytes) in rows.iter().flatten() {
+            data.extend_from_slice(bytes);
+        }
+        for value in &[
+            size.0,
+            size.1,
+            1,
+            2,
+            layer_type,
+            edges as u32,
+            table as u32,
+            0,
+        ] {
+            header.write_u32::<LE>(*value).unwrap();
+        }
+    }
+
+    #[test]
+    fn decode() {
+        let mut bytes = SMP_SIGNATURE.to_vec();
+        for value in &[0x0B, 1, 1, 1, 0, 0, 0] {
+            bytes.write_u32::<LE>(*value).unwrap();
+        }
+        bytes.extend_from_slice(&[0; 32]);
+        bytes.write_u32::<LE>(68).unwrap();
+
+        let mut header = vec![];
+        header.write_u32::<LE>(3).unwrap();
+        let data_offset = 4 + 3 * 32;
+        let mut data = vec![];
+        #[rustfmt::skip]
+        layer(&mut header, &mut data, data_offset, (4, 2), LAYER_MAIN, &[
+            None,
+            Some((1, 0, &[
+                0x01, 7, 0x01, 0, 0, // draw 1 pixel with index 0x107
+                0x06, 9, 0x00, 0, 0, 10, 0x02, 0, 0, // draw 2 player color pixels
+                0x03,
+            ])),
+        ]);
+        layer(
+            &mut header,
+            &mut data,
+            data_offset,
+            (2, 1),
+            LAYER_SHADOW,
+            &[Some((1, 0, &[0x01, 90, 0, 0, 0, 0x03]))],
+        );
+        layer(
+            &mut header,
+            &mut data,
+            data_offset,
+            (2, 1),
+            LAYER_OUTLINE,
+            &[Some((0, 1, &[0x01, 1, 0, 0, 0, 0x03]))],
+        );
+        bytes.extend(header);
+        bytes.extend(data);
+
+        let smp = SMP::from_bytes(&bytes).unwrap();
+        assert_eq!(smp.version(), 0x0B);
+        assert_eq!(smp.len(), 1);
+        let frame = &smp.frames()[0];
+        let main = frame.main().unwrap();
+        assert_eq!(main.hotspot(), (1, 2));
+        assert_eq!(
+            main.pixels(),
+            &[
+                MainPixel::Transparent,
+                MainPixel::Transparent,
+                MainPixel::Transparent,
+                MainPixel::Transparent,
+                MainPixel::Transparent,
+                MainPixel::Color(0x107),
+                MainPixel::PlayerColor(9),
+                MainPixel::PlayerColor(0x20A),
+            ]
+        );
+        assert_eq!(frame.shadow().unwrap().pixels(), &[None, Some(90)]);
+        assert_eq!(frame.outline().unwrap().pixels(), &[true, false]);
+
+        assert!(matches!(
+            SMP::from_bytes(&bytes[..bytes.len() - 3]),
+            Err(ReadSMXError::OutOfBounds { frame: 0, .. })
+        ));
+        assert!(matches!(
+            SMP::from_bytes(b"SMPX"),
+            Err(ReadSMXError::IoError(_))
+        ));
+
+        // SMP files can be converted to SMX.
+        let smx = smp.into_smx();
+        let mut encoded = vec![];
+        smx.write_to(&mut encoded).unwrap();
+        assert_eq!(SMX::from_bytes(&encoded).unwrap(), smx);
+    }
+}
@@ -0,0 +1,1284 @@
+//! SMX is the compressed sprite format used by Age of Empires 2: Definitive Edition.
+//!
+//! Each SMX frame consists of up to three layers: a main graphics layer, a shadow layer, and an
+//! outline layer. Main layer pixels are indices into 1024-color palettes, stored as an 8-bit index
+//! and a 2-bit palette section.
+//!
+//! Main layers use one of two pixel encodings. In "4plus1", each group of 4 pixels is stored as 4
+//! index bytes followed by 1 byte containing the 4 palette sections. In "8to5", each group of 2
+//! pixels is stored in 5 bytes: every pixel takes 20 bits, holding the 10-bit palette index and
+//! two damage mask values. Frames with a damage layer are written using "8to5".
+use crate::{IndexedImage, Pixel, SLPFrame};
+use byteorder::{ByteOrder, WriteBytesExt, LE};
+use genie_pal::Palette;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+/// The signature at the start of SMX files.
+pub const SMX_SIGNATURE: [u8; 4] = *b"SMPX";
+/// The SMX version written by this library.
+const SMX_VERSION: u16 = 2;
+
+/// Frame type bit for frames with a main graphics layer.
+const FRAME_MAIN: u8 = 0x01;
+/// Frame type bit for frames with a shadow layer.
+const FRAME_SHADOW: u8 = 0x02;
+/// Frame type bit for frames with an outline layer.
+const FRAME_OUTLINE: u8 = 0x04;
+/// Frame type bit for frames whose main layer uses the 8to5 encoding instead of 4plus1.
+const FRAME_8TO5: u8 = 0x08;
+
+/// Alpha value used for shadow pixels that are converted from SLP frames.
+pub const SLP_SHADOW_ALPHA: u8 = 0x80;
+
+/// Frames are run-length encoded in rows, so a corrupt size could otherwise allocate huge images.
+const MAX_LAYER_SIZE: u32 = 0x7FFF;
+
+#[derive(Debug)]
+pub enum ReadSMXError {
+    IoError(io::Error),
+    /// The file does not start with the expected signature.
+    InvalidSignature([u8; 4]),
+    /// The frame has an impossibly large size.
+    InvalidFrameSize {
+        frame: usize,
+    },
+    /// The frame refers to data past the end of the file.
+    OutOfBounds {
+        frame: usize,
+        offset: usize,
+    },
+    /// The frame contains an unknown drawing command.
+    InvalidCommand {
+        frame: usize,
+        row: usize,
+        command: u8,
+    },
+    /// The frame draws pixels outside of the layer.
+    RowTooLong {
+        frame: usize,
+        row: usize,
+    },
+}
+
+impl From<io::Error> for ReadSMXError {
+    fn from(err: io::Error) -> ReadSMXError {
+        ReadSMXError::IoError(err)
+    }
+}
+
+impl std::fmt::Display for ReadSMXError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReadSMXError::IoError(err) => write!(f, "{}", err),
+            ReadSMXError::InvalidSignature(signature) => write!(
+                f,
+                "invalid signature {:?}",
+                String::from_utf8_lossy(signature)
+            ),
+            ReadSMXError::InvalidFrameSize { frame } => {
+                write!(f, "frame {} has an invalid size", frame)
+            }
+            ReadSMXError::OutOfBounds { frame, offset } => write!(
+                f,
+                "frame {} refers to offset {}, past the end of the file",
+                frame, offset
+            ),
+            ReadSMXError::InvalidCommand {
+                frame,
+                row,
+                command,
+            } => write!(
+                f,
+                "invalid command {:#04x} in row {} of frame {}",
+                command, row, frame
+            ),
+            ReadSMXError::RowTooLong { frame, row } => {
+                write!(f, "row {} of frame {} is wider than the layer", row, frame)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReadSMXError {}
+
+type Result<T> = std::result::Result<T, ReadSMXError>;
+
+/// A pixel in a main graphics layer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MainPixel {
+    /// Nothing is drawn.
+    #[default]
+    Transparent,
+    /// An index into the frame's 1024-color palette.
+    Color(u16),
+    /// An index into the palette of the player that owns the unit.
+    PlayerColor(u16),
+}
+
+/// The damage mask values of a main layer pixel, which control how the pixel changes as a
+/// building gets damaged. They are only stored by the 8to5 encoding.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DamageMask {
+    /// The first damage modifier, a 6-bit value.
+    pub first: u8,
+    /// The second damage modifier, a 4-bit value.
+    pub second: u8,
+}
+
+/// A layer of an SMX or SMP frame.
+///
+/// The default pixel value is transparent: `MainPixel::Transparent` for main layers, `None` for
+/// shadow layers, and `false` for outline layers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer<P> {
+    width: u32,
+    height: u32,
+    hotspot: (i32, i32),
+    pixels: Vec<P>,
+}
+
+/// A main graphics layer.
+pub type MainLayer = Layer<MainPixel>;
+/// A shadow layer, with the opacity of each shadow pixel.
+pub type ShadowLayer = Layer<Option<u8>>;
+/// An outline layer, drawn when a unit is hidden behind a building.
+pub type OutlineLayer = Layer<bool>;
+/// The damage mask values of the pixels in a main layer.
+pub type DamageLayer = Layer<DamageMask>;
+
+impl<P> Layer<P>
+where
+    P: Copy + Default + PartialEq,
+{
+    /// Create a fully transparent layer.
+    pub fn new(width: u32, height: u32, hotspot: (i32, i32)) -> Self {
+        Self {
+            width,
+            height,
+            hotspot,
+            pixels: vec![P::default(); width as usize * height as usize],
+        }
+    }
+
+    /// Create a layer from a list of pixels, row by row. Returns None if the number of pixels
+    /// does not match the size.
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        hotspot: (i32, i32),
+        pixels: Vec<P>,
+    ) -> Option<Self> {
+        if pixels.len() == width as usize * height as usize {
+            Some(Self {
+                width,
+                height,
+                hotspot,
+                pixels,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get the width of the layer in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the layer in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the hotspot: the point in the layer that is placed at the position of the unit.
+    pub fn hotspot(&self) -> (i32, i32) {
+        self.hotspot
+    }
+
+    /// Set the hotspot.
+    pub fn set_hotspot(&mut self, hotspot: (i32, i32)) {
+        self.hotspot = hotspot;
+    }
+
+    /// Get all pixels, row by row.
+    pub fn pixels(&self) -> &[P] {
+        &self.pixels
+    }
+
+    /// Iterate over the rows of the layer.
+    pub fn rows(&self) -> impl Iterator<Item = &[P]> {
+        let width = self.width as usize;
+        (0..self.height as usize).map(move |y| &self.pixels[y * width..(y + 1) * width])
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Get the pixel at a position, or None if the position is outside the layer.
+    pub fn get(&self, x: u32, y: u32) -> Option<P> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
+
+    /// Set the pixel at a position. Returns false if the position is outside the layer.
+    pub fn set(&mut self, x: u32, y: u32, pixel: P) -> bool {
+        match self.index(x, y) {
+            Some(index) => {
+                self.pixels[index] = pixel;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check if the layer contains any pixels that are not transparent.
+    fn is_blank(&self) -> bool {
+        self.pixels.iter().all(|p| *p == P::default())
+    }
+}
+
+impl MainLayer {
+    /// Convert the layer to 8-bit RGBA pixel data.
+    ///
+    /// Colors are looked up in `palette`, and player colors in `player_palette`, which should be
+    /// the palette for the color of the player that owns the unit. Colors that are missing from
+    /// the palettes are drawn as black.
+    pub fn to_rgba(&self, palette: &Palette, player_palette: &Palette) -> Vec<u8> {
+        let color = |palette: &Palette, index: u16| match palette.get(usize::from(index)) {
+            Some(color) => [color.r, color.g, color.b, 0xFF],
+            None => [0, 0, 0, 0xFF],
+        };
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            let bytes = match *pixel {
+                MainPixel::Transparent => [0, 0, 0, 0],
+                MainPixel::Color(index) => color(palette, index),
+                MainPixel::PlayerColor(index) => color(player_palette, index),
+            };
+            rgba.extend_from_slice(&bytes);
+        }
+        rgba
+    }
+}
+
+impl ShadowLayer {
+    /// Convert the layer to 8-bit RGBA pixel data, drawing shadows as black with their opacity.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| [0, 0, 0, pixel.unwrap_or(0)])
+            .collect()
+    }
+}
+
+/// A frame in an SMX or SMP file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SMXFrame {
+    palette: u8,
+    main: Option<MainLayer>,
+    shadow: Option<ShadowLayer>,
+    outline: Option<OutlineLayer>,
+    damage: Option<DamageLayer>,
+}
+
+impl SMXFrame {
+    /// Create a frame without any layers, using the palette with the given number.
+    pub fn new(palette: u8) -> Self {
+        Self {
+            palette,
+            ..Default::default()
+        }
+    }
+
+    /// Get the number of the palette used by this frame.
+    pub fn palette(&self) -> u8 {
+        self.palette
+    }
+
+    /// Set the number of the palette used by this frame.
+    pub fn set_palette(&mut self, palette: u8) {
+        self.palette = palette;
+    }
+
+    /// Get the main graphics layer.
+    pub fn main(&self) -> Option<&MainLayer> {
+        self.main.as_ref()
+    }
+
+    /// Get the shadow layer.
+    pub fn shadow(&self) -> Option<&ShadowLayer> {
+        self.shadow.as_ref()
+    }
+
+    /// Get the outline layer.
+    pub fn outline(&self) -> Option<&OutlineLayer> {
+        self.outline.as_ref()
+    }
+
+    /// Set or remove the main graphics layer.
+    pub fn set_main(&mut self, layer: Option<MainLayer>) {
+        self.main = layer;
+    }
+
+    /// Set or remove the shadow layer.
+    pub fn set_shadow(&mut self, layer: Option<ShadowLayer>) {
+        self.shadow = layer;
+    }
+
+    /// Set or remove the outline layer.
+    pub fn set_outline(&mut self, layer: Option<OutlineLayer>) {
+        self.outline = layer;
+    }
+
+    /// Get the damage mask values of the main layer, if the frame uses the 8to5 encoding.
+    pub fn damage(&self) -> Option<&DamageLayer> {
+        self.damage.as_ref()
+    }
+
+    /// Set or remove the damage mask values of the main layer. The damage layer must have the
+    /// same size as the main layer. Frames with a damage layer are written using the 8to5
+    /// encoding, which only stores damage mask values for pixels that are drawn.
+    pub fn set_damage(&mut self, layer: Option<DamageLayer>) {
+        self.damage = layer;
+    }
+
+    /// Split an SLP frame into layers.
+    ///
+    /// Colors become main layer pixels, shadows become shadow layer pixels with an opacity of
+    /// `SLP_SHADOW_ALPHA`, and both types of outlines become outline layer pixels. Shadow and
+    /// outline layers are only added if the SLP frame contains such pixels.
+    pub fn from_slp_frame(frame: &SLPFrame, palette: u8) -> Self {
+        let image = frame.image();
+        let (width, height, hotspot) = (image.width(), image.height(), frame.hotspot());
+        let pixels = image.pixels();
+
+        let main = pixels
+            .iter()
+            .map(|&pixel| match pixel {
+                Pixel::Color(index) => MainPixel::Color(u16::from(index)),
+                Pixel::PlayerColor(index) => MainPixel::PlayerColor(u16::from(index)),
+                _ => MainPixel::Transparent,
+            })
+            .collect();
+        let shadow = pixels
+            .iter()
+            .map(|&pixel| Some(SLP_SHADOW_ALPHA).filter(|_| pixel == Pixel::Shadow))
+            .collect();
+        let outline = pixels
+            .iter()
+            .map(|&pixel| pixel == Pixel::PlayerOutline || pixel == Pixel::BlackOutline)
+            .collect();
+
+        let shadow = Layer::from_pixels(width, height, hotspot, shadow).unwrap();
+        let outline = Layer::from_pixels(width, height, hotspot, outline).unwrap();
+        Self {
+            palette,
+            main: Layer::from_pixels(width, height, hotspot, main),
+            shadow: Some(shadow).filter(|layer| !layer.is_blank()),
+            outline: Some(outline).filter(|layer| !layer.is_blank()),
+            damage: None,
+        }
+    }
+
+    /// Merge the layers into an SLP frame, using the size and hotspot of the main layer.
+    ///
+    /// Main layer pixels take precedence over outlines, which take precedence over shadows.
+    /// Outlines become player color outlines. Returns None if there is no main layer, or if it
+    /// uses colors outside the first 256 palette entries, which SLP frames can not store.
+    pub fn to_slp_frame(&self) -> Option<SLPFrame> {
+        let main = self.main.as_ref()?;
+        let mut image = IndexedImage::new(main.width, main.height);
+        let (hx, hy) = main.hotspot;
+
+        // Copy another layer's pixels onto the image, aligning the hotspots.
+        let mut overlay = |layer_hotspot: (i32, i32), pixels: Vec<(u32, u32, Pixel)>| {
+            for (x, y, pixel) in pixels {
+                let x = i64::from(x) - i64::from(layer_hotspot.0) + i64::from(hx);
+                let y = i64::from(y) - i64::from(layer_hotspot.1) + i64::from(hy);
+                if let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) {
+                    image.set(x, y, pixel);
+                }
+            }
+        };
+        fn drawn<P: Copy + Default + PartialEq>(
+            layer: &Layer<P>,
+            pixel: Pixel,
+        ) -> Vec<(u32, u32, Pixel)> {
+            let width = layer.width.max(1);
+            layer
+                .pixels
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| **p != P::default())
+                .map(|(i, _)| (i as u32 % width, i as u32 / width, pixel))
+                .collect()
+        }
+
+        if let Some(shadow) = &self.shadow {
+            overlay(shadow.hotspot, drawn(shadow, Pixel::Shadow));
+        }
+        if let Some(outline) = &self.outline {
+            overlay(outline.hotspot, drawn(outline, Pixel::PlayerOutline));
+        }
+        for (i, pixel) in main.pixels.iter().enumerate() {
+            let pixel = match *pixel {
+                MainPixel::Transparent => continue,
+                MainPixel::Color(index) => Pixel::Color(u8::try_from(index).ok()?),
+                MainPixel::PlayerColor(index) => Pixel::PlayerColor(u8::try_from(index).ok()?),
+            };
+            image.set(i as u32 % main.width, i as u32 / main.width, pixel);
+        }
+
+        Some(SLPFrame::new(image, main.hotspot))
+    }
+}
+
+/// Bounds-checked reads from a sprite file, attributed to a frame for error reporting.
+pub(crate) struct Data<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) frame: usize,
+}
+
+impl<'a> Data<'a> {
+    pub(crate) fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or(ReadSMXError::OutOfBounds {
+                frame: self.frame,
+                offset,
+            })
+    }
+
+    pub(crate) fn u8(&self, offset: usize) -> Result<u8> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(LE::read_u16(self.slice(offset, 2)?))
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(LE::read_u32(self.slice(offset, 4)?))
+    }
+}
+
+/// Kind of a drawing command. SMX and SMP files use the same command bytes: the low 2 bits are
+/// the kind, and the high 6 bits are the number of pixels minus one.
+const SKIP: u8 = 0b00;
+const DRAW: u8 = 0b01;
+pub(crate) const PLAYER_COLOR: u8 = 0b10;
+const END_OF_ROW: u8 = 0b11;
+
+/// Decode the drawing commands of a layer, starting at position `start`.
+///
+/// `row_start` is called with the position after the previous row's commands, and returns the
+/// left edge of the row and the position of its first command, or None if the row is empty.
+/// `pixel` is called for every drawn pixel with the command kind and the position just after the
+/// command or the previous pixel, and returns the pixel and the position after its data.
+pub(crate) fn decode_commands<P>(
+    data: &Data<'_>,
+    width: u32,
+    height: u32,
+    start: usize,
+    mut row_start: impl FnMut(usize, usize) -> Result<Option<(u16, usize)>>,
+    mut pixel: impl FnMut(u8, usize) -> Result<(P, usize)>,
+) -> Result<Vec<P>>
+where
+    P: Copy + Default,
+{
+    let frame = data.frame;
+    if width > MAX_LAYER_SIZE || height > MAX_LAYER_SIZE {
+        return Err(ReadSMXError::InvalidFrameSize { frame });
+    }
+    let width = width as usize;
+    // The size comes from the file, so the pixels are only allocated for rows that exist.
+    let mut pixels = vec![];
+    let mut pos = start;
+    for row in 0..height as usize {
+        let row_start = row_start(row, pos)?;
+        pixels
+            .try_reserve(width)
+            .map_err(|_| ReadSMXError::InvalidFrameSize { frame })?;
+        pixels.resize((row + 1) * width, P::default());
+        let left = match row_start {
+            Some((left, start)) => {
+                pos = start;
+                left
+            }
+            None => continue,
+        };
+        let row_pixels = &mut pixels[row * width..(row + 1) * width];
+        let mut x = usize::from(left);
+        loop {
+            let command = data.u8(pos)?;
+            pos += 1;
+            let count = usize::from(command >> 2) + 1;
+            match command & 0b11 {
+                SKIP => x += count,
+                END_OF_ROW => break,
+                kind => {
+                    for _ in 0..count {
+                        let (value, next) = pixel(kind, pos)?;
+                        pos = next;
+                        match row_pixels.get_mut(x) {
+                            Some(target) => *target = value,
+                            None => return Err(ReadSMXError::RowTooLong { frame, row }),
+                        }
+                        x += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+/// Encode the drawing commands of a layer, returning the row edges and the commands.
+///
+/// `kind` returns the command kind for a pixel that is not transparent, and `emit` writes the
+/// data for the pixels of a command directly after the command.
+fn encode_commands<P>(
+    layer: &Layer<P>,
+    kind: impl Fn(P) -> u8,
+    mut emit: impl FnMut(&[P], &mut Vec<u8>),
+) -> (Vec<(u16, u16)>, Vec<Vec<u8>>)
+where
+    P: Copy + Default + PartialEq,
+{
+    let mut edges = vec![];
+    let mut rows = vec![];
+    for row in layer.rows() {
+        let mut commands = vec![];
+        let transparent = |p: &&P| **p == P::default();
+        let left = row.iter().take_while(transparent).count();
+        if left == row.len() {
+            edges.push((0xFFFF, 0xFFFF));
+            rows.push(commands);
+            continue;
+        }
+        let right = row.iter().rev().take_while(transparent).count();
+
+        let pixels = &row[left..row.len() - right];
+        let mut i = 0;
+        while i < pixels.len() {
+            let command = if pixels[i] == P::default() {
+                SKIP
+            } else {
+                kind(pixels[i])
+            };
+            let run = pixels[i..]
+                .iter()
+                .take(64)
+                .take_while(|&&p| {
+                    let same = if p == P::default() { SKIP } else { kind(p) };
+                    same == command
+                })
+                .count();
+            commands.push(((run - 1) as u8) << 2 | command);
+            if command != SKIP {
+                emit(&pixels[i..i + run], &mut commands);
+            }
+            i += run;
+        }
+        commands.push(END_OF_ROW);
+        edges.push((left as u16, right as u16));
+        rows.push(commands);
+    }
+    (edges, rows)
+}
+
+/// Decode a pixel from 4plus1 encoded data.
+fn read_4plus1(pixel_data: &Data<'_>, offset: usize, index: usize) -> Result<u16> {
+    let group = offset + (index / 4) * 5;
+    let color = pixel_data.u8(group + index % 4)?;
+    let sections = pixel_data.u8(group + 4)?;
+    let section = (sections >> ((index % 4) * 2)) & 0b11;
+    Ok(u16::from(section) << 8 | u16::from(color))
+}
+
+/// Encode palette indices using 4plus1 encoding.
+fn write_4plus1(indices: &[u16]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(indices.len().div_ceil(4) * 5);
+    for group in indices.chunks(4) {
+        let mut sections = 0;
+        for (i, index) in group.iter().enumerate() {
+            data.push(*index as u8);
+            sections |= (((index >> 8) & 0b11) as u8) << (i * 2);
+        }
+        data.resize(data.len() + 4 - group.len(), 0);
+        data.push(sections);
+    }
+    data
+}
+
+/// Decode a pixel from 8to5 encoded data.
+fn read_8to5(pixel_data: &Data<'_>, offset: usize, index: usize) -> Result<(u16, DamageMask)> {
+    let group = pixel_data.slice(offset + (index / 2) * 5, 5)?;
+    let bits = LE::read_uint(group, 5) >> ((index % 2) * 20);
+    let damage = DamageMask {
+        first: ((bits >> 10) & 0x3F) as u8,
+        second: ((bits >> 16) & 0x0F) as u8,
+    };
+    Ok(((bits & 0x3FF) as u16, damage))
+}
+
+/// Encode palette indices and damage mask values using 8to5 encoding.
+fn write_8to5(pixels: &[(u16, DamageMask)]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(pixels.len().div_ceil(2) * 5);
+    for group in pixels.chunks(2) {
+        let mut bits = 0;
+        for (i, (index, damage)) in group.iter().enumerate() {
+            let pixel = u64::from(index & 0x3FF)
+                | u64::from(damage.first & 0x3F) << 10
+                | u64::from(damage.second & 0x0F) << 16;
+            bits |= pixel << (i * 20);
+        }
+        let mut bytes = [0; 8];
+        LE::write_u64(&mut bytes, bits);
+        data.extend_from_slice(&bytes[..5]);
+    }
+    data
+}
+
+/// Layer header, as stored in SMX files.
+struct LayerHeader {
+    width: u16,
+    height: u16,
+    hotspot_x: i16,
+    hotspot_y: i16,
+    length: u32,
+}
+
+fn read_layer_header(data: &Data<'_>, offset: usize) -> Result<LayerHeader> {
+    let header = data.slice(offset, 16)?;
+    Ok(LayerHeader {
+        width: LE::read_u16(&header[0..]),
+        height: LE::read_u16(&header[2..]),
+        hotspot_x: LE::read_i16(&header[4..]),
+        hotspot_y: LE::read_i16(&header[6..]),
+        length: LE::read_u32(&header[8..]),
+    })
+}
+
+/// Read a layer at `offset`, returning the layer and the offset of the next layer.
+fn read_layer<P: Copy + Default>(
+    data: &Data<'_>,
+    offset: usize,
+    decode: impl FnOnce(&LayerHeader, usize, usize) -> Result<Vec<P>>,
+) -> Result<(Layer<P>, usize)> {
+    let header = read_layer_header(data, offset)?;
+    let body = offset + 16;
+    let next = body + header.length as usize;
+    // The row edges come first, then the layer-specific data.
+    let commands = body + 4 * usize::from(header.height);
+    let pixels = decode(&header, body, commands)?;
+    let layer = Layer {
+        width: u32::from(header.width),
+        height: u32::from(header.height),
+        hotspot: (i32::from(header.hotspot_x), i32::from(header.hotspot_y)),
+        pixels,
+    };
+    Ok((layer, next))
+}
+
+/// Read the left edge of a row from the row edge table at `edges`. Rows are stored one after
+/// another, and empty rows have no commands at all.
+fn row_edges<'a>(
+    data: &'a Data<'a>,
+    edges: usize,
+) -> impl FnMut(usize, usize) -> Result<Option<(u16, usize)>> + 'a {
+    move |row, pos| {
+        let left = data.u16(edges + row * 4)?;
+        let right = data.u16(edges + row * 4 + 2)?;
+        if left == 0xFFFF || right == 0xFFFF {
+            return Ok(None);
+        }
+        Ok(Some((left, pos)))
+    }
+}
+
+/// Read a main layer, and its damage mask values if it uses the 8to5 encoding.
+fn read_main_layer(
+    data: &Data<'_>,
+    offset: usize,
+    eight_to_five: bool,
+) -> Result<(MainLayer, Option<DamageLayer>, usize)> {
+    let (layer, next) = read_layer(data, offset, |header, edges, pos| {
+        let commands_len = data.u32(pos)? as usize;
+        let pixels_len = data.u32(pos + 4)? as usize;
+        let commands = pos + 8;
+        let pixel_data = commands + commands_len;
+        data.slice(pixel_data, pixels_len)?;
+        let mut next_pixel = 0;
+        decode_commands(
+            data,
+            u32::from(header.width),
+            u32::from(header.height),
+            commands,
+            row_edges(data, edges),
+            |kind, pos| {
+                let (index, damage) = if eight_to_five {
+                    read_8to5(data, pixel_data, next_pixel)?
+                } else {
+                    (
+                        read_4plus1(data, pixel_data, next_pixel)?,
+                        DamageMask::default(),
+                    )
+                };
+                next_pixel += 1;
+                let pixel = if kind == PLAYER_COLOR {
+                    MainPixel::PlayerColor(index)
+                } else {
+                    MainPixel::Color(index)
+                };
+                Ok(((pixel, damage), pos))
+            },
+        )
+    })?;
+
+    let (width, height, hotspot) = (layer.width, layer.height, layer.hotspot);
+    let (main, damage): (Vec<_>, Vec<_>) = layer.pixels.into_iter().unzip();
+    let main = Layer::from_pixels(width, height, hotspot, main).unwrap();
+    let damage =
+        Some(Layer::from_pixels(width, height, hotspot, damage).unwrap()).filter(|_| eight_to_five);
+    Ok((main, damage, next))
+}
+
+fn read_shadow_layer(data: &Data<'_>, offset: usize) -> Result<(ShadowLayer, usize)> {
+    read_layer(data, offset, |header, edges, pos| {
+        decode_commands(
+            data,
+            u32::from(header.width),
+            u32::from(header.height),
+            pos + 4,
+            row_edges(data, edges),
+            |_, pos| Ok((Some(data.u8(pos)?), pos + 1)),
+        )
+    })
+}
+
+fn read_outline_layer(data: &Data<'_>, offset: usize) -> Result<(OutlineLayer, usize)> {
+    read_layer(data, offset, |header, edges, pos| {
+        decode_commands(
+            data,
+            u32::from(header.width),
+            u32::from(header.height),
+            pos + 4,
+            row_edges(data, edges),
+            |_, pos| Ok((true, pos)),
+        )
+    })
+}
+
+/// An SMX sprite file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SMX {
+    frames: Vec<SMXFrame>,
+}
+
+impl SMX {
+    /// Create an empty SMX file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an SMX file containing the given frames.
+    pub fn from_frames(frames: Vec<SMXFrame>) -> Self {
+        Self { frames }
+    }
+
+    /// Read and decode an SMX file.
+    pub fn from<R: Read>(input: &mut R) -> Result<SMX> {
+        let mut bytes = vec![];
+        input.read_to_end(&mut bytes)?;
+        SMX::from_bytes(&bytes)
+    }
+
+    /// Decode an SMX file from a byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SMX> {
+        let header = Data { bytes, frame: 0 }.slice(0, 32).map_err(|_| {
+            ReadSMXError::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "SMX header is incomplete",
+            ))
+        })?;
+        let mut signature = [0; 4];
+        signature.copy_from_slice(&header[0..4]);
+        if signature != SMX_SIGNATURE {
+            return Err(ReadSMXError::InvalidSignature(signature));
+        }
+        let num_frames = usize::from(LE::read_u16(&header[6..]));
+
+        let mut frames = vec![];
+        let mut offset = 32;
+        for frame in 0..num_frames {
+            let data = Data { bytes, frame };
+            let frame_type = data.u8(offset)?;
+            let mut smx_frame = SMXFrame::new(data.u8(offset + 1)?);
+            offset += 6;
+            if frame_type & FRAME_MAIN != 0 {
+                let (layer, damage, next) =
+                    read_main_layer(&data, offset, frame_type & FRAME_8TO5 != 0)?;
+                smx_frame.main = Some(layer);
+                smx_frame.damage = damage;
+                offset = next;
+            }
+            if frame_type & FRAME_SHADOW != 0 {
+                let (layer, next) = read_shadow_layer(&data, offset)?;
+                smx_frame.shadow = Some(layer);
+                offset = next;
+            }
+            if frame_type & FRAME_OUTLINE != 0 {
+                let (layer, next) = read_outline_layer(&data, offset)?;
+                smx_frame.outline = Some(layer);
+                offset = next;
+            }
+            frames.push(smx_frame);
+        }
+
+        Ok(SMX { frames })
+    }
+
+    /// Get the number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Get the frames in this file.
+    pub fn frames(&self) -> &[SMXFrame] {
+        &self.frames
+    }
+
+    /// Get mutable access to the frames in this file.
+    pub fn frames_mut(&mut self) -> &mut Vec<SMXFrame> {
+        &mut self.frames
+    }
+
+    /// Add a frame to the end of the file.
+    pub fn add_frame(&mut self, frame: SMXFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Encode the SMX file and write it to an output stream.
+    ///
+    /// Returns an `InvalidInput` error if there are more than 65535 frames, if a layer does not
+    /// fit the 16-bit sizes and hotspots in SMX files, or if a damage layer does not have the
+    /// same size as its main layer.
+    pub fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        if self.frames.len() > usize::from(u16::MAX) {
+            return Err(invalid_input("too many frames"));
+        }
+
+        let mut body = vec![];
+        let mut uncompressed_size = 0;
+        for frame in &self.frames {
+            let mut frame_type = 0;
+            let mut layers = vec![];
+            if let Some(main) = &frame.main {
+                frame_type |= FRAME_MAIN;
+                // Damage mask values are only stored for drawn pixels.
+                let damage = match &frame.damage {
+                    Some(damage) if (damage.width, damage.height) == (main.width, main.height) => {
+                        frame_type |= FRAME_8TO5;
+                        damage.pixels.clone()
+                    }
+                    Some(_) => return Err(invalid_input("damage layer does not match main layer")),
+                    None => vec![DamageMask::default(); main.pixels.len()],
+                };
+                let pixels = main
+                    .pixels
+                    .iter()
+                    .zip(damage)
+                    .map(|(&pixel, damage)| match pixel {
+                        MainPixel::Transparent => (pixel, DamageMask::default()),
+                        _ => (pixel, damage),
+                    })
+                    .collect();
+                let layer =
+                    Layer::from_pixels(main.width, main.height, main.hotspot, pixels).unwrap();
+                let mut indices = vec![];
+                let (edges, rows) = encode_commands(
+                    &layer,
+                    |(p, _)| match p {
+                        MainPixel::PlayerColor(_) => PLAYER_COLOR,
+                        _ => DRAW,
+                    },
+                    |pixels, _| {
+                        indices.extend(pixels.iter().map(|&(p, damage)| match p {
+                            MainPixel::Color(index) | MainPixel::PlayerColor(index) => {
+                                (index, damage)
+                            }
+                            MainPixel::Transparent => (0, damage),
+                        }))
+                    },
+                );
+                let commands = rows.concat();
+                let pixel_data = if frame_type & FRAME_8TO5 != 0 {
+                    write_8to5(&indices)
+                } else {
+                    let indices: Vec<_> = indices.iter().map(|(index, _)| *index).collect();
+                    write_4plus1(&indices)
+                };
+                let mut data = vec![];
+                data.write_u32::<LE>(commands.len() as u32)?;
+                data.write_u32::<LE>(pixel_data.len() as u32)?;
+                data.extend(commands);
+                data.extend(pixel_data);
+                uncompressed_size += 4 * indices.len();
+                layers.push(encode_layer(main, &edges, data)?);
+            }
+            if let Some(shadow) = &frame.shadow {
+                frame_type |= FRAME_SHADOW;
+                let (edges, rows) = encode_commands(
+                    shadow,
+                    |_| DRAW,
+                    |pixels, out| out.extend(pixels.iter().map(|p| p.unwrap_or(0))),
+                );
+                layers.push(encode_layer(shadow, &edges, with_length(rows.concat())?)?);
+            }
+            if let Some(outline) = &frame.outline {
+                frame_type |= FRAME_OUTLINE;
+                let (edges, rows) = encode_commands(outline, |_| DRAW, |_, _| ());
+                layers.push(encode_layer(outline, &edges, with_length(rows.concat())?)?);
+            }
+
+            let layers = layers.concat();
+            uncompressed_size += layers.len();
+            body.write_u8(frame_type)?;
+            body.write_u8(frame.palette)?;
+            body.write_u32::<LE>(layers.len() as u32)?;
+            body.extend(layers);
+        }
+
+        output.write_all(&SMX_SIGNATURE)?;
+        output.write_u16::<LE>(SMX_VERSION)?;
+        output.write_u16::<LE>(self.frames.len() as u16)?;
+        output.write_u32::<LE>(body.len() as u32)?;
+        output.write_u32::<LE>(uncompressed_size as u32)?;
+        output.write_all(&[0; 16])?;
+        output.write_all(&body)?;
+        Ok(())
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Prefix command data with its length, as stored in shadow and outline layers.
+fn with_length(commands: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(commands.len() + 4);
+    data.write_u32::<LE>(commands.len() as u32)?;
+    data.extend(commands);
+    Ok(data)
+}
+
+/// Write a layer header, row edges, and layer data.
+fn encode_layer<P>(layer: &Layer<P>, edges: &[(u16, u16)], data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let width = u16::try_from(layer.width).map_err(|_| invalid_input("layer too wide"))?;
+    let height = u16::try_from(layer.height).map_err(|_| invalid_input("layer too tall"))?;
+    let hotspot_x =
+        i16::try_from(layer.hotspot.0).map_err(|_| invalid_input("hotspot out of range"))?;
+    let hotspot_y =
+        i16::try_from(layer.hotspot.1).map_err(|_| invalid_input("hotspot out of range"))?;
+
+    let length = 4 * edges.len() + data.len();
+    let mut out = Vec::with_capacity(16 + length);
+    out.write_u16::<LE>(width)?;
+    out.write_u16::<LE>(height)?;
+    out.write_i16::<LE>(hotspot_x)?;
+    out.write_i16::<LE>(hotspot_y)?;
+    out.write_u32::<LE>(length as u32)?;
+    out.write_u32::<LE>(0)?;
+    for &(left, right) in edges {
+        out.write_u16::<LE>(left)?;
+        out.write_u16::<LE>(right)?;
+    }
+    out.extend(data);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genie_pal::Color;
+    use proptest::prelude::*;
+
+    fn example_frame() -> SMXFrame {
+        let mut main = MainLayer::new(70, 3, (35, 2));
+        for x in 0..70 {
+            main.set(x, 0, MainPixel::Color(x as u16 * 13 % 1024));
+        }
+        main.set(3, 2, MainPixel::PlayerColor(5));
+        main.set(4, 2, MainPixel::PlayerColor(300));
+        main.set(6, 2, MainPixel::Color(1023));
+
+        let mut shadow = ShadowLayer::new(10, 2, (3, 1));
+        shadow.set(2, 0, Some(40));
+        shadow.set(3, 0, Some(0));
+        shadow.set(9, 1, Some(255));
+
+        let mut outline = OutlineLayer::new(4, 4, (1, 1));
+        outline.set(0, 1, true);
+        outline.set(1, 1, true);
+
+        let mut frame = SMXFrame::new(21);
+        frame.set_main(Some(main));
+        frame.set_shadow(Some(shadow));
+        frame.set_outline(Some(outline));
+        frame
+    }
+
+    #[test]
+    fn four_plus_one() {
+        let indices = [1, 0x102, 0x203, 0x304, 5];
+        let data = write_4plus1(&indices);
+        assert_eq!(data, vec![1, 2, 3, 4, 0b11_10_01_00, 5, 0, 0, 0, 0]);
+        let data = Data {
+            bytes: &data,
+            frame: 0,
+        };
+        for (i, index) in indices.iter().enumerate() {
+            assert_eq!(read_4plus1(&data, 0, i).unwrap(), *index);
+        }
+    }
+
+    #[test]
+    fn eight_to_five() {
+        let damage = |first, second| DamageMask { first, second };
+        let pixels = [
+            (0x3FF, damage(0x3F, 0xF)),
+            (0x102, damage(1, 2)),
+            (5, damage(0, 0)),
+        ];
+        let data = write_8to5(&pixels);
+        assert_eq!(data, vec![0xFF, 0xFF, 0x2F, 0x50, 0x20, 5, 0, 0, 0, 0]);
+        let data = Data {
+            bytes: &data,
+            frame: 0,
+        };
+        for (i, pixel) in pixels.iter().enumerate() {
+            assert_eq!(read_8to5(&data, 0, i).unwrap(), *pixel);
+        }
+    }
+
+    #[test]
+    fn encode_layer_commands() {
+        let mut layer = MainLayer::new(7, 2, (0, 0));
+        layer.set(1, 0, MainPixel::Color(1));
+        layer.set(2, 0, MainPixel::Color(2));
+        layer.set(4, 0, MainPixel::PlayerColor(3));
+        let mut indices = vec![];
+        let (edges, rows) = encode_commands(
+            &layer,
+            |p| match p {
+                MainPixel::PlayerColor(_) => PLAYER_COLOR,
+                _ => DRAW,
+            },
+            |pixels, _| indices.extend_from_slice(pixels),
+        );
+        assert_eq!(edges, vec![(1, 2), (0xFFFF, 0xFFFF)]);
+        assert_eq!(rows, vec![vec![0x05, 0x00, 0x02, 0x03], vec![]]);
+        assert_eq!(indices.len(), 3);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut smx = SMX::new();
+        smx.add_frame(example_frame());
+        smx.add_frame(SMXFrame::new(0));
+        let mut bytes = vec![];
+        smx.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[0..4], b"SMPX");
+
+        let decoded = SMX::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, smx);
+
+        assert!(matches!(
+            SMX::from_bytes(&bytes[..bytes.len() - 20]),
+            Err(ReadSMXError::OutOfBounds { frame: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn huge_layer_without_data() {
+        let mut bytes = SMX_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&[2, 0, 1, 0]);
+        bytes.extend_from_slice(&[0; 24]);
+        bytes.extend_from_slice(&[FRAME_MAIN, 0, 0, 0, 0, 0]);
+        // A 0x7FFF×0x7FFF layer whose rows all start drawing at x=0, but without any commands.
+        bytes.extend_from_slice(&[0xFF, 0x7F, 0xFF, 0x7F, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend(vec![0; 4 * 0x7FFF]);
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(matches!(
+            SMX::from_bytes(&bytes),
+            Err(ReadSMXError::OutOfBounds { frame: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn round_trip_damage_masks() {
+        let mut frame = example_frame();
+        let main = frame.main().unwrap();
+        let mut damage = DamageLayer::new(main.width(), main.height(), main.hotspot());
+        for x in 0..70 {
+            damage.set(
+                x,
+                0,
+                DamageMask {
+                    first: x as u8 % 64,
+                    second: x as u8 % 16,
+                },
+            );
+        }
+        frame.set_damage(Some(damage.clone()));
+        let mut smx = SMX::new();
+        smx.add_frame(frame);
+        let mut bytes = vec![];
+        smx.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            bytes[32],
+            FRAME_MAIN | FRAME_SHADOW | FRAME_OUTLINE | FRAME_8TO5
+        );
+        let decoded = SMX::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, smx);
+
+        // Damage mask values of transparent pixels are not stored.
+        let main = smx.frames()[0].main().unwrap();
+        damage.set(
+            0,
+            1,
+            DamageMask {
+                first: 1,
+                second: 1,
+            },
+        );
+        assert_eq!(main.get(0, 1), Some(MainPixel::Transparent));
+        smx.frames_mut()[0].set_damage(Some(damage));
+        let mut bytes = vec![];
+        smx.write_to(&mut bytes).unwrap();
+        let decoded = SMX::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            decoded.frames()[0].damage().unwrap().get(0, 1),
+            Some(DamageMask::default())
+        );
+
+        // The damage layer must match the main layer.
+        smx.frames_mut()[0].set_damage(Some(DamageLayer::new(1, 1, (0, 0))));
+        assert_eq!(
+            smx.write_to(&mut vec![]).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn convert_slp_frames() {
+        let frame = example_frame();
+        let slp = frame.to_slp_frame();
+        // Color 1023 does not fit in an SLP frame.
+        assert!(slp.is_none());
+
+        let mut main = frame.main().unwrap().clone();
+        main.set(4, 2, MainPixel::PlayerColor(4));
+        main.set(6, 2, MainPixel::Color(255));
+        for x in 0..70 {
+            main.set(x, 0, MainPixel::Color(x as u16));
+        }
+        let mut frame = frame.clone();
+        frame.set_main(Some(main));
+        let slp = frame.to_slp_frame().unwrap();
+        let image = slp.image();
+        assert_eq!(slp.hotspot(), (35, 2));
+        assert_eq!(image.get(69, 0), Some(Pixel::Color(69)));
+        assert_eq!(image.get(4, 2), Some(Pixel::PlayerColor(4)));
+        // Layers are aligned by their hotspots: shadow (9, 1) with hotspot (3, 1) ends up at
+        // (41, 2), and outline (1, 1) with hotspot (1, 1) at (35, 2).
+        assert_eq!(image.get(41, 2), Some(Pixel::Shadow));
+        assert_eq!(image.get(35, 2), Some(Pixel::PlayerOutline));
+
+        let back = SMXFrame::from_slp_frame(&slp, 21);
+        assert_eq!(back.palette(), 21);
+        assert_eq!(back.main().unwrap().get(69, 0), Some(MainPixel::Color(69)));
+        assert_eq!(
+            back.shadow().unwrap().get(41, 2),
+            Some(Some(SLP_SHADOW_ALPHA))
+        );
+        assert_eq!(back.outline().unwrap().get(35, 2), Some(true));
+        assert_eq!(back.shadow().unwrap().get(35, 2), Some(None));
+    }
+
+    #[test]
+    fn layer_to_rgba() {
+        let palette: Palette = (0..1024)
+            .map(|i| Color {
+                r: (i >> 8) as u8,
+                g: i as u8,
+                b: 0,
+            })
+            .collect();
+        let player: Palette = (0..256).map(|_| Color::new(0, 0, 255)).collect();
+        let layer = MainLayer::from_pixels(
+            3,
+            1,
+            (0, 0),
+            vec![
+                MainPixel::Transparent,
+                MainPixel::Color(0x203),
+                MainPixel::PlayerColor(7),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            layer.to_rgba(&palette, &player),
+            vec![0, 0, 0, 0, 2, 3, 0, 255, 0, 0, 255, 255]
+        );
+    }
+
+    fn arbitrary_layer<P: Copy + Default + PartialEq + std::fmt::Debug>(
+        pixel: impl Strategy<Value = P> + Clone,
+    ) -> impl Strategy<Value = Layer<P>> {
+        (0..200u32, 0..6u32, any::<(i16, i16)>()).prop_flat_map(move |(width, height, hotspot)| {
+            let hotspot = (i32::from(hotspot.0), i32::from(hotspot.1));
+            prop::collection::vec(pixel.clone(), (width * height) as usize)
+                .prop_map(move |pixels| Layer::from_pixels(width, height, hotspot, pixels).unwrap())
+        })
+    }
+
+    fn arbitrary_main_pixel() -> impl Strategy<Value = MainPixel> + Clone {
+        prop_oneof![
+            4 => Just(MainPixel::Transparent),
+            4 => (0..4u16).prop_map(MainPixel::Color),
+            1 => (0..1024u16).prop_map(MainPixel::Color),
+            2 => (0..8u16).prop_map(MainPixel::PlayerColor),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_layers(
+            main in arbitrary_layer(arbitrary_main_pixel()),
+            shadow in arbitrary_layer(prop::option::weighted(0.3, any::<u8>())),
+            outline in arbitrary_layer(prop::bool::weighted(0.3)),
+        ) {
+            let mut frame = SMXFrame::new(1);
+            frame.set_main(Some(main));
+            frame.set_shadow(Some(shadow));
+            frame.set_outline(Some(outline));
+            let smx = SMX::from_frames(vec![frame]);
+            let mut bytes = vec![];
+            smx.write_to(&mut bytes).unwrap();
+            prop_assert_eq!(SMX::from_bytes(&bytes).unwrap(), smx);
+        }
+    }
+}
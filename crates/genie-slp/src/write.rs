@@ -0,0 +1,408 @@
+use crate::{is_version_4, Pixel, SLPFrame, MAX_FRAME_SIZE, SLP};
+use byteorder::{WriteBytesExt, LE};
+use std::io::{self, Write};
+
+#[derive(Debug)]
+pub enum WriteSLPError {
+    IoError(io::Error),
+    /// Version 4.x files can store at most 65535 frames.
+    TooManyFrames,
+    /// The file is too large for the 32-bit offsets in the SLP format.
+    TooLarge,
+    /// A frame is wider or taller than `MAX_FRAME_SIZE` pixels.
+    FrameTooLarge {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl From<io::Error> for WriteSLPError {
+    fn from(err: io::Error) -> WriteSLPError {
+        WriteSLPError::IoError(err)
+    }
+}
+
+impl std::fmt::Display for WriteSLPError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WriteSLPError::IoError(err) => write!(f, "{}", err),
+            WriteSLPError::TooManyFrames => write!(f, "too many frames for this SLP version"),
+            WriteSLPError::TooLarge => write!(f, "SLP file is too large"),
+            WriteSLPError::FrameTooLarge { width, height } => write!(
+                f,
+                "{}x{} frame is too large, the maximum size is {}x{}",
+                width, height, MAX_FRAME_SIZE, MAX_FRAME_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WriteSLPError {}
+
+/// Runs of identical colors that are at least this long are encoded as fills instead of copies.
+const MIN_FILL: usize = 3;
+
+/// Write a command that stores its pixel count in the high 4 bits, or in the next byte.
+fn encode_count(command: u8, mut count: usize, data: Option<u8>, out: &mut Vec<u8>) {
+    while count > 0 {
+        let n = count.min(0xFF);
+        if n < 0x10 {
+            out.push(((n as u8) << 4) | command);
+        } else {
+            out.push(command);
+            out.push(n as u8);
+        }
+        out.extend(data);
+        count -= n;
+    }
+}
+
+fn encode_skip(mut count: usize, out: &mut Vec<u8>) {
+    while count > 0 {
+        let n = count.min(0xFFF);
+        if n < 0x40 {
+            out.push(((n as u8) << 2) | 0x01);
+        } else {
+            out.push((((n >> 8) as u8) << 4) | 0x03);
+            out.push(n as u8);
+        }
+        count -= n;
+    }
+}
+
+fn encode_copy(indices: &[u8], out: &mut Vec<u8>) {
+    for chunk in indices.chunks(0xFFF) {
+        let n = chunk.len();
+        if n < 0x40 {
+            out.push((n as u8) << 2);
+        } else {
+            out.push((((n >> 8) as u8) << 4) | 0x02);
+            out.push(n as u8);
+        }
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn encode_player_copy(indices: &[u8], out: &mut Vec<u8>) {
+    for chunk in indices.chunks(0xFF) {
+        encode_count(0x06, chunk.len(), None, out);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn encode_outline(command: u8, mut count: usize, out: &mut Vec<u8>) {
+    while count > 0 {
+        let n = count.min(0xFF);
+        if n == 1 {
+            out.push(command);
+        } else {
+            // The span version of an outline command is 0x10 higher.
+            out.push(command + 0x10);
+            out.push(n as u8);
+        }
+        count -= n;
+    }
+}
+
+/// Get the length of the run of identical pixels at the start of a slice.
+fn run_length(pixels: &[Pixel]) -> usize {
+    pixels.iter().take_while(|&&p| p == pixels[0]).count()
+}
+
+/// Collect the palette indices of a sequence of color pixels of the same kind, stopping before
+/// the next run that should be a fill.
+fn collect_copy(pixels: &[Pixel], get: impl Fn(Pixel) -> Option<u8>) -> Vec<u8> {
+    let mut indices = vec![];
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = run_length(&pixels[i..]);
+        match get(pixels[i]) {
+            Some(index) if run < MIN_FILL => {
+                indices.extend(std::iter::repeat_n(index, run));
+                i += run;
+            }
+            _ => break,
+        }
+    }
+    indices
+}
+
+/// Encode a row of pixels, returning the number of transparent pixels on the left and right.
+fn encode_row(row: &[Pixel], out: &mut Vec<u8>) -> (u16, u16) {
+    let left = row.iter().take_while(|&&p| p == Pixel::Transparent).count();
+    if left == row.len() {
+        out.push(0x0F);
+        return (0x8000, 0x8000);
+    }
+    let right = row
+        .iter()
+        .rev()
+        .take_while(|&&p| p == Pixel::Transparent)
+        .count();
+
+    let pixels = &row[left..row.len() - right];
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = run_length(&pixels[i..]);
+        match pixels[i] {
+            Pixel::Transparent => {
+                encode_skip(run, out);
+                i += run;
+            }
+            Pixel::Color(index) if run >= MIN_FILL => {
+                encode_count(0x07, run, Some(index), out);
+                i += run;
+            }
+            Pixel::PlayerColor(index) if run >= MIN_FILL => {
+                encode_count(0x0A, run, Some(index), out);
+                i += run;
+            }
+            Pixel::Color(_) => {
+                let indices = collect_copy(&pixels[i..], |p| match p {
+                    Pixel::Color(index) => Some(index),
+                    _ => None,
+                });
+                encode_copy(&indices, out);
+                i += indices.len();
+            }
+            Pixel::PlayerColor(_) => {
+                let indices = collect_copy(&pixels[i..], |p| match p {
+                    Pixel::PlayerColor(index) => Some(index),
+                    _ => None,
+                });
+                encode_player_copy(&indices, out);
+                i += indices.len();
+            }
+            Pixel::Shadow => {
+                encode_count(0x0B, run, None, out);
+                i += run;
+            }
+            Pixel::PlayerOutline => {
+                encode_outline(0x4E, run, out);
+                i += run;
+            }
+            Pixel::BlackOutline => {
+                encode_outline(0x6E, run, out);
+                i += run;
+            }
+        }
+    }
+    out.push(0x0F);
+    (left as u16, right as u16)
+}
+
+/// Encode the outline table, command table, and commands of a frame, assuming they will be
+/// written at `offset`. Returns the encoded data and the offsets of the command and outline
+/// tables.
+fn encode_frame(frame: &SLPFrame, offset: u32) -> Result<(Vec<u8>, u32, u32), WriteSLPError> {
+    let image = frame.image();
+    if image.width() > MAX_FRAME_SIZE || image.height() > MAX_FRAME_SIZE {
+        return Err(WriteSLPError::FrameTooLarge {
+            width: image.width(),
+            height: image.height(),
+        });
+    }
+    let height = image.height() as usize;
+    let outline_table_offset = offset;
+    let cmd_table_offset = offset + 4 * height as u32;
+    let commands_offset = cmd_table_offset as usize + 4 * height;
+
+    let mut outlines = Vec::with_capacity(4 * height);
+    let mut row_offsets = Vec::with_capacity(4 * height);
+    let mut commands = vec![];
+    for row in image.rows() {
+        let row_offset = commands_offset + commands.len();
+        if row_offset > u32::MAX as usize {
+            return Err(WriteSLPError::TooLarge);
+        }
+        row_offsets.write_u32::<LE>(row_offset as u32)?;
+        let (left, right) = encode_row(row, &mut commands);
+        outlines.write_u16::<LE>(left)?;
+        outlines.write_u16::<LE>(right)?;
+    }
+
+    let mut data = outlines;
+    data.extend(row_offsets);
+    data.extend(commands);
+    Ok((data, cmd_table_offset, outline_table_offset))
+}
+
+impl SLP {
+    /// Encode the SLP file and write it to an output stream.
+    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<(), WriteSLPError> {
+        let version_4 = is_version_4(self.version);
+        let main_offset = 32u32;
+        let secondary_offset = main_offset + 32 * self.frames.len() as u32;
+        let secondary_frames: &[SLPFrame] = if version_4 {
+            &self.secondary_frames
+        } else {
+            &[]
+        };
+        if version_4 && self.frames.len() > usize::from(u16::MAX) {
+            return Err(WriteSLPError::TooManyFrames);
+        }
+        // Secondary frames share the frame count with the main frames.
+        if !secondary_frames.is_empty() && secondary_frames.len() != self.frames.len() {
+            return Err(WriteSLPError::TooManyFrames);
+        }
+
+        let mut infos = vec![];
+        let mut frame_data = vec![];
+        let mut offset = secondary_offset as usize + 32 * secondary_frames.len();
+        for frame in self.frames.iter().chain(secondary_frames) {
+            if offset > u32::MAX as usize {
+                return Err(WriteSLPError::TooLarge);
+            }
+            let (data, cmd_table_offset, outline_table_offset) =
+                encode_frame(frame, offset as u32)?;
+            let image = frame.image();
+            infos.write_u32::<LE>(cmd_table_offset)?;
+            infos.write_u32::<LE>(outline_table_offset)?;
+            infos.write_u32::<LE>(frame.palette_offset())?;
+            infos.write_u32::<LE>(frame.properties())?;
+            infos.write_i32::<LE>(image.width() as i32)?;
+            infos.write_i32::<LE>(image.height() as i32)?;
+            infos.write_i32::<LE>(frame.hotspot().0)?;
+            infos.write_i32::<LE>(frame.hotspot().1)?;
+            offset += data.len();
+            frame_data.push(data);
+        }
+
+        output.write_all(&self.version)?;
+        if version_4 {
+            output.write_u16::<LE>(self.frames.len() as u16)?;
+            output.write_u16::<LE>(self.frame_type)?;
+            output.write_u16::<LE>(self.num_directions)?;
+            output.write_u16::<LE>(self.frames_per_direction)?;
+            output.write_u32::<LE>(self.palette_id)?;
+            output.write_u32::<LE>(main_offset)?;
+            output.write_u32::<LE>(if secondary_frames.is_empty() {
+                0
+            } else {
+                secondary_offset
+            })?;
+            output.write_all(&[0; 8])?;
+        } else {
+            output.write_i32::<LE>(self.frames.len() as i32)?;
+            output.write_all(&self.comment)?;
+        }
+        output.write_all(&infos)?;
+        for data in frame_data {
+            output.write_all(&data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IndexedImage, VERSION_3_0, VERSION_4_0};
+    use proptest::prelude::*;
+
+    fn round_trip(slp: &SLP) -> SLP {
+        let mut bytes = vec![];
+        slp.write_to(&mut bytes).unwrap();
+        SLP::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn encode_commands() {
+        use Pixel::*;
+        let mut out = vec![];
+        let row = [
+            Transparent,
+            Color(1),
+            Color(2),
+            Color(3),
+            Color(3),
+            Color(3),
+            Transparent,
+            PlayerColor(4),
+            Shadow,
+            Shadow,
+            BlackOutline,
+            Transparent,
+        ];
+        assert_eq!(encode_row(&row, &mut out), (1, 1));
+        assert_eq!(
+            out,
+            vec![0x08, 1, 2, 0x37, 3, 0x05, 0x16, 4, 0x2B, 0x6E, 0x0F]
+        );
+
+        let mut out = vec![];
+        let row = vec![Color(9); 300];
+        encode_row(&row, &mut out);
+        assert_eq!(out, vec![0x07, 0xFF, 9, 0x07, 45, 9, 0x0F]);
+
+        let mut out = vec![];
+        encode_row(&[Transparent; 3], &mut out);
+        assert_eq!(out, vec![0x0F]);
+    }
+
+    #[test]
+    fn round_trip_versions() {
+        let mut image = IndexedImage::new(100, 3);
+        for x in 0..100 {
+            image.set(x, 0, Pixel::Color(x as u8));
+            image.set(x, 2, Pixel::PlayerColor(x as u8 % 8));
+        }
+        image.set(50, 1, Pixel::PlayerOutline);
+        let mut frame = SLPFrame::new(image, (-4, 7));
+        frame.set_properties(0x10);
+
+        for &version in &[crate::VERSION_2_0, VERSION_3_0, VERSION_4_0] {
+            let mut slp = SLP::new(version);
+            slp.add_frame(frame.clone());
+            slp.add_frame(SLPFrame::new(IndexedImage::new(0, 0), (0, 0)));
+            let decoded = round_trip(&slp);
+            assert_eq!(decoded, slp);
+        }
+    }
+
+    #[test]
+    fn frame_size_limit() {
+        let mut image = IndexedImage::new(MAX_FRAME_SIZE, 1);
+        image.set(MAX_FRAME_SIZE - 1, 0, Pixel::Color(1));
+        let mut slp = SLP::new(VERSION_3_0);
+        slp.add_frame(SLPFrame::new(image, (0, 0)));
+        assert_eq!(round_trip(&slp), slp);
+
+        for &(width, height) in &[(MAX_FRAME_SIZE + 1, 1), (1, MAX_FRAME_SIZE + 1)] {
+            let mut slp = SLP::new(VERSION_3_0);
+            slp.add_frame(SLPFrame::new(IndexedImage::new(width, height), (0, 0)));
+            assert!(matches!(
+                slp.write_to(&mut vec![]),
+                Err(WriteSLPError::FrameTooLarge { .. })
+            ));
+        }
+    }
+
+    fn arbitrary_pixel() -> impl Strategy<Value = Pixel> {
+        prop_oneof![
+            4 => Just(Pixel::Transparent),
+            4 => (0..4u8).prop_map(Pixel::Color),
+            1 => any::<u8>().prop_map(Pixel::Color),
+            2 => (0..8u8).prop_map(Pixel::PlayerColor),
+            1 => Just(Pixel::Shadow),
+            1 => Just(Pixel::PlayerOutline),
+            1 => Just(Pixel::BlackOutline),
+        ]
+    }
+
+    fn arbitrary_image() -> impl Strategy<Value = IndexedImage> {
+        (0..300u32, 0..8u32).prop_flat_map(|(width, height)| {
+            prop::collection::vec(arbitrary_pixel(), (width * height) as usize)
+                .prop_map(move |pixels| IndexedImage::from_pixels(width, height, pixels).unwrap())
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_images(image in arbitrary_image(), hotspot in any::<(i32, i32)>()) {
+            let mut slp = SLP::default();
+            slp.add_frame(SLPFrame::new(image, hotspot));
+            prop_assert_eq!(round_trip(&slp), slp);
+        }
+    }
+}
@@ -47,7 +47,9 @@ fn main() -> CliResult {
     }
 
     let mut outstream = File::create(output)?;
-    scen.write_to_version(&mut outstream, &version)?;
+    for warning in scen.write_to_version(&mut outstream, &version)? {
+        println!("Warning: {}", warning);
+    }
 
     println!("Conversion complete!");
 
@@ -1,10 +1,9 @@
 use std::{
-    io::{self, stdout, Write},
-    fs::{File, create_dir_all},
+    io::{self, stdout, Read, Write},
+    fs::{File, OpenOptions, create_dir_all},
     path::PathBuf,
-    collections::HashSet,
 };
-use genie_drs::{DRSReader, DRSWriter, ReserveDirectoryStrategy};
+use genie_drs::{DRSEditor, DRSReader, DRSWriter, SaveMode, TempFileStrategy};
 use quicli::prelude::*;
 use structopt::StructOpt;
 
@@ -25,6 +24,9 @@ enum Command {
     #[structopt(name = "extract")]
     /// Extract the entire archive to a directory.
     Extract(Extract),
+    #[structopt(name = "pack")]
+    /// Rebuild an archive from a directory created by `extract`.
+    Pack(Pack),
     #[structopt(name = "add")]
     /// Add a resource to an existing archive.
     Add(Add),
@@ -52,7 +54,8 @@ struct Extract {
     /// Path to the .drs archive.
     #[structopt(parse(from_os_str))]
     archive: PathBuf,
-    /// Only extract resources from this table.
+    /// Only extract resources from this table. No manifest is written in this case, so the
+    /// archive can not be rebuilt with `pack`.
     #[structopt(long, short = "t")]
     table: Option<String>,
     /// Output directory to place the resources in.
@@ -60,6 +63,16 @@ struct Extract {
     out: PathBuf,
 }
 
+#[derive(StructOpt)]
+struct Pack {
+    /// Directory containing the extracted resources and manifest.
+    #[structopt(parse(from_os_str))]
+    dir: PathBuf,
+    /// Path to place the .drs archive.
+    #[structopt(long, short = "o", parse(from_os_str))]
+    out: PathBuf,
+}
+
 #[derive(Debug, StructOpt)]
 struct Add {
     /// Path to the .drs archive.
@@ -114,14 +127,23 @@ fn extract(args: Extract) -> CliResult {
     let mut file = File::open(args.archive)?;
     let drs = DRSReader::new(&mut file)?;
 
+    let filter_ext = match args.table {
+        Some(filter_ext) => filter_ext,
+        None => {
+            if !drs.is_packed() {
+                eprintln!("warning: the archive has gaps or is out of order; `pack` will not produce an identical file");
+            }
+            drs.extract(&mut file, &args.out)?;
+            return Ok(());
+        }
+    };
+
     create_dir_all(&args.out)?;
 
     for table in drs.tables() {
         let table_ext = table.resource_ext();
-        if let Some(ref filter_ext) = args.table {
-            if &table_ext != filter_ext {
-                continue;
-            }
+        if table_ext != filter_ext {
+            continue;
         }
 
         for resource in table.resources() {
@@ -134,54 +156,37 @@ fn extract(args: Extract) -> CliResult {
     Ok(())
 }
 
+fn pack(args: Pack) -> CliResult {
+    let output = File::create(args.out)?;
+    let drs = DRSWriter::from_manifest(args.dir, output, TempFileStrategy::new()?)?;
+    drs.flush()?;
+    Ok(())
+}
+
 fn add(args: Add) -> CliResult {
     assert_eq!(args.file.len(), args.table.len(), "Must set a --table for every file");
     assert_eq!(args.file.len(), args.id.len(), "Must set an --id for every file");
 
-    let mut input = File::open(&args.archive)?;
-    let drs_read = DRSReader::new(&mut input)?;
-
-    let (tables, files) = drs_read.tables().fold((0, 0), |(tables, files), table| (tables + 1, files + table.len() as u32));
-    let new_tables = args.table.iter().fold(HashSet::new(), |mut uniq, table| {
-        uniq.insert(table);
-        uniq
-    }).len() as u32;
-    let new_files = args.id.len() as u32;
-
-    use std::time::SystemTime;
-    let suffix = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-        Ok(d) => format!("{}", d.as_secs()),
-        _ => "temp".to_string(),
-    };
-    let mut temp_out = args.output.as_ref().unwrap_or(&args.archive).clone();
-    temp_out.set_file_name(format!("{}.{}", temp_out.file_name().unwrap().to_str().unwrap(), suffix));
-
-    let output = File::create(&temp_out)?;
-    let mut drs_write = DRSWriter::new(output,
-        ReserveDirectoryStrategy::new(tables + new_tables, files + new_files))?;
-
-    for t in drs_read.tables() {
-        for r in t.resources() {
-            let b = drs_read.get_resource_reader(&mut input, t.resource_type, r.id)?;
-            drs_write.add(t.resource_type, r.id, b)?;
-        }
-    }
+    let input = OpenOptions::new()
+        .read(true)
+        .write(args.output.is_none())
+        .open(&args.archive)?;
+    let mut drs = DRSEditor::new(input)?;
 
     for (i, path) in args.file.iter().enumerate() {
-        let mut res_type = [0x20; 4];
-        let slice = args.table[i].as_bytes();
-        (&mut res_type[0..slice.len()]).copy_from_slice(slice);
-        res_type.reverse();
-        drs_write.add(res_type, args.id[i], File::open(path)?)?;
+        let mut data = vec![];
+        File::open(path)?.read_to_end(&mut data)?;
+        drs.add(args.table[i].as_str(), args.id[i], data)?;
     }
 
-    drs_write.flush()?;
-
-    std::fs::rename(temp_out, if let Some(outfile) = args.output {
-        outfile
-    } else {
-        args.archive
-    })?;
+    match args.output {
+        Some(output) => drs.write_to(&mut File::create(output)?)?,
+        None => {
+            if let SaveMode::Rebuilt { len } = drs.save()? {
+                drs.into_inner().set_len(len)?;
+            }
+        }
+    }
 
     Ok(())
 }
@@ -193,6 +198,7 @@ fn main() -> CliResult {
         Command::List(args) => list(args),
         Command::Get(args) => get(args),
         Command::Extract(args) => extract(args),
+        Command::Pack(args) => pack(args),
         Command::Add(args) => add(args),
     }
 }
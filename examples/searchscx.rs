@@ -0,0 +1,124 @@
+use genie::scx::search::{Query, SearchIndex};
+use genie::Scenario;
+use quicli::prelude::*;
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+/// Build and query a search index over scenario files.
+#[derive(Debug, StructOpt)]
+enum Cli {
+    /// Index all scenario files in a directory.
+    #[structopt(name = "build")]
+    Build {
+        /// Directory containing scenario files.
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        /// Index file to write.
+        #[structopt(parse(from_os_str))]
+        index: PathBuf,
+    },
+    /// Find scenarios in an index.
+    #[structopt(name = "query")]
+    Query {
+        /// Index file to read.
+        #[structopt(parse(from_os_str))]
+        index: PathBuf,
+        /// Object type that must exist, optionally with its owner, like '725' or '725:0' for
+        /// Gaia-owned Jaguar Warriors.
+        #[structopt(long = "object")]
+        objects: Vec<String>,
+        /// Terrain ID that must be used on the map.
+        #[structopt(long = "terrain")]
+        terrains: Vec<i8>,
+        /// Trigger effect type that must be used.
+        #[structopt(long = "effect")]
+        effects: Vec<i32>,
+        /// Words that must appear in messages or triggers.
+        #[structopt(long = "text")]
+        text: Option<String>,
+    },
+}
+
+fn is_scenario(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => {
+            ["scn", "scx", "scx2", "aoescn", "aoe2scenario"].contains(&ext.to_lowercase().as_str())
+        }
+        None => false,
+    }
+}
+
+fn build(dir: PathBuf, output: PathBuf) -> CliResult {
+    let mut index = SearchIndex::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !is_scenario(&path) {
+            continue;
+        }
+        let mut file = File::open(&path)?;
+        match Scenario::from(&mut file) {
+            Ok(scenario) => index.add(path.to_string_lossy(), &scenario),
+            Err(err) => eprintln!("skipping {}: {}", path.display(), err),
+        }
+    }
+    index.write_to(&mut File::create(output)?)?;
+    println!("Indexed {} scenarios", index.len());
+    Ok(())
+}
+
+fn invalid_arg(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_object(arg: &str) -> io::Result<(i16, Option<usize>)> {
+    let mut parts = arg.splitn(2, ':');
+    let object_type = parts
+        .next()
+        .and_then(|part| part.parse().ok())
+        .ok_or_else(|| invalid_arg(format!("invalid object type {}", arg)))?;
+    let owner = match parts.next() {
+        Some(part) => Some(
+            part.parse()
+                .map_err(|_| invalid_arg(format!("invalid owner {}", arg)))?,
+        ),
+        None => None,
+    };
+    Ok((object_type, owner))
+}
+
+fn main() -> CliResult {
+    match Cli::from_args() {
+        Cli::Build { dir, index } => build(dir, index),
+        Cli::Query {
+            index,
+            objects,
+            terrains,
+            effects,
+            text,
+        } => {
+            let index = SearchIndex::from(&mut File::open(index)?)?;
+            let mut query = Query::new();
+            for object in &objects {
+                let (object_type, owner) = parse_object(object)?;
+                query = query.object(object_type, owner);
+            }
+            for terrain in terrains {
+                query = query.terrain(terrain);
+            }
+            for effect in effects {
+                query = query.effect(effect);
+            }
+            if let Some(text) = text {
+                query = query.text(&text);
+            }
+            for entry in index.search(&query) {
+                println!("{}", entry.name());
+            }
+            Ok(())
+        }
+    }
+}
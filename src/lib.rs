@@ -48,12 +48,45 @@
 //!
 //! Hotkey files contain groups of key mappings for different game areas.
 //!
+//! ## Sprite Files
+//!
+//! > Supported version range: SLP 2.0N, 3.0, 4.0X, 4.1X
+//!
+//! SLP files contain the sprites for units, buildings, and interface elements. genie-slp can
+//! decode SLP frames to images that use palette indices, and encode such images back into SLP
+//! files.
+//!
 //! ## Palette Files
 //!
 //! > Supported version range: all versions
 //!
-//! Support for palette files is implemented by [chariot_palette](https://github.com/ChariotEngine/Palette/).
-//! chariot_palette only supports _reading_ palette files at the moment.
+//! Palette files map the color indices used by sprites to RGB colors. genie-pal can read and write
+//! the JASC-PAL palette files that are stored in DRS archives.
+//!
+//! Earlier versions re-exported [chariot_palette](https://github.com/ChariotEngine/Palette/)
+//! instead, which can only read palette files. `genie::read_palette` and `genie::pal::read_from`
+//! still return chariot_palette palettes, but are deprecated; convert their results to the new
+//! `Palette` type using `Palette::from`.
+//!
+//! ```rust
+//! use genie::drs::{DRSArchive, DRSWriter, InMemoryStrategy};
+//! use genie::pal::Color;
+//! use genie::Palette;
+//! use std::io::Cursor;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let palette: Palette = (0..=255).map(|i| Color::new(i, i, i)).collect();
+//!
+//! let mut bytes = vec![];
+//! palette.write_to(&mut bytes)?;
+//! let mut drs = DRSWriter::new(Cursor::new(vec![]), InMemoryStrategy::default())?;
+//! drs.add("bina", 50500, &bytes[..])?;
+//! let archive = DRSArchive::from(drs.flush()?.into_inner())?;
+//!
+//! let mut data = archive.read_resource("bina".into(), 50500)?;
+//! assert_eq!(Palette::from(&mut data)?, palette);
+//! # Ok(())
+//! # }
+//! ```
 
 #![warn(rust_2018_idioms)]
 
@@ -62,12 +95,46 @@ pub use genie_drs as drs;
 pub use genie_hki as hki;
 pub use genie_lang as lang;
 pub use genie_scx as scx;
-pub use chariot_palette as pal;
+pub use genie_slp as slp;
 
 pub use genie_cpx::Campaign;
 pub use genie_drs::{DRSReader, DRSWriter};
 pub use genie_hki::HotkeyInfo;
 pub use genie_lang::LangFile;
+pub use genie_pal::Palette;
 pub use genie_scx::Scenario;
-pub use chariot_palette::Palette;
-pub use chariot_palette::read_from as read_palette;
+pub use genie_slp::SLP;
+
+/// Read a palette file using chariot_palette.
+#[deprecated(note = "use `genie::Palette::from` instead")]
+pub fn read_palette<R: std::io::BufRead + std::io::Seek>(
+    file: &mut R,
+) -> chariot_palette::Result<chariot_palette::Palette> {
+    chariot_palette::read_from(file)
+}
+
+/// Palette files. This re-exports genie-pal, and the deprecated chariot_palette functions that
+/// were available here before genie-pal existed.
+pub mod pal {
+    pub use genie_pal::*;
+
+    /// A color in a chariot_palette palette.
+    #[deprecated(note = "use `genie::pal::Color` instead")]
+    pub type PaletteColor = chariot_palette::PaletteColor;
+
+    /// Read a palette file using chariot_palette.
+    #[deprecated(note = "use `genie::Palette::from` instead")]
+    pub fn read_from<R: std::io::BufRead + std::io::Seek>(
+        file: &mut R,
+    ) -> chariot_palette::Result<chariot_palette::Palette> {
+        chariot_palette::read_from(file)
+    }
+
+    /// Read a palette file from disk using chariot_palette.
+    #[deprecated(note = "use `genie::Palette::from` instead")]
+    pub fn read_from_file<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> chariot_palette::Result<chariot_palette::Palette> {
+        chariot_palette::read_from_file(path)
+    }
+}